@@ -0,0 +1,420 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::write::GzEncoder;
+use flate2::{Compression, Crc};
+use serde::Serialize;
+use crate::exec;
+use crate::mosaic::{self, Artifact};
+use crate::naming;
+use crate::palette::Swatch;
+use crate::poster;
+use crate::preview;
+use crate::quarantine;
+use crate::sandbox::{self, Sandbox};
+use crate::storyboard;
+use crate::thumbnails;
+use crate::{Created, Options};
+
+/// Name of the manifest at the top of every archive.
+const MANIFEST: &str = "manifest.json";
+
+/// How an archive is packed, from its file extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Tar,
+    TarGz,
+    /// Compressed by the `zstd` program.
+    TarZst,
+    /// Stored, not compressed: the sheets are JPEGs already.
+    Zip,
+}
+
+/// Where to write the archive of a run's outputs, and how.
+#[derive(Clone, Debug)]
+pub struct Archive {
+    path: PathBuf,
+    format: Format,
+}
+
+impl Archive {
+    pub fn parse(path: &str) -> Result<Archive, String> {
+        let lower = path.to_lowercase();
+        let format = if lower.ends_with(".tar") {
+            Format::Tar
+        } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Format::TarGz
+        } else if lower.ends_with(".tar.zst") || lower.ends_with(".tzst") {
+            Format::TarZst
+        } else if lower.ends_with(".zip") {
+            Format::Zip
+        } else {
+            return Err("expected a .tar, .tar.gz, .tar.zst or .zip file".to_string());
+        };
+        Ok(Archive { path: PathBuf::from(path), format })
+    }
+}
+
+/// The manifest: what each sheet was made from, and what failed.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    sheets: Vec<ManifestSheet<'a>>,
+    failed: &'a [quarantine::Entry],
+}
+
+#[derive(Serialize)]
+struct ManifestSheet<'a> {
+    video: &'a Path,
+    /// The sheet and any copies of it (posters, scaled sizes, text layers),
+    /// as named in the archive.
+    files: Vec<String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    colors: &'a [Swatch],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blurhash: Option<&'a str>,
+}
+
+/// Bundle the `sheets` written (with their posters, scaled copies and text
+/// layers) and a manifest listing them and the `failures` into `archive`.
+///
+/// Files are named by their path below `base` (the input or output
+/// directory), or by file name when they aren't under it.
+pub fn write(archive: &Archive, sheets: &[Created], failures: &[quarantine::Entry], base: Option<&Path>, opts: &Options) -> Result<()> {
+    let mut names = HashSet::from([MANIFEST.to_string()]);
+    let mut members = Vec::new();
+    let mut manifest = Manifest { sheets: Vec::new(), failed: failures };
+    for sheet in sheets {
+        let mut files = Vec::new();
+        for file in companions(&sheet.output, opts).into_iter().filter(|f| f.is_file()) {
+            let name = unique_name(&mut names, member_name(&file, base));
+            files.push(name.clone());
+            members.push((name, file));
+        }
+        manifest.sheets.push(ManifestSheet {
+            video: &sheet.video,
+            files,
+            colors: &sheet.colors,
+            blurhash: sheet.blurhash.as_deref(),
+        });
+    }
+    let manifest = serde_json::to_string_pretty(&manifest)? + "\n";
+
+    // Written aside and renamed, so a failed run never leaves half an archive.
+    let partial = archive.path.with_extension("partial");
+    pack(archive.format, &partial, manifest.as_bytes(), &members, opts)
+        .and_then(|_| fs::rename(&partial, &archive.path).map_err(Into::into))
+        .with_context(|| format!("Failed to write archive {}", archive.path.display()))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })?;
+    log::info!("Archived {} sheets to {}", sheets.len(), archive.path.display());
+    Ok(())
+}
+
+/// A sheet and the copies of it this run's options write.
+fn companions(sheet: &Path, opts: &Options) -> Vec<PathBuf> {
+    let mut files = vec![sheet.to_path_buf()];
+    if opts.outputs.contains(&Artifact::Poster) {
+        files.push(poster::poster_output_path(sheet));
+    }
+    if opts.outputs.contains(&Artifact::Storyboard) {
+        files.push(storyboard::image_path(sheet));
+        files.push(storyboard::track_path(sheet));
+    }
+    if opts.outputs.contains(&Artifact::Preview) {
+        files.push(preview::path(sheet));
+    }
+    if opts.outputs.contains(&Artifact::Thumbnails) {
+        files.extend(thumbnails::files(sheet));
+    }
+    if opts.text_layer {
+        files.push(mosaic::text_layer_path(sheet));
+    }
+    let mut scaled: Vec<PathBuf> = vec![sheet.to_path_buf()];
+    if opts.square.is_some() {
+        let square = poster::square_output_path(sheet);
+        files.push(square.clone());
+        scaled.push(square);
+    }
+    for image in scaled {
+        files.extend(opts.sizes.iter().map(|&width| naming::sized_output_path(&image, width)));
+    }
+    files
+}
+
+/// `file`'s name in the archive, with `/` separators.
+fn member_name(file: &Path, base: Option<&Path>) -> String {
+    let relative = base.and_then(|base| file.strip_prefix(base).ok())
+        .unwrap_or_else(|| Path::new(file.file_name().unwrap_or_default()));
+    relative.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `name`, numbered `-2`, `-3`, ... if another member already has it.
+fn unique_name(names: &mut HashSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while !names.insert(candidate.clone()) {
+        candidate = naming::with_suffix(Path::new(&name), n).to_string_lossy().into_owned();
+        n += 1;
+    }
+    candidate
+}
+
+fn pack(format: Format, path: &Path, manifest: &[u8], members: &[(String, PathBuf)], opts: &Options) -> Result<()> {
+    let create = || File::create(path).map(BufWriter::new);
+    match format {
+        Format::Tar => write_tar(create()?, manifest, members)?.flush()?,
+        Format::TarGz => write_tar(GzEncoder::new(create()?, Compression::default()), manifest, members)?.finish()?.flush()?,
+        Format::TarZst => pack_zstd(path, manifest, members, opts)?,
+        Format::Zip => write_zip(create()?, manifest, members)?.flush()?,
+    }
+    Ok(())
+}
+
+/// Write a tar beside `path` and have the `zstd` program compress it into
+/// `path`, run like the other tools (killed on Ctrl-C, sandboxed with
+/// `--sandbox`).
+fn pack_zstd(path: &Path, manifest: &[u8], members: &[(String, PathBuf)], opts: &Options) -> Result<()> {
+    let mut tar = path.as_os_str().to_owned();
+    tar.push(".tar");
+    let tar = PathBuf::from(tar);
+    let packed = pack(Format::Tar, &tar, manifest, members, opts).and_then(|_| {
+        let sandbox = opts.sandbox.then(|| sandbox::output_dir(path).map(|dir| Sandbox::new(vec![dir]))).transpose()?;
+        exec::run(sandbox::command("zstd", sandbox.as_ref()).args(["-q", "-f", "-o"]).arg(path).arg("--").arg(&tar))
+    });
+    let _ = fs::remove_file(&tar);
+    packed.map(drop)
+}
+
+/// When `file` was last modified (now, for the manifest).
+fn mtime(file: Option<&Path>) -> SystemTime {
+    file.and_then(|f| fs::metadata(f).and_then(|m| m.modified()).ok()).unwrap_or_else(SystemTime::now)
+}
+
+/// Write a ustar archive of the manifest and `members` to `out`.
+fn write_tar<W: Write>(mut out: W, manifest: &[u8], members: &[(String, PathBuf)]) -> Result<W> {
+    tar_member(&mut out, MANIFEST, manifest, mtime(None))?;
+    for (name, file) in members {
+        let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        tar_member(&mut out, name, &data, mtime(Some(file)))?;
+    }
+    // Two empty blocks end the archive.
+    out.write_all(&[0; 1024])?;
+    Ok(out)
+}
+
+fn tar_member(out: &mut impl Write, name: &str, data: &[u8], modified: SystemTime) -> Result<()> {
+    // Names over 100 bytes are split at a `/` into the 155-byte prefix field.
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name),
+        _ => name.char_indices()
+            .rfind(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| (&name[..i], &name[i + 1..]))
+            .with_context(|| format!("Name too long for a tar archive: {}", name))?,
+    };
+    let secs = modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+    let mut header = [0u8; 512];
+    let mut field = |offset: usize, value: &[u8]| header[offset..offset + value.len()].copy_from_slice(value);
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{:011o}\0", data.len()).as_bytes());
+    field(136, format!("{:011o}\0", secs).as_bytes());
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\x0000");
+    field(345, prefix.as_bytes());
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    out.write_all(&header)?;
+    out.write_all(data)?;
+    out.write_all(&vec![0; data.len().next_multiple_of(512) - data.len()])?;
+    Ok(())
+}
+
+/// Write a zip archive of the manifest and `members` to `out`, stored
+/// uncompressed.
+fn write_zip<W: Write>(mut out: W, manifest: &[u8], members: &[(String, PathBuf)]) -> Result<W> {
+    let mut central = Vec::new();
+    let mut offset: u64 = 0;
+    let mut count: u16 = 0;
+    let mut add = |out: &mut W, name: &str, data: &[u8], modified: SystemTime| -> Result<()> {
+        if u32::try_from(data.len()).is_err() || offset > u32::MAX as u64 {
+            bail!("{} is too large for a zip archive", name);
+        }
+        let mut crc = Crc::new();
+        crc.update(data);
+        let (time, date) = dos_time(modified);
+        // Version 2.0, UTF-8 names, stored.
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0x0800u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        out.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        out.write_all(&common)?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(data)?;
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&common);
+        // Comment length, disk, internal and external attributes.
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&(offset as u32).to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        offset += 30 + name.len() as u64 + data.len() as u64;
+        count = count.checked_add(1).context("Too many files for a zip archive")?;
+        Ok(())
+    };
+
+    add(&mut out, MANIFEST, manifest, mtime(None))?;
+    for (name, file) in members {
+        let data = fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        add(&mut out, name, &data, mtime(Some(file)))?;
+    }
+    if offset > u32::MAX as u64 {
+        bail!("Too much data for a zip archive");
+    }
+
+    out.write_all(&central)?;
+    out.write_all(&0x0605_4b50u32.to_le_bytes())?;
+    out.write_all(&[0; 4])?;
+    out.write_all(&count.to_le_bytes())?;
+    out.write_all(&count.to_le_bytes())?;
+    out.write_all(&(central.len() as u32).to_le_bytes())?;
+    out.write_all(&(offset as u32).to_le_bytes())?;
+    out.write_all(&[0; 2])?;
+    Ok(out)
+}
+
+/// `modified` as MS-DOS (time, date), in local time; zip can't go before 1980.
+fn dos_time(modified: SystemTime) -> (u16, u16) {
+    let t = DateTime::<Local>::from(modified);
+    if t.year() < 1980 {
+        return (0, 0x21);
+    }
+    let time = (t.hour() << 11 | t.minute() << 5 | (t.second() / 2)) as u16;
+    let date = (((t.year() - 1980) as u32) << 9 | t.month() << 5 | t.day()) as u16;
+    (time, date)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Two members on disk: one with a name too long for the tar name field.
+    fn members(dir: &Path) -> Vec<(String, PathBuf)> {
+        let long = format!("{}/{}/a_tn.jpg", "Shows".repeat(12), "Season 1".repeat(8));
+        fs::write(dir.join("a"), vec![7; 1000]).unwrap();
+        fs::write(dir.join("b"), b"").unwrap();
+        vec![(long, dir.join("a")), ("b_tn.jpg".to_string(), dir.join("b"))]
+    }
+
+    fn octal(field: &[u8]) -> usize {
+        let digits = std::str::from_utf8(field).unwrap().trim_matches(|c| c == '\0' || c == ' ');
+        usize::from_str_radix(digits, 8).unwrap()
+    }
+
+    fn read_tar(mut tar: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let text = |field: &[u8]| String::from_utf8(field.iter().copied().take_while(|&b| b != 0).collect()).unwrap();
+        let mut files = Vec::new();
+        while tar[..512].iter().any(|&b| b != 0) {
+            let header = &tar[..512];
+            let mut blank = header.to_vec();
+            blank[148..156].fill(b' ');
+            assert_eq!(octal(&header[148..156]), blank.iter().map(|&b| b as usize).sum::<usize>());
+            assert_eq!(&header[257..265], b"ustar\x0000");
+            let (prefix, name) = (text(&header[345..500]), text(&header[..100]));
+            let name = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let size = octal(&header[124..136]);
+            files.push((name, tar[512..512 + size].to_vec()));
+            tar = &tar[512 + size.next_multiple_of(512)..];
+        }
+        assert_eq!(tar, [0; 1024]);
+        files
+    }
+
+    fn read_zip(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let u16_at = |at: usize| u16::from_le_bytes([zip[at], zip[at + 1]]) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap()) as usize;
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        let (count, mut entry) = (u16_at(end + 10), u32_at(end + 16));
+        assert_eq!(entry + u32_at(end + 12), end);
+
+        let mut files = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(entry), 0x0201_4b50);
+            let (crc, size, name_len, local) = (u32_at(entry + 16), u32_at(entry + 20), u16_at(entry + 28), u32_at(entry + 42));
+            let name = String::from_utf8(zip[entry + 46..entry + 46 + name_len].to_vec()).unwrap();
+            assert_eq!(u32_at(local), 0x0403_4b50);
+            assert_eq!(zip[local + 4..local + 30], zip[entry + 6..entry + 32]);
+            let data = zip[local + 30 + name_len..local + 30 + name_len + size].to_vec();
+            let mut check = Crc::new();
+            check.update(&data);
+            assert_eq!(check.sum() as usize, crc);
+            files.push((name, data));
+            entry += 46 + name_len;
+        }
+        files
+    }
+
+    fn expected(members: &[(String, PathBuf)]) -> Vec<(String, Vec<u8>)> {
+        std::iter::once((MANIFEST.to_string(), b"{}\n".to_vec()))
+            .chain(members.iter().map(|(name, file)| (name.clone(), fs::read(file).unwrap())))
+            .collect()
+    }
+
+    #[test]
+    fn tar_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let members = members(dir.path());
+        let tar = write_tar(Vec::new(), b"{}\n", &members).unwrap();
+        assert_eq!(tar.len() % 512, 0);
+        assert_eq!(read_tar(&tar), expected(&members));
+    }
+
+    #[test]
+    fn tar_rejects_names_that_cant_be_split() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"").unwrap();
+        let name = format!("Shows/{}.jpg", "a".repeat(120));
+        assert!(write_tar(Vec::new(), b"{}\n", &[(name, dir.path().join("a"))]).is_err());
+    }
+
+    #[test]
+    fn zip_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let members = members(dir.path());
+        let zip = write_zip(Vec::new(), b"{}\n", &members).unwrap();
+        assert_eq!(read_zip(&zip), expected(&members));
+    }
+
+    #[test]
+    fn dos_time_packs_local_time() {
+        let t = Local.with_ymd_and_hms(2024, 3, 5, 13, 47, 58).unwrap();
+        assert_eq!(dos_time(t.into()), (13 << 11 | 47 << 5 | 29, 44 << 9 | 3 << 5 | 5));
+        assert_eq!(dos_time(UNIX_EPOCH), (0, 0x21));
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::mosaic::Job;
+use crate::probe::Stream;
+use crate::sandbox;
+use crate::Options;
+
+/// One line per audio track of the job's video: codec, channels, language
+/// and integrated loudness (EBU R128). A track that can't be measured is
+/// listed without its loudness, with a warning.
+pub fn summary(job: &mut Job, opts: &Options) -> Vec<String> {
+    let labels = opts.labels;
+    let tracks: Vec<&Stream> = job.info.streams.iter().filter(|s| s.codec_type == "audio").collect();
+    if tracks.is_empty() {
+        return vec![format!("{}: -", labels.audio)];
+    }
+
+    let mut lines = Vec::new();
+    let mut warnings = Vec::new();
+    for (n, track) in tracks.iter().enumerate() {
+        let channels = match (track.channel_layout.as_str(), track.channels) {
+            ("", Some(count)) => format!("{} ch", count),
+            ("", None) => "? ch".to_string(),
+            (layout, _) => layout.to_string(),
+        };
+        let language = track.tags.get("language").map_or("und", String::as_str);
+        let mut line = format!("{} {}: {}, {}, {}", labels.audio, n + 1, track.codec_name, channels, language);
+        match loudness(job, track.index) {
+            Ok(lufs) => line += &format!(", {} LUFS", labels.number(lufs, 1)),
+            Err(e) => warnings.push(format!("loudness of audio track {} not measured: {:#}", n + 1, e)),
+        }
+        lines.push(line);
+    }
+    job.warnings.extend(warnings);
+    lines
+}
+
+/// Integrated loudness of stream `index` in LUFS, from FFmpeg's ebur128 filter.
+fn loudness(job: &Job, index: usize) -> Result<f64> {
+    // Per-frame measurements go to the verbose log, leaving just the summary.
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-nostats", "-i", &job.video_path])
+        .args(["-map", &format!("0:{}", index)])
+        .args(["-af", "ebur128=framelog=verbose", "-f", "null", "-"]))
+        .with_context(|| "Failed to measure loudness with ffmpeg")?;
+    parse_integrated(&String::from_utf8_lossy(&output.stderr))
+        .ok_or_else(|| anyhow::anyhow!("no loudness summary in ffmpeg's output"))
+}
+
+/// The `I:` value of the last ebur128 summary in `stderr`.
+fn parse_integrated(stderr: &str) -> Option<f64> {
+    let (_, summary) = stderr.rsplit_once("Summary:")?;
+    summary.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("I:"))
+        .and_then(|value| value.trim().trim_end_matches("LUFS").trim().parse().ok())
+}
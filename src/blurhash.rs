@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+use anyhow::Result;
+use crate::mosaic::{Job, Tile};
+use crate::poster;
+
+/// Width the poster frame is shrunk to before it's hashed; a blurhash keeps
+/// only a few cosine components, so more pixels add nothing.
+const SAMPLE_WIDTH: u32 = 32;
+
+/// Cosine components along the frame's long and short sides.
+const LONG_COMPONENTS: usize = 4;
+const SHORT_COMPONENTS: usize = 3;
+
+const BASE83: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// The blurhash (https://blurha.sh) of the video's poster frame (the most
+/// detailed of `tiles`), for web UIs to show while the sheet loads.
+pub fn of_poster(job: &Job, tiles: &[Tile]) -> Result<String> {
+    let pixels = poster::poster_pixels(job, tiles, SAMPLE_WIDTH)?;
+    let width = SAMPLE_WIDTH as usize;
+    let height = pixels.len() / 3 / width;
+    let (x, y) = if width >= height {
+        (LONG_COMPONENTS, SHORT_COMPONENTS)
+    } else {
+        (SHORT_COMPONENTS, LONG_COMPONENTS)
+    };
+    Ok(encode(&pixels, width, height, (x, y)))
+}
+
+/// Blurhash of `width` x `height` RGB `pixels` with `x` by `y` components.
+fn encode(pixels: &[u8], width: usize, height: usize, (x, y): (usize, usize)) -> String {
+    let linear: Vec<f64> = pixels.iter().map(|&c| to_linear(c)).collect();
+    let mut factors = Vec::with_capacity(x * y);
+    for j in 0..y {
+        for i in 0..x {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut sum = [0.0; 3];
+            for py in 0..height {
+                let cos_y = (PI * j as f64 * py as f64 / height as f64).cos();
+                for px in 0..width {
+                    let basis = normalisation * (PI * i as f64 * px as f64 / width as f64).cos() * cos_y;
+                    let p = (py * width + px) * 3;
+                    (0..3).for_each(|c| sum[c] += basis * linear[p + c]);
+                }
+            }
+            factors.push(sum.map(|s| s / (width * height) as f64));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().unwrap();
+    let mut hash = base83((x - 1) + (y - 1) * 9, 1);
+    let max = ac.iter().flatten().fold(0.0_f64, |m, v| m.max(v.abs()));
+    let max = if ac.is_empty() {
+        hash += &base83(0, 1);
+        1.0
+    } else {
+        let quantised = ((max * 166.0 - 0.5).floor()).clamp(0.0, 82.0) as usize;
+        hash += &base83(quantised, 1);
+        (quantised + 1) as f64 / 166.0
+    };
+    let dc_value = dc.iter().fold(0, |value, &c| (value << 8) + to_srgb(c) as usize);
+    hash += &base83(dc_value, 4);
+    for factor in ac {
+        let quantise = |v: f64| {
+            let scaled = (v / max).abs().sqrt().copysign(v / max);
+            (scaled * 9.0 + 9.5).floor().clamp(0.0, 18.0) as usize
+        };
+        hash += &base83(quantise(factor[0]) * 19 * 19 + quantise(factor[1]) * 19 + quantise(factor[2]), 2);
+    }
+    hash
+}
+
+fn to_linear(c: u8) -> f64 {
+    let v = c as f64 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn to_srgb(v: f64) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let srgb = if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (srgb * 255.0 + 0.5) as u8
+}
+
+/// `value` as `length` base-83 digits.
+fn base83(value: usize, length: usize) -> String {
+    (1..=length)
+        .map(|i| BASE83[value / 83_usize.pow((length - i) as u32) % 83] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_reference_encoder() {
+        // A 6x4 gradient, and what woltapp/blurhash's C encoder makes of it.
+        let pixels: Vec<u8> = (0..4u8)
+            .flat_map(|y| (0..6u8).flat_map(move |x| [40 * x, 60 * y, 200 - 30 * x]))
+            .collect();
+        assert_eq!(encode(&pixels, 6, 4, (4, 3)), "LsEL]kB@SS%4z$Ngb0nneEe;fQe;");
+        assert_eq!(encode(&pixels, 6, 4, (3, 4)), "TsEL]kB@SSz$Ngb0eEe;fQ%gOZb0");
+        assert_eq!(encode(&pixels, 6, 4, (1, 1)), "00EL]k");
+    }
+}
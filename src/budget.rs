@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::mosaic::Job;
+use crate::sandbox;
+use crate::Options;
+
+/// Smallest a sheet is scaled to (as a fraction of its width) to fit a budget.
+const MIN_SCALE: f64 = 0.25;
+
+/// An encoder's quality setting, searched from best to worst.
+#[derive(Clone, Copy)]
+struct Quality {
+    option: &'static str,
+    best: u32,
+    worst: u32,
+}
+
+impl Quality {
+    /// The setting for images like `path`, if its format has one worth searching.
+    fn for_image(path: &Path) -> Option<Quality> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "jpg" | "jpeg" => Some(Quality { option: "-q:v", best: 2, worst: 31 }),
+            "webp" => Some(Quality { option: "-quality", best: 100, worst: 0 }),
+            _ => None,
+        }
+    }
+
+    fn steps(self) -> u32 {
+        self.best.abs_diff(self.worst)
+    }
+
+    /// The value `step` steps down from the best.
+    fn at(self, step: u32) -> u32 {
+        if self.best < self.worst { self.best + step } else { self.best - step }
+    }
+}
+
+/// Re-encode `sheet` at the best quality that keeps it within `budget`
+/// bytes, scaling it down as well when even the worst quality doesn't.
+pub fn fit(job: &mut Job, sheet: &Path, budget: u64, opts: &Options) -> Result<()> {
+    let size = fs::metadata(sheet)?.len();
+    if size <= budget {
+        return Ok(());
+    }
+    let Some(quality) = Quality::for_image(sheet) else {
+        job.warnings.push(format!(
+            "{} is {} bytes, over --target-size, which only adjusts JPEG and WebP sheets", sheet.display(), size
+        ));
+        return Ok(());
+    };
+    let extension = sheet.extension().unwrap_or_default().to_string_lossy().into_owned();
+    let temp = job.temp_path().to_path_buf();
+    let candidate = |step: u32| temp.join(format!("fit_{:02}.{}", step, extension));
+
+    let mut scale = 1.0;
+    loop {
+        let worst = quality.steps();
+        let smallest = encode(job, sheet, &candidate(worst), quality, worst, scale, opts)?;
+        if smallest > budget {
+            if scale <= MIN_SCALE {
+                anyhow::bail!("Couldn't get {} under {} bytes, even scaled down", sheet.display(), budget);
+            }
+            scale = (scale * ((budget as f64 / smallest as f64).sqrt() * 0.95).min(0.9)).max(MIN_SCALE);
+            continue;
+        }
+        // The best quality that fits: `fits` always does, `step` below it never.
+        let (mut step, mut fits) = (0, worst);
+        while step < fits {
+            let mid = (step + fits) / 2;
+            if encode(job, sheet, &candidate(mid), quality, mid, scale, opts)? <= budget {
+                fits = mid;
+            } else {
+                step = mid + 1;
+            }
+        }
+        log::debug!(
+            "{}: {} {} at {:.0}% fits --target-size", sheet.display(), quality.option, quality.at(fits), scale * 100.0
+        );
+        if scale < 1.0 {
+            job.warnings.push(format!("sheet scaled to {:.0}% to fit --target-size", scale * 100.0));
+        }
+        fs::copy(candidate(fits), sheet).with_context(|| format!("Failed to write {}", sheet.display()))?;
+        return Ok(());
+    }
+}
+
+/// Encode `sheet` at `step` of `quality`, `scale`d, into `output`; returns its size.
+fn encode(job: &Job, sheet: &Path, output: &Path, quality: Quality, step: u32, scale: f64, opts: &Options) -> Result<u64> {
+    let mut cmd = sandbox::command("ffmpeg", job.sandbox());
+    cmd.arg("-i").arg(sheet);
+    if scale < 1.0 {
+        cmd.args(["-vf", &format!("scale=trunc(iw*{:.4}/2)*2:-2", scale)]);
+    }
+    if quality.option == "-q:v" {
+        cmd.args(["-pix_fmt", opts.chroma.pix_fmt()]);
+    }
+    exec::run(cmd
+        .args([quality.option, &quality.at(step).to_string(), "-update", "1", "-y"])
+        .arg(output))
+        .with_context(|| format!("Failed to re-encode {} for --target-size", sheet.display()))?;
+    Ok(fs::metadata(output)?.len())
+}
@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::font;
+use crate::mosaic::Artifact;
+use crate::sandbox;
+use crate::Options;
+
+/// The filters and encoders this FFmpeg was built with.
+struct Build {
+    filters: Vec<String>,
+    encoders: Vec<String>,
+}
+
+impl Build {
+    /// Ask FFmpeg for its filters and encoders.
+    fn query() -> Result<Build> {
+        Ok(Build { filters: names("-filters")?, encoders: names("-encoders")? })
+    }
+}
+
+/// The names FFmpeg lists for `-filters` or `-encoders`: the second word of
+/// each line (`=` for the legend's).
+fn names(list: &str) -> Result<Vec<String>> {
+    let output = exec::run(sandbox::command("ffmpeg", None).args(["-hide_banner", list]))
+        .with_context(|| format!("Failed to list FFmpeg's {}", list.trim_start_matches('-')))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().filter_map(|line| line.split_whitespace().nth(1).map(str::to_string)).collect())
+}
+
+/// Before any file is processed, turn off or replace what the run asks for
+/// but this system can't do, so files don't fail one after another for
+/// it. Returns what was given up, to report with the run.
+pub fn degrade(opts: &mut Options) -> Vec<String> {
+    let build = match Build::query() {
+        Ok(build) => build,
+        // No FFmpeg at all; the first file stops the run with the reason.
+        Err(e) => {
+            log::debug!("FFmpeg's capabilities not checked: {:#}", e);
+            return Vec::new();
+        }
+    };
+    let has_filter = |name: &str| build.filters.iter().any(|f| f == name);
+    let mut degraded = Vec::new();
+
+    if !opts.no_text {
+        let missing = if !has_filter("drawtext") {
+            Some("FFmpeg has no drawtext filter (it was built without libfreetype)".to_string())
+        } else {
+            font::path(opts).err().map(|e| format!("{:#}", e))
+        };
+        if let Some(reason) = missing {
+            opts.no_text = true;
+            degraded.push(format!("{}: sheets are made without text", reason));
+        }
+    }
+    if opts.outputs.contains(&Artifact::Preview) {
+        if opts.preview_loudnorm && !has_filter("loudnorm") {
+            opts.preview_loudnorm = false;
+            degraded.push("FFmpeg has no loudnorm filter: previews keep their own loudness".to_string());
+        }
+        if !build.encoders.iter().any(|e| e == "libx264") {
+            opts.preview_codec = "mpeg4";
+            degraded.push("FFmpeg has no libx264 encoder: previews are MPEG-4 Part 2, and larger".to_string());
+        }
+    }
+    for degradation in &degraded {
+        log::warn!("{}", degradation);
+    }
+    degraded
+}
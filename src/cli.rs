@@ -0,0 +1,887 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{ArgAction, Parser, Subcommand};
+use crate::archive::Archive;
+use crate::compare::Metric;
+use crate::filter::Glob;
+use crate::i18n::Lang;
+use crate::index::Condition;
+use crate::mosaic::{Artifact, Chroma, Corner, FrameFormat, GridMode, Layout, SeekMode, TextFallback};
+use crate::naming::{SafeNames, Template};
+use crate::pick::Preview;
+use crate::post::ShellCommand;
+use crate::poster::SquareCrop;
+use crate::preview::PreviewAudio;
+use crate::theme::ThemeName;
+
+/// Generate thumbnail mosaics (contact sheets) from video files.
+#[derive(Parser, Debug)]
+#[command(
+    name = "thumbnailer",
+    version,
+    subcommand_negates_reqs = true,
+    after_help = "Exit status: 0 when no file failed, 1 when an error stopped the run, 2 for bad usage, \
+        3 when some files failed, 4 when every file failed, 5 when FFmpeg isn't installed, 130 when interrupted."
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Video file, or directory of videos, to process.
+    #[arg(required_unless_present_any = ["retry_failed", "files_from", "jobs"])]
+    pub input: Option<PathBuf>,
+
+    /// Where to write the sheet of a single video (default: next to it, as
+    /// <name>_tn.jpg); `-` writes it to standard output (the sheet alone, so
+    /// not with other --outputs).
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["retry_failed", "schedule", "gallery", "markdown"])]
+    pub output: Option<PathBuf>,
+
+    /// In directory mode, also process videos in subdirectories.
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// With --recursive, descend at most this many levels of subdirectories
+    /// (0 = only the directory itself).
+    #[arg(long, value_name = "N", requires = "recursive")]
+    pub max_depth: Option<usize>,
+
+    /// In directory mode, only process files matching this glob (repeatable),
+    /// e.g. `*.mkv`, or `Shows/**` for paths below the input directory.
+    #[arg(long, value_name = "GLOB", value_parser = Glob::parse)]
+    pub include: Vec<Glob>,
+
+    /// In directory mode, leave out files and directories matching this glob
+    /// (repeatable), e.g. `*sample*` or `Extras`.
+    #[arg(long, value_name = "GLOB", value_parser = Glob::parse)]
+    pub exclude: Vec<Glob>,
+
+    /// In directory mode, honor ignore files with this name (such as
+    /// .thumbnailerignore or .gitignore): one glob per line, relative to the
+    /// file's directory, with `!` to bring back what an earlier line left out.
+    #[arg(long, value_name = "NAME")]
+    pub ignore_file: Option<String>,
+
+    /// Take files with these extensions as videos instead of the built-in
+    /// list (mp4, mkv, mov, avi, webm, ts, flv, 3gp, ogv, ...), e.g.
+    /// `--extensions mkv,mp4` (default: the config file's `extensions`).
+    #[arg(long, value_name = "EXTS", value_delimiter = ',')]
+    pub extensions: Vec<String>,
+
+    /// Take files with these extensions as videos as well, e.g. the `dav` or
+    /// `h264` of capture tools (besides the config file's `extra_extensions`).
+    #[arg(long, value_name = "EXTS", value_delimiter = ',')]
+    pub extra_extensions: Vec<String>,
+
+    /// In directory mode, also look inside files without a video extension
+    /// (extension-less files, unusual containers) and take those holding
+    /// video, by their magic bytes or, failing that, ffprobe. Slower.
+    #[arg(long)]
+    pub probe_all: bool,
+
+    /// In directory mode, descend into symlinked directories as well (links
+    /// that lead back up the tree are left out). Links to files are taken
+    /// even without it; a file reached by several links or hard links gets
+    /// one sheet.
+    #[arg(long, overrides_with = "no_follow_symlinks")]
+    pub follow_symlinks: bool,
+
+    /// In directory mode, leave out symbolic links altogether, to files as
+    /// well as to directories.
+    #[arg(long, overrides_with = "follow_symlinks")]
+    pub no_follow_symlinks: bool,
+
+    /// Write sheets under this directory instead of next to their videos,
+    /// recreating the input directory's tree below it.
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    pub output_dir: Option<PathBuf>,
+
+    /// With --output-dir, write every sheet directly in it instead of
+    /// recreating the input's tree; sheets whose names clash get -2, -3, ...
+    #[arg(long, requires = "output_dir")]
+    pub flat: bool,
+
+    /// Name sheets so they're safe on any filesystem (for flat directories,
+    /// caches and uploads): `slug` keeps only ASCII letters, digits, `.`, `_`
+    /// and `-` (dropping accents, anything else becoming `-`); `hash` names
+    /// them after a hash of the video's path.
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub safe_names: Option<SafeNames>,
+
+    /// List the files that would be processed, where their sheets would go
+    /// and the FFmpeg commands that would extract their frames, without
+    /// running FFmpeg (ffprobe still runs, as the frame times depend on it).
+    #[arg(long, conflicts_with_all = ["schedule", "index", "gallery", "markdown"])]
+    pub dry_run: bool,
+
+    /// Leave sheets that already exist alone.
+    #[arg(long, conflicts_with_all = ["overwrite", "if_newer"])]
+    pub skip_existing: bool,
+
+    /// Replace sheets that already exist (the default).
+    #[arg(long, conflicts_with = "if_newer")]
+    pub overwrite: bool,
+
+    /// Replace sheets that already exist only if their video was modified
+    /// since.
+    #[arg(long)]
+    pub if_newer: bool,
+
+    /// Name sheets after this template instead of <video>_tn.jpg, e.g.
+    /// "{stem}.{rows}x{cols}.{ext}". Tokens: {name}, {stem}, {source_ext},
+    /// {ext}, {rows}, {cols}, {frames}, {resolution}, {duration} (HH-MM-SS),
+    /// {date} (the video's modification date) and {hash} (of its content).
+    #[arg(long, value_name = "TEMPLATE", value_parser = Template::parse, conflicts_with = "output")]
+    pub name_template: Option<Template>,
+
+    /// Also write copies of each sheet (and square poster) scaled to these
+    /// widths, as <sheet>_<width>.jpg, e.g. for responsive images.
+    #[arg(long, value_name = "WIDTHS", value_delimiter = ',', value_parser = clap::value_parser!(u32).range(16..=16384))]
+    pub sizes: Vec<u32>,
+
+    /// Scale frames to this width (keeping their shape) as they're
+    /// extracted, instead of tiling them at the video's own size. Posters
+    /// are made from the same frames.
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(16..=16384))]
+    pub tile_width: Option<u32>,
+
+    /// Keep sheets at most this wide by extracting narrower frames: a 4K
+    /// video's 3x3 sheet is otherwise 11520 pixels wide.
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(64..=65535))]
+    pub max_width: Option<u32>,
+
+    /// What to make from each video's frames, all from one extraction:
+    /// `sheet`, `poster` (the most detailed frame, at the size it was
+    /// extracted: full size unless --tile-width or --max-width), `storyboard`
+    /// (a sprite and WebVTT track for scrubbing previews), `preview` (an
+    /// MP4 of a two-second clip at each tile) and `thumbnails` (sprites at a
+    /// fixed interval with a DASH AdaptationSet and HLS image playlist for
+    /// them, taken in a pass of its own), e.g.
+    /// `--outputs sheet,poster`. The others are named after the sheet, even
+    /// when it's left out.
+    #[arg(long, value_enum, value_name = "KINDS", value_delimiter = ',', default_value = "sheet")]
+    pub outputs: Vec<Artifact>,
+
+    /// Seconds between the thumbnails of `--outputs thumbnails`.
+    #[arg(long, value_name = "SECONDS", default_value_t = 10.0, value_parser = parse_interval)]
+    pub thumbnail_interval: f64,
+
+    /// Width of those thumbnails, all one size.
+    #[arg(long, value_name = "PX", default_value_t = 320, value_parser = clap::value_parser!(u32).range(16..=1920))]
+    pub thumbnail_width: u32,
+
+    /// Thumbnails across and down each of their numbered sprites
+    /// (tile-1.jpg, tile-2.jpg, ...).
+    #[arg(long, value_name = "COLSxROWS", default_value = "5x5", value_parser = parse_sprite_grid)]
+    pub thumbnail_grid: (u32, u32),
+
+    /// Work out the N dominant colors of each video's poster frame (its most
+    /// detailed tile) and list them, with their share of it, in --json
+    /// records and --archive manifests, for placeholders while sheets load
+    /// (the --gallery page shows the first).
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub colors: Option<u8>,
+
+    /// Work out a blurhash (https://blurha.sh) of each video's poster frame
+    /// and include it in --json records and --archive manifests, for web UIs
+    /// to show a blurred preview while sheets load.
+    #[arg(long)]
+    pub blurhash: bool,
+
+    /// Also write an N x N poster next to each sheet (<sheet>_square.jpg),
+    /// cut from its most detailed tile, for grid UIs that want square art.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(16..=4096))]
+    pub square: Option<u32>,
+
+    /// Run this shell command on each sheet once it's composed, with `{}`
+    /// standing for its path (e.g. `--post-cmd 'jpegoptim --strip-all {}'`);
+    /// repeatable, run in order. A command that fails fails the file.
+    #[arg(long, value_name = "CMD", value_parser = ShellCommand::parse)]
+    pub post_cmd: Vec<ShellCommand>,
+
+    /// How the square poster is cut from the frame.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = SquareCrop::Smart)]
+    pub square_crop: SquareCrop,
+
+    /// How extracted frames are kept until they're tiled: JPEG (yuv420,
+    /// yuv444) or lossless PNG (rgb24, or rgb48 for 10-bit sources). The PNG
+    /// formats avoid banding in dark scenes, at the cost of scratch space
+    /// (default: the config file's, or yuv420).
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub frame_format: Option<FrameFormat>,
+
+    /// How FFmpeg seeks to each frame: fast, from the keyframe before it, or
+    /// accurate, decoding from the start, for files whose timestamps can't be
+    /// trusted (default: the config file's, or fast).
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub seek: Option<SeekMode>,
+
+    /// Decode with this FFmpeg -hwaccel method, e.g. auto, vaapi or cuda
+    /// (default: the config file's, or software decoding).
+    #[arg(long, value_name = "METHOD")]
+    pub hwaccel: Option<String>,
+
+    /// Chroma subsampling of the sheet's JPEG (default: the config file's, or 420).
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub chroma: Option<Chroma>,
+
+    /// Keep each sheet within this size, e.g. 800K or 2M (K = 1024 bytes),
+    /// lowering its JPEG or WebP quality as little as needed (and scaling
+    /// it down if even the lowest quality is too big), for upload limits.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub target_size: Option<u64>,
+
+    /// Audio track of `--outputs preview` videos: `auto` (the default track,
+    /// passing over commentary and audio description), `none`, a track
+    /// number counted from 1, or a language code such as `eng`.
+    #[arg(long, value_name = "TRACK", default_value = "auto", value_parser = PreviewAudio::parse)]
+    pub preview_audio: PreviewAudio,
+
+    /// Normalize the loudness of preview videos' audio (to -16 LUFS).
+    #[arg(long)]
+    pub preview_loudnorm: bool,
+
+    /// Rows of tiles on a sheet (default: the config file's, or 3).
+    #[arg(long, value_name = "N")]
+    pub rows: Option<usize>,
+
+    /// Columns of tiles on a sheet (default: the config file's, or 3).
+    #[arg(long, value_name = "N")]
+    pub cols: Option<usize>,
+
+    /// Tiles on a sheet (default: rows x cols); fewer leaves the last cells
+    /// empty.
+    #[arg(long, value_name = "N")]
+    pub frames: Option<usize>,
+
+    /// `auto` sizes each video's grid by its duration instead of --rows and
+    /// --cols: one more row and column each time the duration triples, from
+    /// 2x2 at two minutes to 6x6 at three hours (default: the config file's
+    /// `grid`, or `fixed`).
+    #[arg(long, value_enum, value_name = "MODE", conflicts_with_all = ["rows", "cols", "frames", "at_frames", "edl"])]
+    pub grid: Option<GridMode>,
+
+    /// `filmstrip` lays the tiles out in a single row of small frames
+    /// (`vertical-filmstrip`, a column) instead of a grid, for previews in
+    /// file listings and web galleries; filmstrips have no title overlay.
+    /// `hero` puts the most detailed frame, as wide as the grid, above it.
+    #[arg(long, value_enum, value_name = "LAYOUT", default_value = "grid")]
+    pub layout: Layout,
+
+    /// Height in pixels of a filmstrip's frames (their width, for a
+    /// vertical one).
+    #[arg(long, value_name = "PX", default_value_t = 180, value_parser = clap::value_parser!(u32).range(16..=2160))]
+    pub strip_size: u32,
+
+    /// Fewest rows and columns --grid auto gives a sheet.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub min_grid: usize,
+
+    /// Most rows and columns --grid auto gives a sheet.
+    #[arg(long, value_name = "N", default_value_t = 6)]
+    pub max_grid: usize,
+
+    /// Show these frames (numbered from 0, converted to times with the
+    /// video's frame rate) instead of evenly spaced ones, e.g. to match a
+    /// shot list. Black frames are kept.
+    #[arg(long, value_name = "FRAMES", value_delimiter = ',', num_args = 1.., conflicts_with_all = ["frames", "start_offset", "skip"])]
+    pub at_frames: Option<Vec<u64>>,
+
+    /// Show the cut points of an edit decision list, each tile captioned
+    /// with its cut, to review a cut list against the source footage: a
+    /// CMX 3600 `.edl` (its video events' source in points and clip names)
+    /// or a CSV of `in,out,label` lines. Times are source timecodes
+    /// (`01:00:10:12`, counted from the video's start timecode), `HH:MM:SS.sss`
+    /// or seconds.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["at_frames", "frames", "start_offset", "skip"])]
+    pub edl: Option<PathBuf>,
+
+    /// Write files that failed (with the reason and FFmpeg's last stderr
+    /// lines) to this JSON list, for a later --retry-failed.
+    #[arg(long, value_name = "FILE")]
+    pub quarantine: Option<PathBuf>,
+
+    /// Also write a static HTML gallery of the sheets (searchable and sortable
+    /// by metadata) to this directory.
+    #[arg(long, value_name = "DIR")]
+    pub gallery: Option<PathBuf>,
+
+    /// Link the gallery's videos under this URL instead of to the local
+    /// files, keeping their paths below the input directory.
+    #[arg(long, value_name = "URL", requires = "gallery")]
+    pub gallery_base_url: Option<String>,
+
+    /// Also write a Markdown report (sheet, metadata and tile timestamps per
+    /// video) to this file.
+    #[arg(long, value_name = "FILE")]
+    pub markdown: Option<PathBuf>,
+
+    /// Keep running and sweep the input directory on this cron schedule, in
+    /// local time (e.g. "0 3 * * *"). A sweep still running when the next is
+    /// due makes that one skip.
+    #[arg(long, value_name = "CRON", conflicts_with = "retry_failed")]
+    pub schedule: Option<String>,
+
+    /// At the end of the run, also bundle the sheets written (with their
+    /// posters and scaled copies) and a manifest.json listing them and the
+    /// failures into this archive: .tar, .tar.gz, .tar.zst (needs zstd) or
+    /// .zip.
+    #[arg(long, value_name = "FILE", value_parser = Archive::parse, conflicts_with = "dry_run")]
+    pub archive: Option<Archive>,
+
+    /// Record every file processed (its sheet, status and the settings used)
+    /// in this JSON index, for `regen`.
+    #[arg(long, value_name = "FILE")]
+    pub index: Option<PathBuf>,
+
+    /// Don't add this run to the usage stats (`thumbnailer stats`), which
+    /// are otherwise kept in stats.json in the config directory.
+    #[arg(long)]
+    pub no_stats: bool,
+
+    /// When a file fails, keep what its job got to (extracted frames,
+    /// intermediate mosaics) and the error with FFmpeg's last words in
+    /// DIR/<file name>/, for bug reports.
+    #[arg(long, value_name = "DIR")]
+    pub debug_dir: Option<PathBuf>,
+
+    /// Extract frames and compose sheets in a scratch directory made in DIR
+    /// (e.g. a tmpfs, or a drive with room) instead of the system's.
+    #[arg(long, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Leave each file's scratch directory (extracted frames, tiles and
+    /// intermediate mosaics) behind instead of removing it, and log where.
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// Keep each file's extracted tiles in DIR/<file name>/, named by
+    /// position and timestamp, and reuse them instead of extracting again
+    /// when the file and the frame times haven't changed.
+    #[arg(long, value_name = "DIR")]
+    pub keep_frames: Option<PathBuf>,
+
+    /// Process only the files in a quarantine list from a previous run.
+    #[arg(long, value_name = "FILE", conflicts_with = "input")]
+    pub retry_failed: Option<PathBuf>,
+
+    /// Process the files listed in this file, one per line; `-` reads the
+    /// list from standard input (e.g. from `find`).
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "retry_failed", "schedule", "output"])]
+    pub files_from: Option<PathBuf>,
+
+    /// Make the sheets described in this JSON file (`-` for standard input):
+    /// an array of {"source", "output", "title", "options"} objects, where
+    /// only "source" is required and "options" takes the settings of a
+    /// config file (rows, cols, frames, timecode, theme, ...).
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["input", "retry_failed", "files_from", "schedule", "output"])]
+    pub jobs: Option<PathBuf>,
+
+    /// Write a line of JSON to standard output for each file, once it's
+    /// done: its source, status, output, duration, resolution, tiles (the
+    /// times asked for and shown), seconds taken, warnings and any error.
+    /// Progress goes to standard error instead.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub json: bool,
+
+    /// With --files-from, the list is separated by NUL characters instead of
+    /// newlines, as `find -print0` writes it.
+    #[arg(short = '0', long)]
+    pub null: bool,
+
+    /// Say more: -v adds the FFmpeg commands run, their stderr and how long
+    /// each file took; -vv adds everything else.
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only print warnings and errors.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Don't draw the status line (files done, time left, current stage)
+    /// shown when standard error is a terminal.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Also append everything logged, down to -v's details and with
+    /// timestamps, to this file.
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Run ffmpeg/ffprobe in a sandbox with no network access and a read-only
+    /// filesystem apart from the scratch and output directories (Linux, needs bubblewrap).
+    #[arg(long)]
+    pub sandbox: bool,
+
+    /// Don't re-shape the grid for portrait (e.g. 9:16 phone) videos.
+    #[arg(long)]
+    pub keep_grid: bool,
+
+    /// Start sampling this many seconds in (default: 2% of the duration), so
+    /// the first tile isn't always the opening black frame.
+    #[arg(long, value_name = "SECS")]
+    pub start_offset: Option<f64>,
+
+    /// Never sample from this time range, in seconds (e.g. 0-90 for an
+    /// intro; repeatable).
+    #[arg(long, value_name = "START-END", value_parser = parse_time_range)]
+    pub skip: Vec<(f64, f64)>,
+
+    /// Treat files modified less than this many seconds ago as possibly
+    /// still being copied: if they grow while waiting that long, they're put
+    /// off until after the others, then left for a later run (0 = off).
+    #[arg(long, value_name = "SECS", default_value_t = 2.0, value_parser = parse_seconds)]
+    pub settle: f64,
+
+    /// Sheet files still being written (DVR recordings) as far as they've
+    /// got instead of putting them off, and make their sheets again on later
+    /// runs (with --schedule, say) while they grow, until they're finished.
+    #[arg(long)]
+    pub recordings: bool,
+
+    /// Give up on a tile after this many seconds (0 = no limit) and use a
+    /// neighboring frame instead, so corrupt files can't hang the run.
+    #[arg(long, value_name = "SECS", default_value_t = 60.0, value_parser = parse_seconds)]
+    pub frame_timeout: f64,
+
+    /// Give up on a file after this many seconds in all (0 = no limit),
+    /// killing whatever FFmpeg or ffprobe is running for it, and count it
+    /// as failed; for damaged files that make FFmpeg hang in a seek.
+    #[arg(long, value_name = "SECS", default_value_t = 0.0, value_parser = parse_seconds)]
+    pub timeout: f64,
+
+    /// Retry the tiling, tile label and overlay passes this many times when
+    /// FFmpeg fails.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub pass_retries: u32,
+
+    /// When drawing text (overlay, tile labels) still fails after the retries:
+    /// write the sheet without it, or fail the file.
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = TextFallback::Plain)]
+    pub text_fallback: TextFallback,
+
+    /// Retry stat/read/probe operations this many times on transient I/O
+    /// errors (EIO/ESTALE from NFS or SMB mounts).
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub io_retries: u32,
+
+    /// Delay before the first I/O retry, doubling on each further attempt.
+    #[arg(long, value_name = "MS", default_value_t = 250)]
+    pub io_retry_delay: u64,
+
+    /// Keep FFmpeg and ffprobe from reading more than this many megabytes a
+    /// second between them (Linux), so a run over a network share leaves room
+    /// for its other users. Counts everything they read, scratch files too.
+    #[arg(long, value_name = "MB", conflicts_with = "sandbox", value_parser = parse_rate)]
+    pub bandwidth_limit: Option<f64>,
+
+    /// Run at most this many FFmpeg/ffprobe processes at once (for `serve`'s
+    /// workers, which otherwise open the share's files side by side).
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_connections: Option<u32>,
+
+    /// Read settings (such as `rows`, `font_file` or `[theme]` overrides)
+    /// from this TOML file, on top of those in the user's config.toml (in
+    /// $XDG_CONFIG_HOME/thumbnailer, by default ~/.config/thumbnailer; on
+    /// macOS ~/Library/Application Support/thumbnailer, on Windows
+    /// %APPDATA%\thumbnailer). Flags given on the command line win over both;
+    /// a .thumbnailer.toml in a scanned directory overrides them all for that
+    /// directory and everything below it.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Read the user's config.toml from this directory instead.
+    #[arg(long, value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+
+    /// Style of the sheets: background, tile borders and the details banner
+    /// (default: the config file's base theme, or classic).
+    #[arg(long, value_enum)]
+    pub theme: Option<ThemeName>,
+
+    /// Pixels between tiles (default: the theme's spacing).
+    #[arg(long, value_name = "PX")]
+    pub gutter: Option<u32>,
+
+    /// Pixels between the tiles and the edge of the sheet (default: the
+    /// gutter, or the theme's spacing).
+    #[arg(long, value_name = "PX")]
+    pub margin: Option<u32>,
+
+    /// Color of the gaps and margin, anything FFmpeg accepts: `white`,
+    /// `#1e1e1e`, `0x202020` (default: the theme's background).
+    #[arg(long, value_name = "COLOR")]
+    pub background_color: Option<String>,
+
+    /// Outline every tile, `WIDTH:COLOR` (`2:white`, `4:#3c3c3c`) or just a
+    /// width in the theme's border color; 0 draws none.
+    #[arg(long, value_name = "WIDTH:COLOR", value_parser = parse_border)]
+    pub tile_border: Option<(u32, Option<String>)>,
+
+    /// Put the file's details in a panel above the grid, with its duration
+    /// and codecs, instead of over the first tiles.
+    #[arg(long)]
+    pub header: bool,
+
+    /// Height of the panel above the grid in pixels, the text shrunk to fit
+    /// (default: as tall as the text).
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(8..))]
+    pub header_height: Option<u32>,
+
+    /// Color of the panel above the grid (default: the theme's banner color).
+    #[arg(long, value_name = "COLOR")]
+    pub header_color: Option<String>,
+
+    /// Give every tile a drop shadow, to lift it off the background.
+    #[arg(long)]
+    pub tile_shadow: bool,
+
+    /// Label every tile with its SMPTE timecode, counted from the video's
+    /// start timecode (drop-frame for 29.97/59.94 material tagged that way).
+    #[arg(long)]
+    pub timecode: bool,
+
+    /// Burn the time each tile was taken from (`HH:MM:SS`) into its corner,
+    /// to seek a player straight to it.
+    #[arg(long)]
+    pub tile_timestamps: bool,
+
+    /// Burn each tile's number and how far into the video it is (`5/9 — 55%`)
+    /// into its corner, after the time with --tile-timestamps.
+    #[arg(long)]
+    pub tile_index: bool,
+
+    /// Corner of the frame the time and number go in (the top one of that
+    /// side when a --timecode takes the bottom).
+    #[arg(long, value_enum, value_name = "CORNER", default_value_t = Corner::BottomRight)]
+    pub tile_label_corner: Corner,
+
+    /// Height of the time and number as a percentage of the frame's.
+    #[arg(long, value_name = "PERCENT", default_value_t = 5.5, value_parser = parse_label_size)]
+    pub tile_label_size: f64,
+
+    /// Opacity of the time and number, and of the box behind them, from 0
+    /// (invisible) to 1.
+    #[arg(long, value_name = "0-1", default_value_t = 1.0, value_parser = parse_opacity)]
+    pub tile_label_opacity: f64,
+
+    /// Write the title of the chapter each tile falls in under the tile, for
+    /// videos with chapters (concerts, lectures).
+    #[arg(long)]
+    pub chapter_titles: bool,
+
+    /// Draw a timeline bar under the grid, marking where each tile was
+    /// sampled and where chapters begin.
+    #[arg(long)]
+    pub timeline: bool,
+
+    /// Add a footer listing each audio track's codec, channels, language and
+    /// integrated loudness (EBU R128). Measuring decodes all of the audio.
+    #[arg(long)]
+    pub audio_summary: bool,
+
+    /// Draw no text at all (overlay, tile labels, captions, footer), so no
+    /// font is needed.
+    #[arg(long, conflicts_with = "text_layer")]
+    pub no_text: bool,
+
+    /// Leave the text off the sheet and write it to `<sheet>_text.png`
+    /// instead: a transparent layer of the same size, to restyle, localize
+    /// or composite downstream.
+    #[arg(long)]
+    pub text_layer: bool,
+
+    /// Font (TrueType/OpenType file) for all text drawn on sheets; by default
+    /// one is picked from the system's font directories.
+    #[arg(long, value_name = "FILE")]
+    pub font_file: Option<PathBuf>,
+
+    /// Language of the overlay's labels.
+    #[arg(long, value_enum, default_value_t = Lang::En)]
+    pub lang: Lang,
+
+    /// Shorten filenames longer than this many characters in the overlay.
+    #[arg(long, value_name = "CHARS", default_value_t = 60)]
+    pub max_name_length: usize,
+
+    /// In directory mode, skip samples, trailers and other extras (by filename
+    /// word, or by living under an Extras/Featurettes/... folder).
+    #[arg(long)]
+    pub skip_extras: bool,
+
+    /// Filename word that marks a file as an extra (repeatable; replaces the built-in list).
+    #[arg(long = "extras-word", value_name = "WORD")]
+    pub extras_words: Vec<String>,
+
+    /// Folder name whose contents are extras (repeatable; replaces the built-in list).
+    #[arg(long = "extras-dir", value_name = "NAME")]
+    pub extras_dirs: Vec<String>,
+}
+
+/// Parse a `START-END` range of seconds.
+fn parse_time_range(value: &str) -> Result<(f64, f64), String> {
+    let (start, end) = value.split_once('-').ok_or("expected START-END")?;
+    let start: f64 = start.trim().parse().map_err(|_| format!("invalid start {:?}", start))?;
+    let end: f64 = end.trim().parse().map_err(|_| format!("invalid end {:?}", end))?;
+    if !(start >= 0.0 && end > start) {
+        return Err("the end must come after the start".to_string());
+    }
+    Ok((start, end))
+}
+
+/// Parse a non-negative number of seconds, short enough to be a `Duration`.
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(secs) if Duration::try_from_secs_f64(secs).is_ok() => Ok(secs),
+        Ok(secs) if secs > 0.0 => Err("too many seconds".to_string()),
+        _ => Err("expected a number of seconds, 0 or more".to_string()),
+    }
+}
+
+/// Parse a `WIDTH:COLOR` or bare `WIDTH` border.
+fn parse_border(value: &str) -> Result<(u32, Option<String>), String> {
+    let (width, color) = match value.split_once(':') {
+        Some((width, color)) => (width, Some(color.trim())),
+        None => (value, None),
+    };
+    let width = width.trim().parse().map_err(|_| format!("expected WIDTH:COLOR like 2:white, not {:?}", value))?;
+    match color {
+        Some("") => Err("the color after the ':' is missing".to_string()),
+        color => Ok((width, color.map(str::to_string))),
+    }
+}
+
+/// Parse a number of seconds of at least a tenth of one.
+fn parse_interval(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.1 => Ok(secs),
+        _ => Err("expected a number of seconds, 0.1 or more".to_string()),
+    }
+}
+
+/// Parse a `COLSxROWS` sprite grid like `5x5`.
+fn parse_sprite_grid(value: &str) -> Result<(u32, u32), String> {
+    let grid = value.trim().to_ascii_lowercase();
+    let (cols, rows) = grid.split_once('x').ok_or_else(|| format!("expected COLSxROWS like 5x5, not {:?}", value))?;
+    match (cols.trim().parse::<u32>(), rows.trim().parse::<u32>()) {
+        (Ok(cols), Ok(rows)) if (1..=32).contains(&cols) && (1..=32).contains(&rows) => Ok((cols, rows)),
+        _ => Err(format!("expected COLSxROWS of 1 to 32 each, not {:?}", value)),
+    }
+}
+
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let upper = value.to_ascii_uppercase();
+    let number = upper.trim_end_matches('B');
+    let (number, unit) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 1024.0),
+        Some((i, 'M')) => (&number[..i], 1024.0 * 1024.0),
+        Some((i, 'G')) => (&number[..i], 1024.0 * 1024.0 * 1024.0),
+        _ => (number, 1.0),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() && n * unit >= 1.0 => Ok((n * unit) as u64),
+        _ => Err(format!("expected a size like 800K or 2M, not {:?}", value)),
+    }
+}
+
+fn parse_label_size(value: &str) -> Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (1.0..=50.0).contains(&percent) => Ok(percent),
+        _ => Err("expected a percentage of the frame's height from 1 to 50".to_string()),
+    }
+}
+
+fn parse_opacity(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err("expected an opacity from 0 to 1".to_string()),
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(mb) if mb.is_finite() && mb > 0.0 => Ok(mb),
+        _ => Err("expected a number of megabytes a second, more than 0".to_string()),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Choose every tile of one video's sheet by hand, from candidate frames
+    /// shown in the terminal.
+    ///
+    /// General options such as --sandbox go before `pick`.
+    Pick {
+        /// Video file to build the sheet for.
+        video: PathBuf,
+
+        /// Candidate frames offered per tile, and added each time more are asked for.
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        candidates: usize,
+
+        /// How candidates are drawn (auto uses kitty or sixel graphics when
+        /// the terminal supports them, ASCII otherwise).
+        #[arg(long, value_enum, default_value_t = Preview::Auto)]
+        preview: Preview,
+    },
+
+    /// Compare two videos (say a source and its encode) on one sheet, with
+    /// rows of A's tiles alternating with B's tiles from the same timestamps.
+    ///
+    /// General options such as --sandbox go before `compare`.
+    Compare {
+        /// First video; the sheet is written next to it.
+        a: PathBuf,
+        /// Second video, sampled at the frame times picked for the first.
+        b: PathBuf,
+
+        /// Score B against A as the reference at every tile, drawing the
+        /// score on B's tile and logging it (repeatable).
+        #[arg(long = "metric", value_enum, value_name = "METRIC")]
+        metrics: Vec<Metric>,
+    },
+
+    /// Put several videos on one sheet, a row of --cols tiles each labeled
+    /// with its file, to compare the episodes of a season or the camera
+    /// angles of one event.
+    ///
+    /// General options such as --cols go before `combine`.
+    Combine {
+        /// Videos to combine, one row each, in order.
+        #[arg(required = true, num_args = 2..)]
+        videos: Vec<PathBuf>,
+
+        /// Where to write the sheet (default: <first file name>_combined.jpg
+        /// next to the first video).
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+
+    /// Extract the frames a sheet would show, as separate JPEGs, without
+    /// making the sheet (for doing your own composition).
+    ///
+    /// General options such as --start-offset go before `frames`.
+    Frames {
+        /// Video file to take the frames from.
+        video: PathBuf,
+
+        /// Directory to write the frames to (default: <name>_frames next to
+        /// the video).
+        #[arg(long, value_name = "DIR")]
+        out: Option<PathBuf>,
+
+        /// Number of frames (default: --frames).
+        #[arg(long, value_name = "N")]
+        count: Option<usize>,
+    },
+
+    /// Probe every video in a directory and write an HTML report of the
+    /// collection (codecs, resolutions, durations, sizes) showing each sheet.
+    Report {
+        /// Directory of videos to report on.
+        dir: PathBuf,
+
+        /// Where to write the report (default: report.html in the directory).
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Find likely duplicate videos in a directory (same content in different
+    /// encodes, names or containers) by perceptual hashes of their sheets'
+    /// frames, as recorded in --index by the runs that made them; videos with
+    /// none recorded have frames sampled instead.
+    ///
+    /// General options such as --index go before `dedupe`.
+    Dedupe {
+        /// Directory of videos to check.
+        dir: PathBuf,
+    },
+
+    /// Serve frames of the videos in a directory over HTTP, for web players'
+    /// hover-seek previews: `GET /frame?src=PATH&t=SECS&w=WIDTH` returns the
+    /// frame of DIR/PATH at that time as a JPEG. Adding `&priority=background`
+    /// queues a request for the background workers instead, so bulk fetches
+    /// don't hold up players.
+    ///
+    /// General options such as --sandbox go before `serve`.
+    Serve {
+        /// Directory of videos to serve (nothing outside it is reachable).
+        dir: PathBuf,
+
+        /// Address to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Memory for caching served frames, in megabytes.
+        #[arg(long, value_name = "MB", default_value_t = 64)]
+        cache_mb: usize,
+
+        /// Requests answered at once, each may be running an FFmpeg
+        /// (default: the config file's, or 4).
+        #[arg(long, value_name = "N")]
+        workers: Option<usize>,
+
+        /// Requests with priority=background answered at once, on top of
+        /// --workers (default: the config file's, or 1).
+        #[arg(long, value_name = "N")]
+        background_workers: Option<usize>,
+    },
+
+    /// Process again the files in the --index that meet every condition,
+    /// e.g. `--where 'options != current'` for sheets made with other
+    /// settings, or `--where 'status = failed'`.
+    ///
+    /// Fields are options, version and status (created or failed); `current`
+    /// stands for this run's settings or version. General options go before
+    /// `regen`, and are the settings the files are processed with.
+    Regen {
+        /// FIELD = VALUE or FIELD != VALUE (repeatable; all must hold).
+        #[arg(long = "where", value_name = "CONDITION", required = true, value_parser = Condition::parse)]
+        conditions: Vec<Condition>,
+    },
+
+    /// Check the sheets of the videos in a directory: that each is there and
+    /// decodes, that its video hasn't changed since, and (with --index) that
+    /// it was made with this run's settings. Prints the ones to make again
+    /// and exits with 1 if there are any.
+    ///
+    /// General options go before `verify`, and are the settings checked against.
+    Verify {
+        /// Directory of videos whose sheets are checked.
+        dir: PathBuf,
+
+        /// Also write the videos whose sheets need making again to FILE, one
+        /// per line, for `--files-from`.
+        #[arg(long, value_name = "FILE")]
+        list: Option<PathBuf>,
+    },
+
+    /// Print the usage stats of this machine's batch runs: files created,
+    /// kept, skipped and failed (by kind), and the time spent and saved.
+    /// They're kept in stats.json in the config directory and never leave it.
+    Stats {
+        /// Start counting over.
+        #[arg(long)]
+        reset: bool,
+    },
+
+    /// Print a completion script for a shell's flags and subcommands.
+    ///
+    /// For example `thumbnailer completions bash > /etc/bash_completion.d/thumbnailer`,
+    /// or `thumbnailer completions fish > ~/.config/fish/completions/thumbnailer.fish`.
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Preview one video's sheet in a window, adjusting the grid, spacing and
+    /// overlay until it looks right, then save it.
+    ///
+    /// General options such as --sandbox go before `gui`.
+    #[cfg(feature = "gui")]
+    Gui {
+        /// Video file to build the sheet for.
+        video: PathBuf,
+    },
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::compare;
+use crate::exec;
+use crate::mosaic::{self, Job, TileText};
+use crate::naming;
+use crate::text;
+use crate::{Options, Outcome};
+
+/// Longest file name (in characters) captioning a row.
+const MAX_ROW_LABEL: usize = 40;
+
+/// Build one sheet of several `videos` (the episodes of a season, the
+/// angles of one event): a row of `--cols` tiles per video, in order, each
+/// row's first tile captioned with its number and file name. Written to
+/// `output`, or next to the first video.
+pub fn run(videos: &[PathBuf], output: Option<&Path>, opts: &Options) -> Result<Outcome> {
+    let output_image = match output {
+        Some(path) => path.to_path_buf(),
+        None => naming::combined_output_path(&videos[0]),
+    };
+    let first = videos[0].to_str()
+        .with_context(|| format!("{} isn't a valid UTF-8 path", videos[0].display()))?;
+    let mut job = match Job::open(first, &output_image, opts) {
+        Ok(job) => job,
+        Err(e) => return mosaic::skipped(e),
+    };
+    let cols = opts.cols;
+    let sheet = opts.sheet(videos.len(), cols, videos.len() * cols);
+    job.scale_tiles(&sheet, opts);
+    job.check_disk_space(cols * videos.len() * 2)?;
+    // Tiles are decoded at each video's own size; the tile filter needs them
+    // all alike, so other shapes are letterboxed into the first's.
+    let tile_size = job.tile_size();
+
+    let mut tiles = mosaic::extract_tiles(&mut job, cols, opts)?;
+    let mut texts = label_row(mosaic::tile_texts(&job.info, &tiles, opts), 0, cols, &videos[0]);
+    let mut titles = vec![format!("1 {}", mosaic::overlay_text(&job.video_path, &job.info, opts)?)];
+    for (row, video) in videos.iter().enumerate().skip(1) {
+        exec::check_interrupted()?;
+        // Its own scratch directory (and lock) for the row's frames.
+        let scratch_output = job.temp_path().join(format!("row_{:03}.jpg", row));
+        let video_path = video.to_str()
+            .with_context(|| format!("{} isn't a valid UTF-8 path", video.display()))?;
+        let mut other = Job::open(video_path, &scratch_output, opts)
+            .with_context(|| format!("Failed to open {}", video.display()))?;
+        other.scale_tiles(&sheet, opts);
+        job.allow_reading(other.temp_path());
+        let row_tiles = mosaic::extract_tiles(&mut other, cols, opts)
+            .with_context(|| format!("Failed to extract the frames of {}", video.display()))?;
+        for i in 0..cols {
+            compare::scale_tile(&job, &other.tile_path(i), &job.tile_path(row * cols + i), tile_size, None, opts)?;
+        }
+        texts.extend(label_row(mosaic::tile_texts(&other.info, &row_tiles, opts), row, cols, video));
+        titles.push(format!("{} {}", row + 1, mosaic::overlay_text(&other.video_path, &other.info, opts)?));
+        job.warnings.extend(other.warnings.iter().map(|w| format!("{}: {}", video.display(), w)));
+        tiles.extend(row_tiles);
+    }
+
+    mosaic::compose_titled(&mut job, &sheet, &titles.join("\n"), &texts, None, &output_image, opts)?;
+    Ok(job.finish(tiles))
+}
+
+/// `texts` for the `cols` tiles of row `row` (the video's own labels, if
+/// any), with the row's number and `video`'s file name under its first tile.
+fn label_row(texts: Vec<TileText>, row: usize, cols: usize, video: &Path) -> Vec<TileText> {
+    let name = video.file_name().unwrap_or_default().to_string_lossy();
+    let mut texts = if texts.is_empty() { vec![TileText::default(); cols] } else { texts };
+    texts[0].caption = format!("{} {}", row + 1, text::display_name(&name, MAX_ROW_LABEL));
+    texts
+}
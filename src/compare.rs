@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use crate::exec;
+use crate::font;
+use crate::mosaic::{self, Job, Source};
+use crate::naming;
+use crate::probe;
+use crate::sandbox::{self, Sandbox};
+use crate::text;
+use crate::{Options, Outcome};
+
+/// Quality metric scored between matching tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    /// Structural similarity (ffmpeg's ssim filter); 1.0 is identical.
+    Ssim,
+    /// Netflix VMAF (needs FFmpeg built with libvmaf); 0-100.
+    Vmaf,
+}
+
+impl Metric {
+    fn label(self) -> &'static str {
+        match self {
+            Metric::Ssim => "SSIM",
+            Metric::Vmaf => "VMAF",
+        }
+    }
+
+    /// Filter taking the distorted input first and the reference second.
+    fn filter(self) -> &'static str {
+        match self {
+            Metric::Ssim => "ssim",
+            Metric::Vmaf => "libvmaf",
+        }
+    }
+
+    /// The overall score from the filter's log.
+    fn parse(self, stderr: &str) -> Option<f64> {
+        let marker = match self {
+            Metric::Ssim => "All:",
+            Metric::Vmaf => "VMAF score:",
+        };
+        stderr.lines().rev().find_map(|line| {
+            let rest = &line[line.find(marker)? + marker.len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        })
+    }
+
+    fn format(self, score: f64) -> String {
+        match self {
+            Metric::Ssim => format!("{} {:.4}", self.label(), score),
+            Metric::Vmaf => format!("{} {:.1}", self.label(), score),
+        }
+    }
+}
+
+/// Length of video each metric is computed over, from the tile's timestamp.
+/// VMAF has temporal features, so a single frame would skew it.
+const METRIC_WINDOW: f64 = 1.0;
+
+/// Build a sheet comparing `a` against `b` (say a source and its encode).
+///
+/// Both are sampled at the same timestamps: `a` is sampled as usual, then
+/// `b` is seeked to the exact frame times `a` ended up with. Rows alternate
+/// between the two, so each `b` row sits directly under the `a` row it matches.
+///
+/// With `metrics`, `b` is scored against `a` as the reference at every tile,
+/// and the scores are drawn on `b`'s tiles and logged.
+pub fn run(a: &Path, b: &Path, metrics: &[Metric], opts: &Options) -> Result<Outcome> {
+    let a_path = a.to_str().with_context(|| format!("{} isn't a valid UTF-8 path", a.display()))?;
+    let b_path = b.to_str().with_context(|| format!("{} isn't a valid UTF-8 path", b.display()))?;
+    let output_image = naming::compare_output_path(a, b);
+    let mut job = match Job::open(a_path, &output_image, opts) {
+        Ok(job) => job,
+        Err(e) => return mosaic::skipped(e),
+    };
+    if metrics.contains(&Metric::Vmaf) {
+        check_vmaf_available(job.sandbox())?;
+    }
+
+    job.allow_reading(b);
+    let b_info = probe::probe(b_path, job.sandbox(), &opts.retry)?;
+    if let Some(reason) = b_info.encryption() {
+        return Ok(Outcome::Skipped(format!("{}: encrypted/DRM-protected content ({})", b.display(), reason)));
+    }
+    let Some(b_stream) = b_info.video_stream().map(|s| s.index) else {
+        return Ok(Outcome::Skipped(format!("{}: {}", b.display(), b_info.missing_video_reason())));
+    };
+    let (b_duration, b_warning) = b_info.duration()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get duration of {} with ffprobe: {}", b.display(), b_info.diagnostics.trim()))?;
+    job.warnings.extend(b_warning.map(|w| format!("{}: {}", b.display(), w)));
+    if (job.duration - b_duration).abs() > 1.0 {
+        job.warnings.push(format!(
+            "durations differ ({:.2}s vs {:.2}s); sampling only the common part",
+            job.duration, b_duration
+        ));
+    }
+    job.duration = job.duration.min(b_duration);
+
+    let (rows, cols, frames) = job.fit_grid(opts.rows, opts.cols, opts.frames, opts);
+    job.scale_tiles(&opts.sheet(rows * 2, cols, frames * 2), opts);
+    job.check_disk_space(frames * 2)?;
+    let tiles = mosaic::extract_tiles(&mut job, frames, opts)?;
+
+    let b_src = Source {
+        path: b_path,
+        stream: b_stream,
+        start_time: b_info.start_time(),
+        sandbox: job.sandbox(),
+        frame_format: opts.frame_format,
+        width: job.tile_width(),
+        seek: opts.seek,
+        hwaccel: opts.hwaccel.as_deref(),
+    };
+    // B's frames come out at its own shape; the tile filter needs them all alike.
+    let tile_size = job.tile_size();
+    let side_tile = |side: &str, i: usize| job.temp_path().join(format!("{}_{:03}.{}", side, i, job.frame_extension()));
+    let mut warnings = Vec::new();
+    for (i, tile) in tiles.iter().enumerate() {
+        fs::rename(job.tile_path(i), side_tile("a", i))?;
+
+        let decoded = job.temp_path().join(format!("b_decoded.{}", job.frame_extension()));
+        let _ = fs::remove_file(&decoded);
+        match mosaic::extract_frame(&b_src, tile.shown, &decoded, opts.frame_timeout) {
+            Ok(_) => {}
+            Err(e) if exec::is_timed_out(&e) => {}
+            Err(e) => return Err(e),
+        }
+        if decoded.is_file() {
+            let mut scores = Vec::new();
+            for &metric in metrics {
+                match measure(&job.source(), &b_src, tile.shown, tile_size, metric, opts.frame_timeout) {
+                    Ok(Some(score)) => scores.push(metric.format(score)),
+                    Ok(None) => warnings.push(format!("tile {}: no {} score came out", i + 1, metric.label())),
+                    Err(e) if exec::is_timed_out(&e) => {
+                        warnings.push(format!("tile {}: gave up computing {}", i + 1, metric.label()));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if !scores.is_empty() {
+                log::info!("tile {} at {:.3}s: {}", i + 1, tile.shown, scores.join(", "));
+            }
+            let label = (!scores.is_empty()).then(|| scores.join("  "));
+            scale_tile(&job, &decoded, &side_tile("b", i), tile_size, label.as_deref(), opts)?;
+        } else {
+            warnings.push(format!("tile {}: no frame of {} could be decoded; padded with a blank tile", i + 1, b.display()));
+            mosaic::write_blank_tile(&side_tile("b", i), tile_size, job.sandbox())?;
+        }
+    }
+
+    // Row by row: a row of `a` tiles, then the matching row of `b` tiles. A
+    // short last row of `a` is padded out so its `b` row still starts below.
+    let a_texts = mosaic::tile_texts(&job.info, &tiles, opts);
+    let b_texts = mosaic::tile_texts(&b_info, &tiles, opts);
+    let mut texts = Vec::new();
+    let mut next = 0;
+    for row in (0..frames).step_by(cols) {
+        let end = (row + cols).min(frames);
+        for (side, side_texts) in [("a", &a_texts), ("b", &b_texts)] {
+            for i in row..end {
+                fs::rename(side_tile(side, i), job.tile_path(next))?;
+                texts.push(side_texts.get(i).cloned().unwrap_or_default());
+                next += 1;
+            }
+            if side == "a" {
+                for _ in end..row + cols {
+                    mosaic::write_blank_tile(&job.tile_path(next), tile_size, job.sandbox())?;
+                    texts.push(Default::default());
+                    next += 1;
+                }
+            }
+        }
+    }
+    if a_texts.is_empty() && b_texts.is_empty() {
+        texts.clear();
+    }
+
+    let title = format!(
+        "A {}\nB {}",
+        mosaic::overlay_text(&job.video_path, &job.info, opts)?,
+        mosaic::overlay_text(b_path, &b_info, opts)?,
+    );
+    let timeline = mosaic::timeline(&job.info, job.duration, &tiles, opts);
+    let sheet = opts.sheet(rows * 2, cols, next);
+    mosaic::compose_titled(&mut job, &sheet, &title, &texts, timeline.as_ref(), &output_image, opts)?;
+
+    job.warnings.extend(warnings);
+    Ok(job.finish(tiles))
+}
+
+/// Score `b` against `a` with `metric`, over `METRIC_WINDOW` seconds from `timestamp`.
+fn measure(
+    a: &Source,
+    b: &Source,
+    timestamp: f64,
+    (width, height): (u32, u32),
+    metric: Metric,
+    timeout: Option<Duration>,
+) -> Result<Option<f64>> {
+    let seek = format!("{:.3}", timestamp);
+    let window = format!("{:.3}", METRIC_WINDOW);
+    // Both inputs are seeked, so their timestamps start at zero together and
+    // the metric filter pairs up matching frames.
+    let graph = format!(
+        "[0:{}]scale={}:{}[dist];[1:{}]scale={}:{}[ref];[dist][ref]{}",
+        b.stream, width, height, a.stream, width, height, metric.filter()
+    );
+    let output = exec::run_with_timeout(sandbox::command("ffmpeg", a.sandbox)
+        .args(["-hide_banner", "-ss", &seek, "-t", &window, "-i", b.path])
+        .args(["-ss", &seek, "-t", &window, "-i", a.path])
+        .args(["-lavfi", &graph, "-an", "-f", "null", "-"]), timeout)
+        .with_context(|| format!("Failed to compute {} at {:.3}s", metric.label(), timestamp))?;
+
+    Ok(metric.parse(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Fail unless FFmpeg was built with the libvmaf filter.
+fn check_vmaf_available(sandbox: Option<&Sandbox>) -> Result<()> {
+    let output = exec::run(sandbox::command("ffmpeg", sandbox).args(["-hide_banner", "-filters"]))
+        .with_context(|| "Failed to list FFmpeg's filters")?;
+    let filters = String::from_utf8_lossy(&output.stdout);
+    if !filters.lines().any(|line| line.split_whitespace().nth(1) == Some("libvmaf")) {
+        anyhow::bail!("--metric vmaf needs an FFmpeg built with libvmaf (--enable-libvmaf)");
+    }
+    Ok(())
+}
+
+/// Fit an extracted frame into `(width, height)`, letterboxed if it's
+/// another shape, drawing `label` along its bottom edge if given.
+pub fn scale_tile(
+    job: &Job,
+    input: &Path,
+    output_file: &Path,
+    (width, height): (u32, u32),
+    label: Option<&str>,
+    opts: &Options,
+) -> Result<()> {
+    let mut filter = format!(
+        "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1",
+        w = width, h = height
+    );
+    if let Some(label) = label {
+        let font_path = font::path(opts)?;
+        let text_file = job.temp_path().join("scores.txt");
+        fs::write(&text_file, label)?;
+        filter += &format!(
+            ",drawtext=fontfile={}:textfile={}:expansion=none:x=10:y=h-th-10:fontsize={}:fontcolor=white:box=1:boxcolor=black@0.6",
+            text::escape_filter_value(&font_path),
+            text::escape_filter_value(&text_file.to_string_lossy()),
+            (height / 12).max(12),
+        );
+    }
+
+    exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .arg("-i")
+        .arg(input)
+        .args(["-vf", &filter, "-q:v", "2", "-update", "1", "-y"])
+        .arg(output_file))
+        .with_context(|| format!("Failed to scale {}", input.display()))?;
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use crate::mosaic::{Chroma, FrameFormat, GridMode, SeekMode};
+use crate::naming::Template;
+use crate::probe::MediaInfo;
+use crate::theme::ThemeOverrides;
+
+/// Name of the config file that overrides settings for a directory and
+/// everything below it.
+pub const DIR_CONFIG: &str = ".thumbnailer.toml";
+
+/// Settings read from the user's config file and the `--config` TOML file,
+/// or from a directory's `.thumbnailer.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub grid: Option<GridMode>,
+    pub rows: Option<usize>,
+    pub cols: Option<usize>,
+    pub frames: Option<usize>,
+    pub timecode: Option<bool>,
+    pub tile_timestamps: Option<bool>,
+    pub tile_index: Option<bool>,
+    pub chapter_titles: Option<bool>,
+    pub timeline: Option<bool>,
+    pub frame_format: Option<FrameFormat>,
+    pub seek: Option<SeekMode>,
+    pub hwaccel: Option<String>,
+    pub chroma: Option<Chroma>,
+    pub tile_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub font_file: Option<PathBuf>,
+    pub name_template: Option<Template>,
+    /// Whether batch runs add to the usage stats (`thumbnailer stats`);
+    /// only read from the user's and `--config` files.
+    pub stats: Option<bool>,
+    /// `serve`'s workers; only read from the user's and `--config` files.
+    pub workers: Option<usize>,
+    pub background_workers: Option<usize>,
+    /// Video extensions instead of the built-in ones, and besides them;
+    /// only read from the user's and `--config` files.
+    pub extensions: Option<Vec<String>>,
+    pub extra_extensions: Option<Vec<String>>,
+    pub theme: ThemeOverrides,
+    /// Settings for the files of one container, codec or resolution, keyed
+    /// by extension (`ts`), video codec (`hevc`), resolution (`sd`, `720p`,
+    /// `1080p`, `4k`, `8k`) or codec and resolution (`hevc-4k`). They're
+    /// applied once the file is probed, in that order, the settings a
+    /// directory's config can change only: `seek = "accurate"` for `ts`, say,
+    /// or `hwaccel` for `hevc-4k`.
+    pub overrides: BTreeMap<String, Config>,
+}
+
+impl Config {
+    /// Apply `over`'s settings on top of these.
+    pub fn merge(&mut self, over: &Config) {
+        // A grid change starts over from rows x cols unless it sets frames too.
+        if over.rows.is_some() || over.cols.is_some() {
+            self.frames = None;
+            self.grid = None;
+        }
+        self.grid = over.grid.or(self.grid);
+        self.rows = over.rows.or(self.rows);
+        self.cols = over.cols.or(self.cols);
+        self.frames = over.frames.or(self.frames);
+        self.timecode = over.timecode.or(self.timecode);
+        self.tile_timestamps = over.tile_timestamps.or(self.tile_timestamps);
+        self.tile_index = over.tile_index.or(self.tile_index);
+        self.chapter_titles = over.chapter_titles.or(self.chapter_titles);
+        self.timeline = over.timeline.or(self.timeline);
+        self.stats = over.stats.or(self.stats);
+        self.frame_format = over.frame_format.or(self.frame_format);
+        self.seek = over.seek.or(self.seek);
+        self.hwaccel = over.hwaccel.clone().or(self.hwaccel.take());
+        self.chroma = over.chroma.or(self.chroma);
+        self.tile_width = over.tile_width.or(self.tile_width);
+        self.max_width = over.max_width.or(self.max_width);
+        self.font_file = over.font_file.clone().or(self.font_file.take());
+        self.name_template = over.name_template.clone().or(self.name_template.take());
+        self.workers = over.workers.or(self.workers);
+        self.background_workers = over.background_workers.or(self.background_workers);
+        self.extensions = over.extensions.clone().or(self.extensions.take());
+        self.extra_extensions = over.extra_extensions.clone().or(self.extra_extensions.take());
+        self.theme.merge(&over.theme);
+        merge_overrides(&mut self.overrides, &over.overrides);
+    }
+}
+
+/// Apply `over`'s `[overrides.*]` sections on top of those in `overrides`.
+pub fn merge_overrides(overrides: &mut BTreeMap<String, Config>, over: &BTreeMap<String, Config>) {
+    for (key, config) in over {
+        overrides.entry(key.clone()).or_default().merge(config);
+    }
+}
+
+/// The `[overrides.*]` keys that apply to `video`, least specific first:
+/// its extension, video codec, resolution, then codec and resolution.
+pub fn media_keys(video: &Path, info: &MediaInfo) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(extension) = video.extension() {
+        keys.push(extension.to_string_lossy().to_lowercase());
+    }
+    let stream = info.video_stream();
+    let codec = stream.map(|s| s.codec_name.to_lowercase()).filter(|c| !c.is_empty());
+    let class = stream.and_then(|s| Some(s.width?.min(s.height?))).map(|short| match short {
+        4320.. => "8k",
+        2160.. => "4k",
+        1080.. => "1080p",
+        720.. => "720p",
+        _ => "sd",
+    });
+    keys.extend(codec.clone());
+    keys.extend(class.map(str::to_string));
+    if let (Some(codec), Some(class)) = (codec, class) {
+        keys.push(format!("{}-{}", codec, class));
+    }
+    keys
+}
+
+/// The directory of the user's settings: `$XDG_CONFIG_HOME/thumbnailer`,
+/// by default `~/.config/thumbnailer`; on macOS `~/Library/Application
+/// Support/thumbnailer` and on Windows `%APPDATA%\thumbnailer`, unless
+/// `XDG_CONFIG_HOME` is set there too.
+pub fn user_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("thumbnailer"));
+    }
+    if cfg!(windows) {
+        return std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("thumbnailer"));
+    }
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library").join("Application Support").join("thumbnailer"))
+    } else {
+        Some(home.join(".config").join("thumbnailer"))
+    }
+}
+
+/// The directory of the user's cached files: `$XDG_CACHE_HOME/thumbnailer`,
+/// by default `~/.cache/thumbnailer`; on macOS `~/Library/Caches/thumbnailer`
+/// and on Windows `%LOCALAPPDATA%\thumbnailer`.
+#[cfg_attr(not(feature = "embedded-font"), allow(dead_code))]
+pub fn user_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("thumbnailer"));
+    }
+    if cfg!(windows) {
+        return std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("thumbnailer"));
+    }
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library").join("Caches").join("thumbnailer"))
+    } else {
+        Some(home.join(".cache").join("thumbnailer"))
+    }
+}
+
+/// The user's config file: `config.toml` in `dir` (`--config-dir`) or in
+/// `user_dir`.
+pub fn user_path(dir: Option<&Path>) -> Option<PathBuf> {
+    let dir = dir.map(Path::to_path_buf).or_else(user_dir)?;
+    Some(dir.join("config.toml"))
+}
+
+/// The run's defaults: the user's config file (in `dir`, if given), if
+/// there is one, with the `--config` file's settings on top.
+pub fn load_defaults(explicit: Option<&Path>, dir: Option<&Path>) -> Result<Config> {
+    let mut config = match user_path(dir) {
+        Some(path) if path.is_file() => load(&path)?,
+        _ => Config::default(),
+    };
+    if let Some(path) = explicit {
+        config.merge(&load(path)?);
+    }
+    Ok(config)
+}
+
+/// Read and parse a config file.
+pub fn load(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+/// The `.thumbnailer.toml` files of a directory tree, read once per directory.
+#[derive(Default)]
+pub struct DirConfigs {
+    loaded: HashMap<PathBuf, Option<Config>>,
+}
+
+impl DirConfigs {
+    /// The overrides for `file` below `root`: every directory config from
+    /// `root` down to the file's own directory, the deepest applied last.
+    /// `None` when there are none.
+    pub fn for_file(&mut self, root: &Path, file: &Path) -> Result<Option<Config>> {
+        let Some(dir) = file.parent() else {
+            return Ok(None);
+        };
+        let mut dirs: Vec<&Path> = dir.ancestors().take_while(|d| d.starts_with(root)).collect();
+        dirs.reverse();
+
+        let mut merged: Option<Config> = None;
+        for dir in dirs {
+            if !self.loaded.contains_key(dir) {
+                let config = load_dir(dir)?;
+                self.loaded.insert(dir.to_path_buf(), config);
+            }
+            if let Some(config) = &self.loaded[dir] {
+                merged.get_or_insert_with(Config::default).merge(config);
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// `dir`'s `.thumbnailer.toml`, if it has one.
+fn load_dir(dir: &Path) -> Result<Option<Config>> {
+    let path = dir.join(DIR_CONFIG);
+    match fs::metadata(&path) {
+        Ok(_) => load(&path).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read config file {}", path.display())),
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::exec;
+use crate::index::{self, Status};
+use crate::mosaic::{Job, SeekMode, Source, Tile};
+use crate::probe;
+use crate::report::format_size;
+use crate::sandbox::{self, Sandbox};
+use crate::text;
+use crate::Options;
+
+/// Frames hashed per video that has no sheet recorded in the index.
+const SAMPLES: usize = 8;
+
+/// Fraction of the duration skipped at either end, where intros, credits
+/// and black frames make unrelated videos look alike.
+const EDGE_FRACTION: f64 = 0.05;
+
+/// Two frames match when their hashes differ in at most this many of 64 bits.
+const MAX_FRAME_DISTANCE: u32 = 10;
+
+/// Share of the compared frames that must match for a likely duplicate.
+const MIN_MATCHING_SHARE: f64 = 0.75;
+
+/// Fewest frames that must be comparable before a pair is judged at all.
+const MIN_COMPARED: usize = 3;
+
+/// Flat frames (fades, black) carry no information and hash alike.
+const MIN_CONTRAST: u8 = 8;
+
+/// Frames of two videos are compared when they were taken this close,
+/// as a share of the duration.
+const MAX_POSITION_GAP: f64 = 0.01;
+
+/// Size of the grey thumbnail a frame is shrunk to for its dHash.
+const HASH_WIDTH: usize = 9;
+const HASH_HEIGHT: usize = 8;
+
+/// FFmpeg output arguments writing each frame as such a thumbnail to stdout.
+const GREY_THUMBNAIL: [&str; 7] = ["-vf", "scale=9:8:flags=area", "-pix_fmt", "gray", "-f", "rawvideo", "-"];
+
+/// The perceptual hash of a frame of a video, as recorded in the index.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameHash {
+    /// Where the frame was asked for, in seconds.
+    pub time: f64,
+    /// dHash of the frame; `None` where it was flat or couldn't be decoded.
+    pub hash: Option<u64>,
+}
+
+/// A video's fingerprint.
+struct Signature {
+    path: PathBuf,
+    duration: f64,
+    resolution: String,
+    size: u64,
+    frames: Vec<FrameHash>,
+}
+
+/// Find videos in `dir` that are likely the same content (different encodes,
+/// names or containers) by comparing perceptual hashes of frames taken at
+/// the same relative positions, and print them in groups.
+///
+/// The frames are those of the videos' sheets, hashed as the sheets were
+/// made and recorded in `index_path`; videos with none there are sampled.
+pub fn run(dir: &Path, index_path: Option<&Path>, opts: &Options) -> Result<()> {
+    // ffmpeg only writes to its stdout here, so the sandbox needs no writable directories.
+    let sandbox = opts.sandbox.then(|| Sandbox::new(Vec::new()));
+    let paths = crate::video_files(dir, opts)?;
+    let mut recorded: HashMap<PathBuf, Vec<FrameHash>> = match index_path {
+        Some(path) => index::read(path)?.into_iter()
+            .filter(|entry| entry.status == Status::Created && !entry.frames.is_empty())
+            .map(|entry| (fs::canonicalize(&entry.path).unwrap_or(entry.path), entry.frames))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let mut signatures = Vec::with_capacity(paths.len());
+    for path in paths {
+        exec::check_interrupted()?;
+        let frames = recorded.remove(&fs::canonicalize(&path).unwrap_or_else(|_| path.clone()));
+        log::info!("{}: {}", if frames.is_some() { "Reading" } else { "Hashing" }, path.display());
+        match signature(&path, frames, sandbox.as_ref(), opts) {
+            Ok(Some(signature)) => signatures.push(signature),
+            Ok(None) => log::info!("Skipped {}: no video stream or duration", path.display()),
+            Err(e) if exec::is_interrupted(&e) => return Err(e),
+            Err(e) => log::error!("Failed to hash {}: {:#}", path.display(), e),
+        }
+    }
+
+    let groups: Vec<Vec<&Signature>> = groups(&signatures).into_iter()
+        .map(|members| members.into_iter().map(|i| &signatures[i]).collect())
+        .collect();
+    if groups.is_empty() {
+        println!("No likely duplicates among {} videos.", signatures.len());
+        return Ok(());
+    }
+    for members in &groups {
+        println!("\nLikely duplicates ({} files):", members.len());
+        for s in members {
+            println!(
+                "  {} ({}, {}, {})",
+                s.path.display(), text::format_time(s.duration), s.resolution, format_size(s.size)
+            );
+        }
+    }
+    println!("\n{} groups of likely duplicates among {} videos.", groups.len(), signatures.len());
+    Ok(())
+}
+
+/// The indices of the signatures that are likely duplicates, in groups of
+/// two or more. Grouped transitively: if A looks like B and B like C, all
+/// three are listed together.
+fn groups(signatures: &[Signature]) -> Vec<Vec<usize>> {
+    let mut group: Vec<usize> = (0..signatures.len()).collect();
+    for i in 0..signatures.len() {
+        for j in i + 1..signatures.len() {
+            if likely_duplicates(&signatures[i], &signatures[j]) {
+                let (a, b) = (find(&mut group, i), find(&mut group, j));
+                group[b] = a;
+            }
+        }
+    }
+
+    let mut by_root: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..signatures.len() {
+        by_root.entry(find(&mut group, i)).or_default().push(i);
+    }
+    by_root.into_values().filter(|g| g.len() > 1).collect()
+}
+
+/// Representative of `i`'s group in a union-find forest.
+fn find(group: &mut [usize], i: usize) -> usize {
+    if group[i] != i {
+        group[i] = find(group, group[i]);
+    }
+    group[i]
+}
+
+/// The fingerprint of the video at `path`, from the `frames` of its sheet
+/// when there are some, or else from frames sampled from it.
+fn signature(path: &Path, frames: Option<Vec<FrameHash>>, sandbox: Option<&Sandbox>, opts: &Options) -> Result<Option<Signature>> {
+    let video_path = path.to_str().context("the video's path isn't valid UTF-8")?;
+    let sandbox = sandbox.cloned().map(|sandbox| sandbox.reading(path));
+    let sandbox = sandbox.as_ref();
+    let info = probe::probe(video_path, sandbox, &opts.retry)?;
+    let (Some(stream), Some((duration, _))) = (info.video_stream(), info.duration()) else {
+        return Ok(None);
+    };
+    let src = Source {
+        path: video_path,
+        stream: stream.index,
+        start_time: info.start_time(),
+        sandbox,
+        frame_format: opts.frame_format,
+        // frame_hash seeks, and shrinks the frames to a few pixels, itself.
+        width: None,
+        seek: SeekMode::Fast,
+        hwaccel: None,
+    };
+    let size = opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(path))?.len();
+
+    let frames = match frames {
+        Some(frames) => frames,
+        None => {
+            let mut frames = Vec::with_capacity(SAMPLES);
+            for k in 0..SAMPLES {
+                let time = duration * (EDGE_FRACTION + (1.0 - 2.0 * EDGE_FRACTION) * (k as f64 + 0.5) / SAMPLES as f64);
+                let hash = match frame_hash(&src, time, opts) {
+                    Ok(hash) => hash,
+                    Err(e) if exec::is_timed_out(&e) => None,
+                    Err(e) => return Err(e),
+                };
+                frames.push(FrameHash { time, hash });
+            }
+            frames
+        }
+    };
+
+    Ok(Some(Signature { path: path.to_path_buf(), duration, resolution: info.resolution(), size, frames }))
+}
+
+/// The hashes of a job's `tiles`, for the index, read from the tiles
+/// extracted for its sheet in one pass.
+pub fn tile_hashes(job: &Job, tiles: &[Tile]) -> Result<Vec<FrameHash>> {
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-v", "error", "-start_number", "0", "-i"])
+        .arg(job.tile_pattern())
+        .args(["-frames:v", &tiles.len().to_string()])
+        .args(GREY_THUMBNAIL))
+        .with_context(|| "Failed to hash the tiles")?;
+    let thumbnails = output.stdout.chunks_exact(HASH_WIDTH * HASH_HEIGHT);
+    Ok(tiles.iter().zip(thumbnails.map(Some).chain(std::iter::repeat(None)))
+        .map(|(tile, pixels)| FrameHash {
+            time: tile.requested,
+            hash: pixels.filter(|_| !tile.blank).and_then(dhash),
+        })
+        .collect())
+}
+
+/// dHash of the frame at `timestamp`: the frame shrunk to 9x8 grey pixels,
+/// one bit per horizontally adjacent pair saying whether brightness rises.
+///
+/// It survives re-encoding, scaling and mild color changes, which is the
+/// point: encodes of the same source hash (nearly) the same.
+fn frame_hash(src: &Source, timestamp: f64, opts: &Options) -> Result<Option<u64>> {
+    let output = exec::run_with_timeout(sandbox::command("ffmpeg", src.sandbox)
+        .args([
+            "-v", "error",
+            "-ss", &format!("{:.3}", timestamp),
+            "-i", src.path,
+            "-map", &src.map(),
+            "-frames:v", "1",
+        ])
+        .args(GREY_THUMBNAIL), opts.frame_timeout)
+        .with_context(|| format!("Failed to sample frame at {:.3}s", timestamp))?;
+    Ok(dhash(&output.stdout))
+}
+
+/// dHash of a 9x8 grey thumbnail; `None` if it's any other size or flat.
+fn dhash(pixels: &[u8]) -> Option<u64> {
+    if pixels.len() != HASH_WIDTH * HASH_HEIGHT {
+        return None;
+    }
+    let (min, max) = (pixels.iter().min()?, pixels.iter().max()?);
+    if max - min < MIN_CONTRAST {
+        return None;
+    }
+
+    let mut hash = 0u64;
+    for row in pixels.chunks(HASH_WIDTH) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[1] > pair[0]) as u64;
+        }
+    }
+    Some(hash)
+}
+
+/// Bits in which two frame hashes differ.
+fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Whether two videos look like the same content: durations agree and most
+/// of the frames compared at the same relative positions match.
+fn likely_duplicates(a: &Signature, b: &Signature) -> bool {
+    if (a.duration - b.duration).abs() > (0.02 * a.duration.max(b.duration)).max(2.0) {
+        return false;
+    }
+    // Each of `a`'s frames against `b`'s nearest, if it's near enough.
+    let gap = |x: &FrameHash, y: &FrameHash| (x.time / a.duration - y.time / b.duration).abs();
+    let pairs: Vec<(u64, u64)> = a.frames.iter()
+        .filter_map(|x| {
+            let y = b.frames.iter().min_by(|y, z| gap(x, y).total_cmp(&gap(x, z)))?;
+            if gap(x, y) > MAX_POSITION_GAP {
+                return None;
+            }
+            Some((x.hash?, y.hash?))
+        })
+        .collect();
+    if pairs.len() < MIN_COMPARED {
+        return false;
+    }
+    let matching = pairs.iter().filter(|&&(x, y)| distance(x, y) <= MAX_FRAME_DISTANCE).count();
+    matching as f64 >= pairs.len() as f64 * MIN_MATCHING_SHARE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(duration: f64, hashes: &[Option<u64>]) -> Signature {
+        let step = duration / hashes.len() as f64;
+        Signature {
+            path: PathBuf::new(),
+            duration,
+            resolution: String::new(),
+            size: 0,
+            frames: hashes.iter().enumerate()
+                .map(|(i, &hash)| FrameHash { time: step * (i as f64 + 0.5), hash })
+                .collect(),
+        }
+    }
+
+    const HASHES: [u64; 6] = [
+        0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210, 0x0f0f_0f0f_0f0f_0f0f,
+        0xf0f0_f0f0_f0f0_f0f0, 0x3333_3333_3333_3333, 0xcccc_cccc_cccc_cccc,
+    ];
+
+    fn hashes(flip: u64) -> Vec<Option<u64>> {
+        HASHES.iter().map(|&h| Some(h ^ flip)).collect()
+    }
+
+    #[test]
+    fn dhash_marks_brightness_rising_to_the_right() {
+        let rising: Vec<u8> = (0..8).flat_map(|_| (0..9).map(|x| x * 20)).collect();
+        assert_eq!(dhash(&rising), Some(u64::MAX));
+        let falling: Vec<u8> = rising.iter().map(|&p| 255 - p).collect();
+        assert_eq!(dhash(&falling), Some(0));
+        // Only the first row rises.
+        let first_row: Vec<u8> = (0..72).map(|i| if i < 9 { i as u8 * 20 } else { 100 }).collect();
+        assert_eq!(dhash(&first_row), Some(0xff << 56));
+        assert_eq!(dhash(&[100; 72]), None, "flat");
+        assert_eq!(dhash(&rising[..71]), None, "wrong size");
+    }
+
+    #[test]
+    fn distance_counts_differing_bits() {
+        assert_eq!(distance(0, 0), 0);
+        assert_eq!(distance(0b1011, 0b0110), 3);
+        assert_eq!(distance(0, u64::MAX), 64);
+    }
+
+    #[test]
+    fn duplicates_need_matching_frames_and_durations() {
+        let a = signature(600.0, &hashes(0));
+        // A re-encode: a few bits off in every frame, a second shorter.
+        assert!(likely_duplicates(&a, &signature(599.0, &hashes(0b1011))));
+        // Too many bits off.
+        assert!(!likely_duplicates(&a, &signature(600.0, &hashes(0xffff))));
+        // Another length.
+        assert!(!likely_duplicates(&a, &signature(700.0, &hashes(0))));
+        // Too few frames to judge by.
+        let flat = [Some(HASHES[0]), Some(HASHES[1]), None, None, None, None];
+        assert!(!likely_duplicates(&a, &signature(600.0, &flat)));
+        // Frames taken elsewhere (another grid) aren't compared.
+        assert!(!likely_duplicates(&a, &signature(600.0, &hashes(0)[..5])));
+    }
+
+    #[test]
+    fn frames_are_paired_by_position() {
+        let a = signature(600.0, &hashes(0));
+        // The same frames, listed with two more in between.
+        let mut b = signature(600.0, &hashes(0));
+        b.frames.push(FrameHash { time: 100.0, hash: Some(!HASHES[0]) });
+        b.frames.push(FrameHash { time: 300.0, hash: Some(!HASHES[1]) });
+        assert!(likely_duplicates(&a, &b));
+    }
+
+    #[test]
+    fn groups_are_transitive() {
+        let signatures = [
+            signature(600.0, &hashes(0)),
+            signature(600.0, &hashes(0xff)),
+            signature(600.0, &hashes(0xffff)),
+            signature(600.0, &hashes(0xffff_ffff)),
+            signature(300.0, &hashes(0)),
+        ];
+        // 0 and 2 are 16 bits apart, too far to match, but each is 8 from 1.
+        assert!(!likely_duplicates(&signatures[0], &signatures[2]));
+        assert_eq!(groups(&signatures), vec![vec![0, 1, 2]]);
+        assert!(groups(&signatures[2..]).is_empty());
+    }
+}
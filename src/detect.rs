@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use infer::MatcherType;
+use crate::probe;
+use crate::sandbox::Sandbox;
+use crate::Options;
+
+/// Extensions taken to be video without looking inside the file, unless
+/// `--extensions` replaces them.
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "mov", "avi", "mkv", "webm", "m4v", "wmv", "mpg", "mpeg", "ts",
+    "flv", "3gp", "3g2", "ogv", "mts", "m2ts", "vob", "asf", "mxf",
+];
+
+/// The video extensions of a run: `replace`, if given, instead of the
+/// built-in ones, and `extra` besides. Lowercase, without dots.
+pub fn extensions(replace: Option<&[String]>, extra: &[String]) -> Vec<String> {
+    let base: Vec<String> = match replace {
+        Some(list) => list.to_vec(),
+        None => VIDEO_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+    };
+    let mut extensions: Vec<String> = Vec::new();
+    for extension in base.iter().chain(extra) {
+        let extension = extension.trim().trim_start_matches('.').to_lowercase();
+        if !extension.is_empty() && !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    }
+    extensions
+}
+
+/// Whether `path` has one of `extensions` (as from `extensions`).
+pub fn has_video_extension(path: &Path, extensions: &[String]) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    extensions.contains(&extension)
+}
+
+/// What directory mode does with symbolic links.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Take links to files, but don't descend into linked directories.
+    #[default]
+    Files,
+    /// Take links to files and descend into linked directories too.
+    Follow,
+    /// Leave every link out.
+    Skip,
+}
+
+/// The files found so far, by device and inode, so that hard links and
+/// symlinks to a file already taken are left out.
+#[derive(Default)]
+pub struct SeenFiles(HashMap<(u64, u64), PathBuf>);
+
+impl SeenFiles {
+    /// The path the file `meta` describes was first seen under, or `None`
+    /// (remembering it as `path`) if it wasn't seen before.
+    pub fn earlier(&mut self, path: &Path, meta: &Metadata) -> Option<PathBuf> {
+        let id = file_id(meta)?;
+        match self.0.get(&id) {
+            Some(first) => Some(first.clone()),
+            None => {
+                self.0.insert(id, path.to_path_buf());
+                None
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_id(meta: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Files have no stable identity to compare elsewhere, so none are merged.
+#[cfg(not(unix))]
+fn file_id(_meta: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether `path` should be made a sheet of: it has a video extension or,
+/// with `--probe-all`, its content is video.
+///
+/// Content is judged by the file's magic bytes, falling back to ffprobe
+/// for what they don't settle: formats they don't identify (MPEG-TS, raw
+/// streams) and Ogg, which can hold Theora video or only audio.
+pub fn is_video(path: &Path, opts: &Options) -> bool {
+    if has_video_extension(path, &opts.extensions) {
+        return true;
+    }
+    if !opts.probe_all {
+        return false;
+    }
+    match infer::get_from_path(path) {
+        Ok(Some(kind)) if kind.matcher_type() == MatcherType::Video => true,
+        Ok(Some(kind)) if kind.extension() == "ogg" => has_video_stream(path, opts),
+        Ok(Some(_)) => false,
+        Ok(None) => has_video_stream(path, opts),
+        // Directories, and files that can't be read (which fail when they're used).
+        Err(_) => false,
+    }
+}
+
+/// Whether ffprobe finds a real video stream (not cover art) in `path`.
+fn has_video_stream(path: &Path, opts: &Options) -> bool {
+    let sandbox = opts.sandbox.then(|| Sandbox::new(Vec::new()).reading(path));
+    match probe::probe(&path.to_string_lossy(), sandbox.as_ref(), &opts.retry) {
+        Ok(info) => info.video_stream().is_some(),
+        Err(e) => {
+            log::debug!("{}: not taken as video, ffprobe failed: {:#}", path.display(), e);
+            false
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+/// Rough JPEG size at `-q:v 2`, in bytes per pixel. Deliberately pessimistic.
+const JPEG_BYTES_PER_PIXEL: f64 = 0.5;
+
+/// Extra room kept free on top of the estimate.
+const HEADROOM_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Error returned when a filesystem doesn't have room for a file's outputs.
+/// Further files would fail the same way, so batch runs stop on it.
+#[derive(Debug)]
+pub struct LowDiskSpace {
+    pub path: PathBuf,
+    pub needed: u64,
+    pub available: u64,
+}
+
+impl fmt::Display for LowDiskSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "not enough free space on {}: need about {:.1} MB, {:.1} MB available",
+            self.path.display(),
+            self.needed as f64 / 1_000_000.0,
+            self.available as f64 / 1_000_000.0
+        )
+    }
+}
+
+impl std::error::Error for LowDiskSpace {}
+
+/// Whether an error (or anything in its context chain) is a `LowDiskSpace`.
+pub fn is_low_disk_space(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<LowDiskSpace>())
+}
+
+/// Estimated bytes needed in the scratch directory and at the destination
+/// for a sheet of `tiles` frames of `width x height`, with extracted frames
+/// taking `frame_bytes_per_pixel`.
+///
+/// Scratch holds every extracted frame plus the untitled mosaic; the
+/// destination holds the final mosaic.
+pub fn estimate(width: u32, height: u32, tiles: usize, frame_bytes_per_pixel: f64) -> (u64, u64) {
+    let pixels = width as f64 * height as f64 * tiles as f64;
+    ((pixels * frame_bytes_per_pixel * 2.0) as u64, (pixels * JPEG_BYTES_PER_PIXEL) as u64)
+}
+
+/// Check that `temp_dir` and `output_dir` have room for the estimated
+/// `(temp, output)` bytes, counting both against one filesystem when shared.
+pub fn check(temp_dir: &Path, output_dir: &Path, (temp, output): (u64, u64)) -> Result<()> {
+    if same_filesystem(temp_dir, output_dir) {
+        return check_one(output_dir, temp + output);
+    }
+    check_one(temp_dir, temp)?;
+    check_one(output_dir, output)
+}
+
+fn check_one(dir: &Path, needed: u64) -> Result<()> {
+    let available = fs4::available_space(dir)
+        .with_context(|| format!("Failed to check free space on {}", dir.display()))?;
+    let needed = needed + HEADROOM_BYTES;
+    if available < needed {
+        return Err(LowDiskSpace { path: dir.to_path_buf(), needed, available }.into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    false
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+
+/// One cut of an edit decision list: where a shot starts in the source.
+#[derive(Clone, Debug)]
+pub struct Cut {
+    /// The source in point as written: `HH:MM:SS:FF` timecode (`;FF` for
+    /// drop-frame), `HH:MM:SS.sss` or seconds.
+    pub at: String,
+    /// Shown under the cut's tile: its event number and clip name, or the
+    /// CSV's label.
+    pub label: String,
+}
+
+impl Cut {
+    /// Whether `at` is a timecode, which needs the video's frame rate.
+    pub fn is_timecode(&self) -> bool {
+        self.at.split([':', ';']).count() == 4
+    }
+
+    /// `at` in seconds, unless it's a timecode.
+    pub fn seconds(&self) -> Option<f64> {
+        if self.is_timecode() {
+            return None;
+        }
+        clock_seconds(&self.at)
+    }
+}
+
+/// Read the cuts of a CMX 3600 EDL (`.edl`) or of a CSV of `in,out,label`
+/// lines (out and label optional, a header line allowed), in file order.
+pub fn read(path: &Path) -> Result<Vec<Cut>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let cmx = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("edl"));
+    let cuts = if cmx { parse_cmx(&text) } else { parse_csv(&text) }
+        .with_context(|| format!("Failed to read the cuts in {}", path.display()))?;
+    if cuts.is_empty() {
+        anyhow::bail!("{} lists no cuts", path.display());
+    }
+    Ok(cuts)
+}
+
+/// Video events of a CMX 3600 list, labeled with their `* FROM CLIP NAME:`
+/// comment or else their reel.
+fn parse_cmx(text: &str) -> Result<Vec<Cut>> {
+    let mut cuts: Vec<Cut> = Vec::new();
+    // Whether the last event line was a video one, which the comments after it describe.
+    let mut in_video_event = false;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("* FROM CLIP NAME:") {
+            if let Some(cut) = cuts.last_mut().filter(|_| in_video_event) {
+                let event = cut.label.split_whitespace().next().unwrap_or_default().to_string();
+                cut.label = format!("{} {}", event, name.trim());
+            }
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 || !fields[0].bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let timecodes = &fields[fields.len() - 4..];
+        if !timecodes.iter().all(|tc| is_timecode(tc)) {
+            anyhow::bail!("event {} has no source and record timecodes", fields[0]);
+        }
+        let track = fields[2];
+        in_video_event = track.contains('V') || track == "B";
+        if in_video_event {
+            cuts.push(Cut { at: timecodes[0].to_string(), label: format!("{} {}", fields[0], fields[1]) });
+        }
+    }
+    Ok(cuts)
+}
+
+/// Lines of `in,out,label`, the first skipped if its in point isn't a time.
+fn parse_csv(text: &str) -> Result<Vec<Cut>> {
+    let mut cuts = Vec::new();
+    let lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'));
+    for (n, (number, line)) in lines.enumerate() {
+        let mut fields = line.splitn(3, ',').map(|f| f.trim().trim_matches('"').trim());
+        let at = fields.next().unwrap_or_default();
+        let out = fields.next().unwrap_or_default();
+        let label = fields.next().unwrap_or_default();
+        if !is_time(at) || !(out.is_empty() || is_time(out)) {
+            if n == 0 {
+                continue;
+            }
+            anyhow::bail!("line {}: expected `in,out,label` with times like 00:01:02:03, 62.5 or 1:02.5", number + 1);
+        }
+        let label = match (label, out) {
+            ("", "") => at.to_string(),
+            ("", out) => format!("{}-{}", at, out),
+            (label, _) => label.to_string(),
+        };
+        cuts.push(Cut { at: at.to_string(), label });
+    }
+    Ok(cuts)
+}
+
+fn is_timecode(field: &str) -> bool {
+    let parts: Vec<&str> = field.split([':', ';']).collect();
+    parts.len() == 4 && parts.iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn is_time(field: &str) -> bool {
+    is_timecode(field) || clock_seconds(field).is_some()
+}
+
+/// Seconds of `SS`, `MM:SS` or `HH:MM:SS`, the seconds possibly fractional.
+fn clock_seconds(field: &str) -> Option<f64> {
+    let parts: Vec<&str> = field.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let (last, whole) = parts.split_last()?;
+    let seconds: f64 = last.parse().ok().filter(|s: &f64| s.is_finite() && *s >= 0.0)?;
+    whole.iter().try_fold(0.0, |total, part| Some(total * 60.0 + part.parse::<u32>().ok()? as f64))
+        .map(|minutes| minutes * 60.0 + seconds)
+}
@@ -0,0 +1,396 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Read};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::throttle;
+
+/// Set by the Ctrl-C handler; checked between files and while children run.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Process groups of the children running now. Each child leads a group of
+/// its own, out of reach of the terminal's signals, so they're killed before
+/// every exit.
+static GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// How often a running child is polled for exit or interruption.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Error returned when a run was cut short by Ctrl-C.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Error returned when a command ran past its time limit and was killed.
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {:.1}s", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Error returned when a file took longer than `--timeout` and the command
+/// running at the time was killed.
+#[derive(Debug)]
+pub struct DeadlineExceeded(pub Duration);
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gave up on the file after {:.1}s (--timeout)", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+thread_local! {
+    /// When the file this thread is working on must be done by, and the
+    /// time it was given.
+    static DEADLINE: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+/// Run `f`, failing every command it runs with `DeadlineExceeded` (killing
+/// it if it's running) once `limit` has passed.
+pub fn with_deadline<T>(limit: Option<Duration>, f: impl FnOnce() -> T) -> T {
+    let outer = DEADLINE.replace(limit.map(|limit| (Instant::now() + limit, limit)));
+    let result = f();
+    DEADLINE.set(outer);
+    result
+}
+
+/// Fail with `DeadlineExceeded` if this thread's deadline has passed.
+fn check_deadline() -> Result<()> {
+    match DEADLINE.get() {
+        Some((at, limit)) if Instant::now() >= at => Err(DeadlineExceeded(limit).into()),
+        _ => Ok(()),
+    }
+}
+
+/// Error returned when a command's program isn't installed (or isn't on PATH).
+#[derive(Debug)]
+pub struct ToolMissing(pub String);
+
+impl fmt::Display for ToolMissing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} not found; is it installed and on PATH?", self.0)
+    }
+}
+
+impl std::error::Error for ToolMissing {}
+
+/// What FFmpeg's stderr says went wrong, for sorting failures without
+/// reading their logs. Also an error of its own, for failures that only
+/// stderr explains (no frame came out, ffprobe couldn't open the file).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    /// No decoder (or demuxer) for the file's format.
+    CodecNotFound,
+    /// The file is damaged or truncated.
+    CorruptData,
+    PermissionDenied,
+    /// Reading it over the network (or a network share) failed.
+    ProtocolError,
+    /// Not from stderr: the file ran past `--timeout`, usually with FFmpeg
+    /// stuck seeking in it.
+    TimedOut,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Category::CodecNotFound => "codec not found",
+            Category::CorruptData => "corrupt data",
+            Category::PermissionDenied => "permission denied",
+            Category::ProtocolError => "protocol error",
+            Category::TimedOut => "timed out",
+        })
+    }
+}
+
+impl std::error::Error for Category {}
+
+/// Messages FFmpeg and ffprobe print for each category, matched
+/// case-insensitively; the first category with a match wins.
+const CATEGORY_MESSAGES: &[(Category, &[&str])] = &[
+    (Category::PermissionDenied, &["permission denied", "operation not permitted"]),
+    (Category::CodecNotFound, &[
+        "decoder not found", "unknown decoder", "no decoder for", "codec not currently supported",
+        "unsupported codec", "could not find codec parameters", "unknown format",
+    ]),
+    (Category::ProtocolError, &[
+        "protocol not found", "connection refused", "connection timed out", "connection reset",
+        "network is unreachable", "server returned", "http error", "stale file handle",
+    ]),
+    (Category::CorruptData, &[
+        "invalid data found", "moov atom not found", "corrupt", "error while decoding",
+        "invalid nal unit", "non-existing pps", "end of file", "truncat", "partial file",
+        "header missing", "error splitting the input",
+    ]),
+];
+
+/// The category of failure `stderr` describes, if it's one we know.
+pub fn classify(stderr: &str) -> Option<Category> {
+    let stderr = stderr.to_lowercase();
+    CATEGORY_MESSAGES.iter()
+        .find(|(_, messages)| messages.iter().any(|m| stderr.contains(m)))
+        .map(|&(category, _)| category)
+}
+
+/// The category of the first classified failure in an error's chain.
+pub fn category(err: &anyhow::Error) -> Option<Category> {
+    err.chain().find_map(|cause| match cause.downcast_ref::<CommandFailed>() {
+        Some(failed) => failed.category,
+        None if cause.is::<DeadlineExceeded>() => Some(Category::TimedOut),
+        None => cause.downcast_ref::<Category>().copied(),
+    })
+}
+
+/// Error returned by `run` when a command exits unsuccessfully.
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub program: String,
+    pub status: ExitStatus,
+    /// What its whole stderr says went wrong, if recognized.
+    pub category: Option<Category>,
+    /// The last few lines the command wrote to stderr.
+    pub stderr_tail: String,
+}
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} exited with {}", self.program, self.status)?;
+        if let Some(category) = self.category {
+            write!(f, " ({})", category)?;
+        }
+        if !self.stderr_tail.is_empty() {
+            write!(f, ": {}", self.stderr_tail)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// Number of stderr lines kept in `CommandFailed`.
+const STDERR_TAIL_LINES: usize = 5;
+
+/// The last `lines` non-empty lines of `stderr`.
+pub fn tail(stderr: &[u8], lines: usize) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let kept: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).collect();
+    kept[kept.len().saturating_sub(lines)..].join("\n")
+}
+
+/// Install the Ctrl-C (and SIGTERM and SIGHUP) handler. A second one exits
+/// immediately.
+pub fn install_interrupt_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            exit(130);
+        }
+        eprintln!("\nInterrupted, finishing up (press Ctrl-C again to force quit)...");
+    })
+    .with_context(|| "Failed to install Ctrl-C handler")
+}
+
+/// Kill every running child's process group, then exit with `code`.
+pub fn exit(code: i32) -> ! {
+    for &group in GROUPS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        kill_group(group);
+    }
+    std::process::exit(code)
+}
+
+/// A child's process group, tracked while it runs.
+struct Group(u32);
+
+impl Group {
+    fn track(id: u32) -> Group {
+        GROUPS.lock().unwrap_or_else(|e| e.into_inner()).push(id);
+        Group(id)
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        GROUPS.lock().unwrap_or_else(|e| e.into_inner()).retain(|&id| id != self.0);
+    }
+}
+
+/// Whether Ctrl-C has been pressed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Fail with `Interrupted` if Ctrl-C has been pressed.
+pub fn check_interrupted() -> Result<()> {
+    if interrupted() {
+        return Err(Interrupted.into());
+    }
+    Ok(())
+}
+
+/// `arg` quoted for a POSIX shell, when it needs to be.
+pub fn shell_quote(arg: &std::ffi::OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if plain { arg.into_owned() } else { format!("'{}'", arg.replace('\'', "'\\''")) }
+}
+
+/// `cmd` as a line that could be pasted into a POSIX shell.
+pub fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program()).chain(cmd.get_args()).map(shell_quote).collect::<Vec<_>>().join(" ")
+}
+
+/// Run a command to completion capturing its output, like `Command::output`,
+/// but kill the child as soon as Ctrl-C is pressed.
+pub fn output(cmd: &mut Command) -> Result<Output> {
+    output_with_timeout(cmd, None)
+}
+
+/// Like `output`, but fail with `CommandFailed` if the command exits unsuccessfully.
+pub fn run(cmd: &mut Command) -> Result<Output> {
+    run_with_timeout(cmd, None)
+}
+
+/// `run` with the time limit of `output_with_timeout`.
+pub fn run_with_timeout(cmd: &mut Command, timeout: Option<Duration>) -> Result<Output> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let output = output_with_timeout(cmd, timeout)?;
+    if !output.status.success() {
+        return Err(CommandFailed {
+            program,
+            status: output.status,
+            category: classify(&String::from_utf8_lossy(&output.stderr)),
+            stderr_tail: tail(&output.stderr, STDERR_TAIL_LINES),
+        }.into());
+    }
+    Ok(output)
+}
+
+/// Like `output`, but also kill the child with a `TimedOut` error if it runs
+/// longer than `timeout`.
+pub fn output_with_timeout(cmd: &mut Command, timeout: Option<Duration>) -> Result<Output> {
+    check_interrupted()?;
+    check_deadline()?;
+    if timeout.is_some_and(|t| t.is_zero()) {
+        return Err(TimedOut(Duration::ZERO).into());
+    }
+    let mut permit = throttle::start()?;
+    let started = Instant::now();
+    log::debug!("Running {}", command_line(cmd));
+
+    // In a process group of its own, so a kill reaches anything it starts too.
+    #[cfg(target_os = "linux")]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ToolMissing(cmd.get_program().to_string_lossy().into_owned()).into(),
+            _ => anyhow::Error::new(e),
+        })?;
+    let _group = Group::track(child.id());
+
+    // Drain both pipes on their own threads so a chatty child can't block on a full pipe.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        permit.poll(child.id());
+        if interrupted() {
+            kill(&mut child);
+            return Err(Interrupted.into());
+        }
+        if let Some(timeout) = timeout {
+            if started.elapsed() >= timeout {
+                kill(&mut child);
+                return Err(TimedOut(timeout).into());
+            }
+        }
+        if let Err(e) = check_deadline() {
+            kill(&mut child);
+            return Err(e);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    log::debug!(
+        "{} finished after {:.2}s ({}){}",
+        cmd.get_program().to_string_lossy(), started.elapsed().as_secs_f64(), status,
+        match String::from_utf8_lossy(&output.stderr).trim_end() {
+            "" => String::new(),
+            stderr => format!(", stderr:\n{}", stderr),
+        }
+    );
+    Ok(output)
+}
+
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Kill `child` and its process group, and reap it.
+fn kill(child: &mut Child) {
+    kill_group(child.id());
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// SIGKILL the process group led by `id`.
+fn kill_group(id: u32) {
+    // SAFETY: kill() only sends a signal; a group that's already gone just fails.
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::kill(-(id as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = id;
+}
+
+/// Whether an error (or anything in its context chain) is an `Interrupted`.
+pub fn is_interrupted(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<Interrupted>())
+}
+
+/// Whether an error (or anything in its context chain) is a `TimedOut`.
+pub fn is_timed_out(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<TimedOut>())
+}
+
+/// Whether an error (or anything in its context chain) is a `ToolMissing`.
+pub fn is_tool_missing(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<ToolMissing>())
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Filename words that mark a file as a sample or bonus feature.
+const DEFAULT_EXTRA_WORDS: &[&str] = &[
+    "sample",
+    "trailer",
+    "teaser",
+    "featurette",
+    "behindthescenes",
+    "deleted",
+    "interview",
+    "extras",
+];
+
+/// Folder names (as used by Plex/Jellyfin/Kodi) whose contents are extras.
+const DEFAULT_EXTRA_DIRS: &[&str] = &[
+    "extras",
+    "featurettes",
+    "behind the scenes",
+    "deleted scenes",
+    "interviews",
+    "scenes",
+    "shorts",
+    "trailers",
+    "samples",
+    "sample",
+    "other",
+];
+
+/// Heuristic for skipping samples, trailers and other extras in library runs.
+pub struct ExtrasFilter {
+    words: Vec<String>,
+    dirs: Vec<String>,
+}
+
+impl ExtrasFilter {
+    /// Build a filter; empty lists fall back to the built-in defaults.
+    pub fn new(words: &[String], dirs: &[String]) -> Self {
+        let pick = |custom: &[String], defaults: &[&str]| -> Vec<String> {
+            if custom.is_empty() {
+                defaults.iter().map(|s| s.to_string()).collect()
+            } else {
+                custom.iter().map(|s| s.to_lowercase()).collect()
+            }
+        };
+        ExtrasFilter {
+            words: pick(words, DEFAULT_EXTRA_WORDS),
+            dirs: pick(dirs, DEFAULT_EXTRA_DIRS),
+        }
+    }
+
+    /// Why `path` looks like an extra, if it does. Only folders below `root`
+    /// are considered, so pointing the tool straight at an Extras folder still
+    /// works; without a root (a list of files) only the filename is.
+    pub fn matches(&self, root: Option<&Path>, path: &Path) -> Option<String> {
+        let relative = root.and_then(|root| path.strip_prefix(root).ok());
+        if let Some(parent) = relative.and_then(Path::parent) {
+            for component in parent.components() {
+                let name = component.as_os_str().to_string_lossy().to_lowercase();
+                if self.dirs.contains(&name) {
+                    return Some(format!("inside extras folder '{}'", component.as_os_str().to_string_lossy()));
+                }
+            }
+        }
+
+        let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+        // Match whole words so "sample" doesn't catch "resampled".
+        stem.split(|c: char| !c.is_alphanumeric())
+            .find(|word| self.words.iter().any(|w| w == word))
+            .map(|word| format!("filename contains '{}'", word))
+    }
+}
+
+/// A glob pattern for paths below the input directory, compared without
+/// regard to case: `*` and `?` match within a name, `**` any number of
+/// directories, `[abc]` and `[a-z]` one listed character.
+///
+/// A pattern without a `/` matches names at any depth (`*.mkv`, `Samples`);
+/// one with a `/` matches from the directory it's relative to (`Shows/**/*.mp4`).
+/// In ignore files a trailing `/` matches only directories, and a leading `!`
+/// lets a later pattern bring back what an earlier one ignored.
+#[derive(Clone, Debug)]
+pub struct Glob {
+    segments: Vec<Vec<char>>,
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl Glob {
+    /// Parse a `--include`/`--exclude` pattern.
+    pub fn parse(pattern: &str) -> Result<Glob, String> {
+        let trimmed = pattern.trim();
+        if trimmed.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        let body = trimmed.trim_start_matches('/').trim_end_matches('/');
+        Ok(Glob {
+            segments: body.split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_lowercase().chars().collect())
+                .collect(),
+            anchored: trimmed.trim_end_matches('/').contains('/'),
+            dir_only: trimmed.ends_with('/'),
+            negated: false,
+        })
+    }
+
+    /// Parse one line of an ignore file; blank lines and `#` comments give `None`.
+    fn parse_ignore_line(line: &str) -> Option<Glob> {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        Glob::parse(pattern).ok().map(|glob| Glob { negated, ..glob })
+    }
+
+    /// Whether the pattern matches `relative`, a path below the pattern's directory.
+    fn matches(&self, relative: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let names: Vec<Vec<char>> = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy().to_lowercase().chars().collect())
+            .collect();
+        let names: Vec<&[char]> = names.iter().map(Vec::as_slice).collect();
+        if self.anchored {
+            match_segments(&self.segments, &names)
+        } else {
+            names.last().is_some_and(|name| self.segments.len() == 1 && match_name(&self.segments[0], name))
+        }
+    }
+}
+
+fn match_segments(pattern: &[Vec<char>], names: &[&[char]]) -> bool {
+    match pattern.split_first() {
+        None => names.is_empty(),
+        Some((first, rest)) if first.as_slice() == ['*', '*'] => {
+            (0..=names.len()).any(|skip| match_segments(rest, &names[skip..]))
+        }
+        Some((first, rest)) => names.split_first()
+            .is_some_and(|(name, tail)| match_name(first, name) && match_segments(rest, tail)),
+    }
+}
+
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_name(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_name(rest, &name[1..]),
+        Some(('[', rest)) => {
+            let Some(end) = rest.iter().position(|&c| c == ']') else {
+                return name.first() == Some(&'[') && match_name(rest, &name[1..]);
+            };
+            let Some(&c) = name.first() else { return false };
+            let class = &rest[..end];
+            let (negate, class) = match class.split_first() {
+                Some(('!' | '^', class)) => (true, class),
+                _ => (false, class),
+            };
+            let mut hit = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    hit |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    hit |= class[i] == c;
+                    i += 1;
+                }
+            }
+            hit != negate && match_name(&rest[end + 1..], &name[1..])
+        }
+        Some((&p, rest)) => name.first() == Some(&p) && match_name(rest, &name[1..]),
+    }
+}
+
+/// `--include`/`--exclude` patterns and per-directory ignore files, checked
+/// while walking the input directory.
+pub struct PathFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    /// Name of the ignore files to honor (say `.thumbnailerignore`).
+    ignore_file: Option<String>,
+    /// Each directory's ignore rules, read once.
+    ignores: HashMap<PathBuf, Vec<Glob>>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[Glob], exclude: &[Glob], ignore_file: Option<&str>) -> Self {
+        PathFilter {
+            include: include.to_vec(),
+            exclude: exclude.to_vec(),
+            ignore_file: ignore_file.map(str::to_string),
+            ignores: HashMap::new(),
+        }
+    }
+
+    /// Whether the walk of `root` should keep `path`. Excluded and ignored
+    /// directories aren't descended into; `--include` only picks files.
+    pub fn allows(&mut self, root: &Path, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else { return true };
+        if self.exclude.iter().any(|g| g.matches(relative, is_dir)) || self.ignored(root, relative, is_dir) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(|g| g.matches(relative, is_dir))
+    }
+
+    /// Whether the ignore files of `relative`'s directories ignore it; the
+    /// last matching rule, deepest directory last, decides.
+    fn ignored(&mut self, root: &Path, relative: &Path, is_dir: bool) -> bool {
+        let Some(name) = self.ignore_file.clone() else { return false };
+        let mut ignored = false;
+        let mut dir = root.to_path_buf();
+        let mut below = relative;
+        for component in relative.components() {
+            let rules = self.ignores.entry(dir.clone()).or_insert_with(|| {
+                fs::read_to_string(dir.join(&name))
+                    .map(|text| text.lines().filter_map(Glob::parse_ignore_line).collect())
+                    .unwrap_or_default()
+            });
+            if let Some(rule) = rules.iter().rev().find(|r| r.matches(below, is_dir)) {
+                ignored = !rule.negated;
+            }
+            dir.push(component);
+            below = below.strip_prefix(component).unwrap_or(below);
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extras() -> ExtrasFilter {
+        ExtrasFilter::new(&[], &[])
+    }
+
+    #[test]
+    fn extras_folders_count_only_below_the_root() {
+        let filter = extras();
+        let root = Path::new("/srv/Extras/Movies");
+        assert_eq!(filter.matches(Some(root), Path::new("/srv/Extras/Movies/Film (2001)/Film.mkv")), None);
+        assert_eq!(
+            filter.matches(Some(root), Path::new("/srv/Extras/Movies/Film (2001)/Trailers/Film.mkv")),
+            Some("inside extras folder 'Trailers'".to_string()),
+        );
+    }
+
+    #[test]
+    fn without_a_root_only_the_filename_counts() {
+        let filter = extras();
+        assert_eq!(filter.matches(None, Path::new("/srv/Extras/Film.mkv")), None);
+        assert_eq!(filter.matches(None, Path::new("/srv/Samples/Film.mkv")), None);
+        assert_eq!(
+            filter.matches(None, Path::new("/srv/Films/Film-sample.mkv")),
+            Some("filename contains 'sample'".to_string()),
+        );
+        assert_eq!(filter.matches(None, Path::new("/srv/Films/Resampled.mkv")), None);
+    }
+
+    fn glob_matches(pattern: &str, path: &str) -> bool {
+        Glob::parse(pattern).unwrap().matches(Path::new(path), false)
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(glob_matches("Shows/**/*.mp4", "Shows/a.mp4"));
+        assert!(glob_matches("Shows/**/*.mp4", "Shows/One/Season 1/a.mp4"));
+        assert!(!glob_matches("Shows/**/*.mp4", "Films/a.mp4"));
+        assert!(glob_matches("**/extras/*", "a/b/Extras/c.mkv"));
+    }
+
+    #[test]
+    fn star_and_question_mark_stay_within_a_name() {
+        assert!(glob_matches("Shows/*.mp4", "Shows/a.mp4"));
+        assert!(!glob_matches("Shows/*.mp4", "Shows/One/a.mp4"));
+        assert!(!glob_matches("Shows*", "Shows/a.mp4"));
+        assert!(glob_matches("ep?.mkv", "EP1.mkv"));
+        assert!(!glob_matches("ep?.mkv", "ep10.mkv"));
+    }
+
+    #[test]
+    fn character_classes_match_one_listed_character() {
+        assert!(glob_matches("ep[0-9].mkv", "ep7.mkv"));
+        assert!(!glob_matches("ep[0-9].mkv", "epx.mkv"));
+        assert!(glob_matches("[abc]*.mkv", "b.mkv"));
+        assert!(!glob_matches("[!abc]*.mkv", "b.mkv"));
+        assert!(glob_matches("[^abc]*.mkv", "d.mkv"));
+        assert!(glob_matches("a[b", "a[b"));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored() {
+        assert!(glob_matches("*.mkv", "a/b/c.mkv"));
+        assert!(glob_matches("Samples", "Films/Samples"));
+        assert!(glob_matches("Films/*.mkv", "Films/c.mkv"));
+        assert!(!glob_matches("Films/*.mkv", "Old/Films/c.mkv"));
+        assert!(glob_matches("/Samples", "Samples"));
+        assert!(!glob_matches("/Samples", "Films/Samples"));
+    }
+
+    #[test]
+    fn trailing_slash_matches_only_directories() {
+        let glob = Glob::parse("samples/").unwrap();
+        assert!(glob.matches(Path::new("Films/Samples"), true));
+        assert!(!glob.matches(Path::new("Films/Samples"), false));
+        assert!(Glob::parse("samples").unwrap().matches(Path::new("Films/Samples"), false));
+    }
+
+    #[test]
+    fn later_ignore_rules_and_deeper_files_win() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(".ignore"), "*.mkv\n!keep*.mkv\n# a comment\n\nold/\n").unwrap();
+        fs::create_dir(root.path().join("sub")).unwrap();
+        fs::write(root.path().join("sub/.ignore"), "!again.mkv\nkeep-not.mkv\n").unwrap();
+        let mut filter = PathFilter::new(&[], &[], Some(".ignore"));
+        let allows = |filter: &mut PathFilter, path: &str, is_dir| filter.allows(root.path(), &root.path().join(path), is_dir);
+
+        assert!(!allows(&mut filter, "a.mkv", false));
+        assert!(allows(&mut filter, "keep.mkv", false));
+        assert!(allows(&mut filter, "a.mp4", false));
+        assert!(!allows(&mut filter, "old", true));
+        assert!(allows(&mut filter, "old", false));
+        assert!(allows(&mut filter, "sub/again.mkv", false));
+        assert!(!allows(&mut filter, "sub/keep-not.mkv", false));
+        assert!(allows(&mut filter, "sub/keep.mkv", false));
+
+        fs::write(root.path().join(".ignore"), "!keep.mkv\n*.mkv\n").unwrap();
+        let mut filter = PathFilter::new(&[], &[], Some(".ignore"));
+        assert!(!allows(&mut filter, "keep.mkv", false));
+    }
+
+    #[test]
+    fn include_only_picks_files() {
+        let root = Path::new("/videos");
+        let mut filter = PathFilter::new(&[Glob::parse("*.mkv").unwrap()], &[Glob::parse("Samples").unwrap()], None);
+        assert!(filter.allows(root, Path::new("/videos/Films"), true));
+        assert!(filter.allows(root, Path::new("/videos/Films/a.mkv"), false));
+        assert!(!filter.allows(root, Path::new("/videos/Films/a.mp4"), false));
+        assert!(!filter.allows(root, Path::new("/videos/Samples"), true));
+    }
+}
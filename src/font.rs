@@ -0,0 +1,156 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::Result;
+use crate::Options;
+
+/// Fonts known to look right, tried before the font directories are searched.
+const PREFERRED: &[&str] = &[
+    // Linux
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/freefont/FreeSans.ttf",
+    // macOS
+    "/System/Library/Fonts/SFNSDisplay.ttf",
+    "/Library/Fonts/Arial.ttf",
+    // Windows
+    "C:/Windows/Fonts/arial.ttf",
+    "C:/Windows/Fonts/segoeui.ttf",
+];
+
+/// How many directory levels below each font directory are searched.
+const MAX_DEPTH: usize = 4;
+
+/// Name parts of fonts that can't show ordinary text (or look odd doing it).
+const UNSUITABLE: &[&str] = &["emoji", "symbol", "dingbat", "icon", "wingding", "webding", "math"];
+
+/// Name parts of styles other than a regular weight.
+const STYLES: &[&str] = &["bold", "italic", "oblique", "light", "thin", "black", "condensed", "narrow", "mono"];
+
+/// Fail early when `--font-file` doesn't point at a file.
+pub fn check_file(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        anyhow::bail!("Font file {} not found", path.display());
+    }
+    Ok(())
+}
+
+/// Font for FFmpeg's drawtext: `--font-file` when given, otherwise a system
+/// font, otherwise the fallback built in with the `embedded-font` feature.
+pub fn path(opts: &Options) -> Result<String> {
+    if let Some(file) = &opts.font_file {
+        return Ok(file.to_string_lossy().into_owned());
+    }
+    static SYSTEM: OnceLock<Option<PathBuf>> = OnceLock::new();
+    match SYSTEM.get_or_init(find_system_font) {
+        Some(found) => Ok(found.to_string_lossy().into_owned()),
+        None => embedded(),
+    }
+}
+
+/// A known font if there is one, else the most regular-looking sans font
+/// found in the platform's font directories.
+fn find_system_font() -> Option<PathBuf> {
+    if let Some(known) = PREFERRED.iter().map(PathBuf::from).find(|p| p.is_file()) {
+        return Some(known);
+    }
+    let mut fonts = Vec::new();
+    for dir in font_dirs() {
+        collect_fonts(&dir, MAX_DEPTH, &mut fonts);
+    }
+    fonts.into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_lowercase();
+            if UNSUITABLE.iter().any(|u| name.contains(u)) {
+                return None;
+            }
+            let sans = name.contains("sans") || name.contains("arial") || name.contains("helvet");
+            let styled = STYLES.iter().any(|s| name.contains(s)) || (name.contains("serif") && !sans);
+            Some(((!sans, styled), path))
+        })
+        .min()
+        .map(|(_, path)| path)
+}
+
+/// Where the platform keeps fonts, system-wide and per user.
+fn font_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "macos") {
+        dirs.extend(["/System/Library/Fonts", "/Library/Fonts"].map(PathBuf::from));
+        dirs.extend(home.map(|h| h.join("Library/Fonts")));
+    } else if cfg!(windows) {
+        let windir = env::var_os("WINDIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("C:/Windows"));
+        dirs.push(windir.join("Fonts"));
+        dirs.extend(env::var_os("LOCALAPPDATA").map(|d| PathBuf::from(d).join("Microsoft/Windows/Fonts")));
+    } else {
+        let data_home = env::var_os("XDG_DATA_HOME").map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|h| h.join(".local/share")));
+        dirs.extend(data_home.map(|d| d.join("fonts")));
+        dirs.extend(home.map(|h| h.join(".fonts")));
+        let data_dirs = env::var("XDG_DATA_DIRS").unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+        dirs.extend(data_dirs.split(':').filter(|d| !d.is_empty()).map(|d| Path::new(d).join("fonts")));
+    }
+    dirs
+}
+
+/// TrueType and OpenType files under `dir`, up to `depth` levels down.
+fn collect_fonts(dir: &Path, depth: usize, fonts: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                collect_fonts(&path, depth - 1, fonts);
+            }
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("ttf") || e.eq_ignore_ascii_case("otf")) {
+            fonts.push(path);
+        }
+    }
+}
+
+/// The built-in DejaVu Sans, written out once so FFmpeg can read it: into
+/// the user's cache directory, where no one else can put a file in its
+/// place, or else a file of this run's own in the temp directory.
+#[cfg(feature = "embedded-font")]
+fn embedded() -> Result<String> {
+    use anyhow::Context;
+
+    static FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+    static WRITTEN: OnceLock<PathBuf> = OnceLock::new();
+    if let Some(path) = WRITTEN.get() {
+        return Ok(path.to_string_lossy().into_owned());
+    }
+
+    let path = match crate::config::user_cache_dir() {
+        Some(dir) => {
+            let path = dir.join(format!("DejaVuSans-{}.ttf", env!("CARGO_PKG_VERSION")));
+            if fs::read(&path).ok().as_deref() != Some(FONT) {
+                fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+                // Written under a private name first, so another run never sees half a font.
+                let mut partial = tempfile::NamedTempFile::new_in(&dir)
+                    .with_context(|| format!("Failed to write the built-in font to {}", dir.display()))?;
+                std::io::Write::write_all(&mut partial, FONT)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| partial.persist(&path).map_err(anyhow::Error::from))
+                    .with_context(|| format!("Failed to write the built-in font to {}", path.display()))?;
+            }
+            path
+        }
+        None => {
+            let mut file = tempfile::Builder::new().prefix("thumbnailer-font-").suffix(".ttf").tempfile()
+                .context("Failed to write the built-in font")?;
+            std::io::Write::write_all(&mut file, FONT).context("Failed to write the built-in font")?;
+            file.into_temp_path().keep().context("Failed to write the built-in font")?
+        }
+    };
+    Ok(WRITTEN.get_or_init(|| path).to_string_lossy().into_owned())
+}
+
+#[cfg(not(feature = "embedded-font"))]
+fn embedded() -> Result<String> {
+    anyhow::bail!(
+        "No usable font found for drawtext; install one (such as DejaVu Sans), pass --font-file, \
+         or build with the embedded-font feature"
+    )
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::mosaic::{self, Job, Sheet};
+use crate::naming;
+use crate::{Options, Outcome};
+
+/// Extract the `count` frames a sheet would show into `out` (default:
+/// `<file name>_frames/` next to the video), without composing a sheet.
+///
+/// Frames are picked like a sheet's tiles (black ones retried later) and
+/// written as `<file name>_<NNN>.jpg`, or `.png` for the PNG frame formats.
+/// Tiles with no decodable frame are left out rather than written as blanks.
+pub fn run(video: &Path, out: Option<&Path>, count: usize, opts: &Options) -> Result<Outcome> {
+    if count == 0 {
+        anyhow::bail!("--count must be at least 1");
+    }
+    let video_path = video.to_str().with_context(|| format!("{} isn't a valid UTF-8 path", video.display()))?;
+    let out = out.map_or_else(|| naming::frames_dir(video), Path::to_path_buf);
+    fs::create_dir_all(&out).with_context(|| format!("Failed to create {}", out.display()))?;
+    // The lock (and sandbox) go with the first frame's path.
+    let mut job = match Job::open(video_path, &naming::frame_path(&out, video, 0, opts.frame_format.extension()), opts) {
+        Ok(job) => job,
+        Err(e) => return mosaic::skipped(e),
+    };
+    // Each frame on its own, as a one-tile sheet would have it.
+    job.scale_tiles(&Sheet::grid(1, 1, 1), opts);
+    job.check_disk_space(count)?;
+
+    let tiles = mosaic::extract_tiles(&mut job, count, opts)?;
+    let mut written = 0;
+    for (i, tile) in tiles.iter().enumerate() {
+        if tile.blank {
+            continue;
+        }
+        let path = naming::frame_path(&out, video, i, job.frame_extension());
+        fs::copy(job.tile_path(i), &path).with_context(|| format!("Failed to write {}", path.display()))?;
+        written += 1;
+    }
+    log::info!("Wrote {} frames of {} to {}", written, video.display(), out.display());
+    Ok(job.finish(tiles))
+}
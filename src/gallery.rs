@@ -0,0 +1,123 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::html;
+use crate::retry::RetryPolicy;
+use crate::text;
+use crate::Created;
+
+/// Gallery-specific styling, on top of `html::STYLE`.
+const GALLERY_STYLE: &str = "\
+#controls { margin-bottom: 1em; }
+#controls input { width: 20em; }
+#grid { display: flex; flex-wrap: wrap; gap: 1em; }
+.card { width: 320px; border: 1px solid #ccc; padding: 0.5em; }
+.card img { width: 100%; }
+.card .name { font-weight: bold; word-break: break-all; }
+.card .meta { color: #666; font-size: 0.9em; }
+";
+
+/// Sorting and filtering, done client-side on the cards' data attributes.
+const GALLERY_SCRIPT: &str = "\
+const grid = document.getElementById('grid');
+const cards = Array.from(grid.children);
+function update() {
+  const query = document.getElementById('search').value.toLowerCase();
+  const key = document.getElementById('sort').value;
+  cards.sort((a, b) => (key === 'name' || key === 'codec')
+    ? a.dataset[key].localeCompare(b.dataset[key])
+    : parseFloat(b.dataset[key]) - parseFloat(a.dataset[key]));
+  for (const card of cards) {
+    card.hidden = query !== '' && !card.dataset.search.includes(query);
+    grid.appendChild(card);
+  }
+}
+document.getElementById('search').addEventListener('input', update);
+document.getElementById('sort').addEventListener('change', update);
+update();
+";
+
+/// Write a static gallery of `sheets` to `dir/index.html`, each sheet
+/// linking to its video.
+///
+/// Videos are linked directly, or under `base_url` when the library is
+/// served from somewhere else; paths below `root` keep their folders there.
+pub fn write(dir: &Path, sheets: &[Created], root: Option<&Path>, base_url: Option<&str>, retry: &RetryPolicy) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create gallery directory {}", dir.display()))?;
+
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Thumbnails</title>\n<style>\n{}{}</style>\n</head>\n<body>\n",
+        html::STYLE,
+        GALLERY_STYLE,
+    );
+    page.push_str(concat!(
+        "<div id=\"controls\">\n",
+        "<input id=\"search\" type=\"search\" placeholder=\"Search name, codec, resolution\">\n",
+        "<select id=\"sort\">",
+        "<option value=\"name\">Name</option>",
+        "<option value=\"duration\">Longest</option>",
+        "<option value=\"size\">Largest file</option>",
+        "<option value=\"pixels\">Highest resolution</option>",
+        "<option value=\"codec\">Codec</option>",
+        "</select>\n</div>\n<div id=\"grid\">\n",
+    ));
+
+    for sheet in sheets {
+        let name = sheet.video.file_name().unwrap_or_default().to_string_lossy();
+        let size = retry.io(format_args!("stat {}", sheet.video.display()), || fs::metadata(&sheet.video))
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+        let duration = sheet.info.duration().map(|(d, _)| d).unwrap_or(0.0);
+        let resolution = sheet.info.resolution();
+        let pixels = sheet.info.display_size().map(|(w, h)| w as u64 * h as u64).unwrap_or(0);
+        let codec = sheet.info.video_stream().map(|s| s.codec_name.as_str()).unwrap_or_default();
+        let link = match base_url {
+            Some(base) => {
+                let relative = root.and_then(|r| sheet.video.strip_prefix(r).ok())
+                    .unwrap_or_else(|| Path::new(sheet.video.file_name().unwrap_or_default()));
+                format!(
+                    "{}/{}",
+                    base.trim_end_matches('/'),
+                    html::url_encode(&relative.to_string_lossy().replace('\\', "/"))
+                )
+            }
+            None => html::href(&sheet.video, dir),
+        };
+        let search = format!("{} {} {}", name, codec, resolution).to_lowercase();
+
+        let _ = writeln!(
+            page,
+            concat!(
+                "<div class=\"card\" data-name=\"{name}\" data-duration=\"{duration}\" data-size=\"{size}\" ",
+                "data-pixels=\"{pixels}\" data-codec=\"{codec}\" data-search=\"{search}\">",
+                "<a href=\"{link}\"><img src=\"{img}\" alt=\"\" loading=\"lazy\"{placeholder}></a>",
+                "<div class=\"name\"><a href=\"{link}\">{name}</a></div>",
+                "<div class=\"meta\">{time} &middot; {resolution} &middot; {codec} &middot; {mb:.2} MB</div></div>",
+            ),
+            name = html::escape(&name),
+            duration = duration,
+            size = size,
+            pixels = pixels,
+            codec = html::escape(codec),
+            search = html::escape(&search),
+            link = html::escape(&link),
+            img = html::escape(&html::href(&sheet.output, dir)),
+            // The dominant color shows until the sheet loads.
+            placeholder = sheet.colors.first()
+                .map(|swatch| format!(" style=\"background-color:{}\"", swatch.color))
+                .unwrap_or_default(),
+            time = text::format_time(duration),
+            resolution = html::escape(&resolution),
+            mb = size as f64 / 1_000_000.0,
+        );
+    }
+    let _ = write!(page, "</div>\n<script>\n{}</script>\n</body>\n</html>\n", GALLERY_SCRIPT);
+
+    let index = dir.join("index.html");
+    fs::write(&index, page).with_context(|| format!("Failed to write gallery {}", index.display()))?;
+    log::info!("Wrote gallery of {} sheets to {}", sheets.len(), index.display());
+    Ok(())
+}
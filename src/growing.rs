@@ -0,0 +1,64 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+use crate::retry::RetryPolicy;
+
+/// Times a growing file is put back in the queue before it's left for a later run.
+pub const MAX_DEFERRALS: u32 = 3;
+
+/// Seconds left off the end of a recording still being written
+/// (`--recordings`), whose last packets may not all be there yet.
+pub const RECORDING_TAIL: f64 = 10.0;
+
+/// Whether `path` looks like it's still being written: modified less than
+/// `settle` ago, and its size or modification time changed while waiting
+/// out the rest of `settle`.
+pub fn is_growing(path: &Path, settle: Duration, retry: &RetryPolicy) -> Result<bool> {
+    if settle.is_zero() {
+        return Ok(false);
+    }
+    let stat = || retry.io(format_args!("stat {}", path.display()), || {
+        let meta = fs::metadata(path)?;
+        Ok((meta.len(), meta.modified()?))
+    });
+    let before = stat()?;
+    // A modification time in the future (clock skew) counts as just now.
+    let age = SystemTime::now().duration_since(before.1).unwrap_or_default();
+    if age >= settle {
+        return Ok(false);
+    }
+    thread::sleep(settle - age);
+    Ok(stat()? != before)
+}
+
+/// `.<sheet name>.recording` next to `sheet`, holding the size of the
+/// recording it was made from while that was still being written.
+fn marker_path(sheet: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(sheet.file_name().unwrap_or_default());
+    name.push(".recording");
+    sheet.with_file_name(name)
+}
+
+/// The size its recording had when `sheet` was made from part of it;
+/// `None` for a sheet of a whole file.
+pub fn partial_size(sheet: &Path) -> Option<u64> {
+    fs::read_to_string(marker_path(sheet)).ok()?.trim().parse().ok()
+}
+
+/// Note that `sheet` shows only the part of `video` written so far (when
+/// `partial`), or, once the recording is finished, that it shows it all.
+pub fn mark(sheet: &Path, video: &Path, partial: bool) -> Result<()> {
+    let marker = marker_path(sheet);
+    if !partial {
+        return match fs::remove_file(&marker) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e).with_context(|| format!("Failed to remove {}", marker.display())),
+            _ => Ok(()),
+        };
+    }
+    let size = fs::metadata(video).with_context(|| format!("Failed to stat {}", video.display()))?.len();
+    fs::write(&marker, format!("{}\n", size)).with_context(|| format!("Failed to write {}", marker.display()))
+}
@@ -0,0 +1,279 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use anyhow::{Context, Result};
+use eframe::egui;
+use crate::exec;
+use crate::mosaic::{self, Job, Sheet, Tile};
+use crate::naming;
+use crate::sandbox;
+use crate::{Options, Outcome};
+
+/// Largest grid dimension offered by the sliders.
+const MAX_GRID: usize = 10;
+
+/// Work for the render thread.
+enum Request {
+    /// Compose a preview of the sheet.
+    Render(Sheet),
+    /// Write the sheet to the output.
+    Save(Sheet),
+}
+
+/// What the render thread reports back.
+enum Response {
+    Preview(Sheet, egui::ColorImage),
+    Saved,
+    Failed(String),
+}
+
+/// Preview `video`'s sheet in a window where the grid, spacing and overlay
+/// can be adjusted, and write it to the usual output when the user saves.
+///
+/// Sheets are composed on a separate thread, so the window stays responsive
+/// while FFmpeg works; settings changed in the meantime replace any render
+/// that hasn't started yet.
+pub fn run(video: &Path, opts: &Options) -> Result<Outcome> {
+    let output_image = naming::output_path(video, None, opts.output_dir.as_deref());
+    let Some(video_path) = video.to_str() else {
+        let message = format!("{} isn't a valid UTF-8 path", video.display());
+        show_error(&message)?;
+        anyhow::bail!(message);
+    };
+    let mut job = match Job::open(video_path, &output_image, opts) {
+        Ok(job) => job,
+        Err(e) => return mosaic::skipped(e),
+    };
+
+    let (rows, cols, frames) = job.fit_grid(opts.rows, opts.cols, opts.frames, opts);
+    let sheet = opts.sheet(rows, cols, frames);
+
+    // The tiles of the saved sheet, once there is one.
+    let mut saved = None;
+    thread::scope(|scope| {
+        let (requests, request_rx) = mpsc::channel();
+        let (response_tx, responses) = mpsc::channel();
+        let job = &mut job;
+        let saved = &mut saved;
+        let title = format!("thumbnailer - {}", video.display());
+
+        eframe::run_native(&title, eframe::NativeOptions::default(), Box::new(move |cc| {
+            let ctx = cc.egui_ctx.clone();
+            // The thread ends once the window (and with it `requests`) is gone.
+            scope.spawn(move || render_loop(job, opts, request_rx, response_tx, ctx, saved));
+            let _ = requests.send(Request::Render(sheet));
+            Ok(Box::new(PreviewApp {
+                sheet,
+                requested: sheet,
+                busy: true,
+                status: "Rendering...".to_string(),
+                texture: None,
+                requests,
+                responses,
+            }))
+        }))
+        .map_err(|e| anyhow::anyhow!("Failed to open the preview window: {}", e))
+    })?;
+
+    Ok(match saved {
+        Some(tiles) => job.finish(tiles),
+        None => Outcome::Skipped("closed without saving".to_string()),
+    })
+}
+
+/// Show `message` in a window of its own, for when there's nothing to preview.
+fn show_error(message: &str) -> Result<()> {
+    let message = message.to_string();
+    eframe::run_native("thumbnailer", eframe::NativeOptions::default(), Box::new(|_| Ok(Box::new(ErrorApp(message)))))
+        .map_err(|e| anyhow::anyhow!("Failed to open the preview window: {}", e))
+}
+
+/// Serve render and save requests until the window closes.
+fn render_loop(
+    job: &mut Job,
+    opts: &Options,
+    requests: Receiver<Request>,
+    responses: Sender<Response>,
+    ctx: egui::Context,
+    saved: &mut Option<Vec<Tile>>,
+) {
+    let preview = job.temp_path().join("preview.jpg");
+    let probe_warnings = job.warnings.len();
+    // Warnings up to here are about the frames; later ones about the last render.
+    let mut extract_warnings = probe_warnings;
+    let mut extracted = None;
+    let mut tiles = Vec::new();
+    let mut rendered = None;
+
+    while let Ok(mut request) = requests.recv() {
+        // Only the newest request matters.
+        while let Ok(next) = requests.try_recv() {
+            request = next;
+        }
+        let sheet = match request {
+            Request::Render(sheet) | Request::Save(sheet) => sheet,
+        };
+
+        let result = (|| -> Result<Response> {
+            if rendered != Some(sheet) {
+                let width = job.tile_width();
+                job.scale_tiles(&sheet, opts);
+                if extracted != Some(sheet.frames) || job.tile_width() != width {
+                    job.check_disk_space(sheet.frames)?;
+                    job.warnings.truncate(probe_warnings);
+                    tiles = mosaic::extract_tiles(job, sheet.frames, opts)?;
+                    extract_warnings = job.warnings.len();
+                    extracted = Some(sheet.frames);
+                }
+                rendered = None;
+                job.warnings.truncate(extract_warnings);
+                mosaic::compose_sheet(job, &sheet, &tiles, &preview, opts)?;
+                rendered = Some(sheet);
+            }
+            match request {
+                Request::Render(_) => Ok(Response::Preview(sheet, load_image(job, &preview)?)),
+                Request::Save(_) => {
+                    fs::copy(&preview, &job.output_image)
+                        .with_context(|| format!("Failed to write {}", job.output_image.display()))?;
+                    *saved = Some(tiles.clone());
+                    Ok(Response::Saved)
+                }
+            }
+        })();
+
+        let response = result.unwrap_or_else(|e| Response::Failed(format!("{:#}", e)));
+        if responses.send(response).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+    }
+}
+
+/// Decode an image into pixels egui can display, via FFmpeg's PPM output.
+fn load_image(job: &Job, image: &Path) -> Result<egui::ColorImage> {
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-v", "error", "-i"])
+        .arg(image)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "ppm", "-"]))
+        .with_context(|| format!("Failed to decode {}", image.display()))?;
+    parse_ppm(&output.stdout).with_context(|| format!("Failed to decode {}", image.display()))
+}
+
+/// Parse a binary (P6) PPM with 8-bit samples, as FFmpeg writes it.
+fn parse_ppm(data: &[u8]) -> Result<egui::ColorImage> {
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while data.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            anyhow::bail!("truncated PPM header");
+        }
+        fields.push(std::str::from_utf8(&data[start..pos])?);
+    }
+    // A single whitespace byte separates the header from the pixels.
+    pos += 1;
+
+    if fields[0] != "P6" || fields[3] != "255" {
+        anyhow::bail!("unsupported PPM format {} (max {})", fields[0], fields[3]);
+    }
+    let width: usize = fields[1].parse()?;
+    let height: usize = fields[2].parse()?;
+    let pixels = data.get(pos..pos + width * height * 3).context("truncated PPM data")?;
+    Ok(egui::ColorImage::from_rgb([width, height], pixels))
+}
+
+struct PreviewApp {
+    /// Settings as currently shown in the controls.
+    sheet: Sheet,
+    /// Settings of the most recent request to the render thread.
+    requested: Sheet,
+    busy: bool,
+    status: String,
+    texture: Option<egui::TextureHandle>,
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+}
+
+impl eframe::App for PreviewApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(response) = self.responses.try_recv() {
+            self.busy = false;
+            match response {
+                Response::Preview(sheet, image) => {
+                    self.texture = Some(ctx.load_texture("sheet", image, egui::TextureOptions::LINEAR));
+                    self.status = format!("{} x {} sheet", sheet.cols, sheet.rows);
+                }
+                Response::Saved => self.status = "Saved".to_string(),
+                Response::Failed(message) => self.status = message,
+            }
+        }
+
+        egui::SidePanel::left("settings").show(ctx, |ui| {
+            ui.heading("Grid");
+            ui.add(egui::Slider::new(&mut self.sheet.cols, 1..=MAX_GRID).text("columns"));
+            ui.add(egui::Slider::new(&mut self.sheet.rows, 1..=MAX_GRID).text("rows"));
+
+            ui.heading("Layout");
+            ui.add(egui::Slider::new(&mut self.sheet.spacing, 0..=64).text("gutter (px)"));
+            ui.add(egui::Slider::new(&mut self.sheet.margin, 0..=64).text("margin (px)"));
+
+            ui.heading("Overlay");
+            ui.checkbox(&mut self.sheet.overlay, "Show file details");
+            ui.add_enabled(
+                self.sheet.overlay,
+                egui::Slider::new(&mut self.sheet.font_size, 12..=192).text("font size"),
+            );
+
+            ui.separator();
+            if ui.add_enabled(!self.busy, egui::Button::new("Save")).clicked() {
+                self.send(Request::Save(self.sheet));
+                self.status = "Saving...".to_string();
+            }
+            ui.label(&self.status);
+        });
+
+        // A full grid is what the sliders mean; keep the tile count in step.
+        self.sheet.frames = self.sheet.rows * self.sheet.cols;
+        if self.sheet != self.requested {
+            self.requested = self.sheet;
+            self.send(Request::Render(self.sheet));
+            self.status = "Rendering...".to_string();
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(texture) = &self.texture {
+                ui.centered_and_justified(|ui| {
+                    ui.add(egui::Image::new(texture).shrink_to_fit());
+                });
+            }
+        });
+    }
+}
+
+impl PreviewApp {
+    fn send(&mut self, request: Request) {
+        self.busy = true;
+        if self.requests.send(request).is_err() {
+            self.busy = false;
+            self.status = "The render thread stopped".to_string();
+        }
+    }
+}
+
+/// A window saying why there's no preview.
+struct ErrorApp(String);
+
+impl eframe::App for ErrorApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.centered_and_justified(|ui| ui.label(&self.0));
+        });
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::{Component, Path};
+
+/// Escape text for use in HTML content and double-quoted attributes.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Link target for `path` from a page in `base`: a relative URL where one
+/// exists, a `file://` URL otherwise. Percent-encoded, not yet HTML-escaped.
+pub fn href(path: &Path, base: &Path) -> String {
+    let absolute = |p: &Path| p.canonicalize().unwrap_or_else(|_| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf()));
+    let base = if base.as_os_str().is_empty() { Path::new(".") } else { base };
+    let (path, base) = (absolute(path), absolute(base));
+
+    match relative_path(&path, &base) {
+        Some(relative) => url_encode(&relative),
+        None => {
+            let path = path.to_string_lossy().replace('\\', "/");
+            let slash = if path.starts_with('/') { "" } else { "/" };
+            format!("file://{}{}", slash, url_encode(&path))
+        }
+    }
+}
+
+/// `path` relative to the directory `base`, `/`-separated, or `None` if they
+/// don't share a root (say, different Windows drives).
+fn relative_path(path: &Path, base: &Path) -> Option<String> {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    if path.first() != base.first() {
+        return None;
+    }
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), base.len() - common)
+        .chain(path[common..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()))
+        .collect();
+    Some(parts.join("/"))
+}
+
+/// Percent-encode everything but unreserved characters and `/`.
+pub fn url_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Undo percent-encoding (and `+` for space) in a query string value.
+pub fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Shared page styling for the generated reports.
+pub const STYLE: &str = "\
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f3f3f3; }
+td.num { text-align: right; }
+img.sheet { max-width: 480px; }
+";
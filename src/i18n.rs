@@ -0,0 +1,57 @@
+use clap::ValueEnum;
+
+/// Language of the text drawn on sheets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    En,
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+    Pl,
+    Pt,
+    Sv,
+}
+
+/// The words used in a sheet's overlay.
+#[derive(Debug)]
+pub struct Labels {
+    pub file: &'static str,
+    pub size: &'static str,
+    pub resolution: &'static str,
+    pub duration: &'static str,
+    pub video: &'static str,
+    pub audio: &'static str,
+    /// Abbreviation for megabytes.
+    pub megabytes: &'static str,
+    pub decimal_separator: char,
+}
+
+impl Lang {
+    pub fn labels(self) -> &'static Labels {
+        match self {
+            Lang::En => &Labels { file: "File", size: "Size", resolution: "Resolution", duration: "Duration", video: "Video", audio: "Audio", megabytes: "MB", decimal_separator: '.' },
+            Lang::De => &Labels { file: "Datei", size: "Größe", resolution: "Auflösung", duration: "Dauer", video: "Video", audio: "Audio", megabytes: "MB", decimal_separator: ',' },
+            Lang::Es => &Labels { file: "Archivo", size: "Tamaño", resolution: "Resolución", duration: "Duración", video: "Vídeo", audio: "Audio", megabytes: "MB", decimal_separator: ',' },
+            Lang::Fr => &Labels { file: "Fichier", size: "Taille", resolution: "Résolution", duration: "Durée", video: "Vidéo", audio: "Audio", megabytes: "Mo", decimal_separator: ',' },
+            Lang::It => &Labels { file: "File", size: "Dimensione", resolution: "Risoluzione", duration: "Durata", video: "Video", audio: "Audio", megabytes: "MB", decimal_separator: ',' },
+            Lang::Nl => &Labels { file: "Bestand", size: "Grootte", resolution: "Resolutie", duration: "Duur", video: "Video", audio: "Audio", megabytes: "MB", decimal_separator: ',' },
+            Lang::Pl => &Labels { file: "Plik", size: "Rozmiar", resolution: "Rozdzielczość", duration: "Czas", video: "Wideo", audio: "Dźwięk", megabytes: "MB", decimal_separator: ',' },
+            Lang::Pt => &Labels { file: "Arquivo", size: "Tamanho", resolution: "Resolução", duration: "Duração", video: "Vídeo", audio: "Áudio", megabytes: "MB", decimal_separator: ',' },
+            Lang::Sv => &Labels { file: "Fil", size: "Storlek", resolution: "Upplösning", duration: "Längd", video: "Video", audio: "Ljud", megabytes: "MB", decimal_separator: ',' },
+        }
+    }
+}
+
+impl Labels {
+    /// `value` with `decimals` digits after this language's decimal separator.
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value);
+        if self.decimal_separator == '.' {
+            formatted
+        } else {
+            formatted.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use crate::dedupe::FrameHash;
+
+/// How processing a file last went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Created,
+    Failed,
+}
+
+/// A processed file, as recorded in the index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// The sheet written, when one was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<PathBuf>,
+    pub status: Status,
+    /// `Options::fingerprint` of the run that processed it.
+    pub options: String,
+    /// Version of the program that processed it.
+    pub version: String,
+    /// When it was processed, in local time (RFC 3339).
+    pub at: String,
+    /// Perceptual hashes of the sheet's frames, for `dedupe`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<FrameHash>,
+}
+
+impl Entry {
+    pub fn new(path: &Path, sheet: Option<&Path>, status: Status, options: &str) -> Self {
+        Entry {
+            path: path.to_path_buf(),
+            sheet: sheet.map(Path::to_path_buf),
+            status,
+            options: options.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            at: Local::now().to_rfc3339(),
+            frames: Vec::new(),
+        }
+    }
+}
+
+/// Read the index; a missing file is an empty index.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read index {}", path.display())),
+    };
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse index {}", path.display()))
+}
+
+/// Record `entries` in the index, replacing earlier entries for the same
+/// files. A lock beside the index is held throughout, so runs finishing
+/// together (a scheduled sweep and a manual run) don't drop each other's.
+pub fn update(path: &Path, entries: Vec<Entry>) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let lock_path = sibling(path, "lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    lock.lock().with_context(|| format!("Failed to lock {}", lock_path.display()))?;
+
+    let mut index = read(path)?;
+    let updated: HashSet<&Path> = entries.iter().map(|new| new.path.as_path()).collect();
+    index.retain(|old| !updated.contains(old.path.as_path()));
+    index.extend(entries);
+    index.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let json = serde_json::to_string_pretty(&index)? + "\n";
+    // Written aside under a name of its own and renamed, so an interrupted
+    // run never leaves half an index.
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut partial = tempfile::Builder::new()
+        .prefix(&sibling(path, "").file_name().unwrap_or_default())
+        .suffix(".partial")
+        .tempfile_in(dir)
+        .with_context(|| format!("Failed to write index {}", path.display()))?;
+    partial.write_all(json.as_bytes())
+        .map_err(anyhow::Error::from)
+        .and_then(|_| partial.persist(path).map(|_| ()).map_err(anyhow::Error::from))
+        .with_context(|| format!("Failed to write index {}", path.display()))
+}
+
+/// `<index file name>.<suffix>`, next to the index.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// An index field a `--where` condition can test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Options,
+    Status,
+    Version,
+}
+
+/// A `--where` condition: `FIELD = VALUE` or `FIELD != VALUE`, where
+/// `current` stands for this run's options or version.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    field: Field,
+    equal: bool,
+    value: String,
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Condition, String> {
+        let (field, equal, value) = if let Some((field, value)) = s.split_once("!=") {
+            (field, false, value)
+        } else if let Some((field, value)) = s.split_once('=') {
+            (field, true, value)
+        } else {
+            return Err(format!("expected FIELD = VALUE or FIELD != VALUE, got '{}'", s));
+        };
+        let field = match field.trim() {
+            "options" => Field::Options,
+            "status" => Field::Status,
+            "version" => Field::Version,
+            other => return Err(format!("unknown field '{}' (expected options, status or version)", other)),
+        };
+        let value = value.trim().trim_matches(['\'', '"']).to_string();
+        if field == Field::Status && !matches!(value.as_str(), "created" | "failed") {
+            return Err(format!("unknown status '{}' (expected created or failed)", value));
+        }
+        Ok(Condition { field, equal, value })
+    }
+
+    /// Whether `entry` meets the condition, for a run with fingerprint `options`.
+    pub fn matches(&self, entry: &Entry, options: &str) -> bool {
+        let (actual, current) = match self.field {
+            Field::Options => (entry.options.as_str(), options),
+            Field::Version => (entry.version.as_str(), env!("CARGO_PKG_VERSION")),
+            Field::Status => (match entry.status {
+                Status::Created => "created",
+                Status::Failed => "failed",
+            }, ""),
+        };
+        let wanted = if self.value == "current" && self.field != Field::Status { current } else { self.value.as_str() };
+        (actual == wanted) == self.equal
+    }
+}
@@ -0,0 +1,35 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use crate::config::Config;
+
+/// One entry of a `--jobs` file: a video, where its sheet goes, and how it
+/// differs from the run's settings.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    pub source: PathBuf,
+    /// Where the sheet is written, instead of the usual name.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    /// Text drawn across the sheet instead of the file name, size and resolution.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Settings as in a config file (`rows`, `timecode`, `theme`, ...).
+    #[serde(default)]
+    pub options: Config,
+}
+
+/// Read a JSON array of job specs from `path` (standard input for `-`).
+pub fn read(path: &Path) -> Result<Vec<Spec>> {
+    let json = if path == Path::new("-") {
+        let mut json = String::new();
+        io::stdin().read_to_string(&mut json).context("Failed to read jobs from standard input")?;
+        json
+    } else {
+        fs::read_to_string(path).with_context(|| format!("Failed to read jobs file {}", path.display()))?
+    };
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse jobs file {}", path.display()))
+}
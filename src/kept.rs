@@ -0,0 +1,98 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::mosaic::{Job, Tile};
+use crate::planner::Segment;
+use crate::naming;
+use crate::Options;
+
+/// Name of the record listing a video's kept frames.
+const RECORD: &str = "frames.json";
+
+/// What kept frames were extracted from; they're only reused for the same.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Key {
+    /// Size and modification time (nanoseconds since the epoch) of the video.
+    size: u64,
+    modified: u128,
+    frame_format: String,
+    /// Width the frames were scaled to, if they were.
+    #[serde(default)]
+    width: Option<u32>,
+    /// Segments the tiles were sampled from.
+    segments: Vec<Segment>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    #[serde(flatten)]
+    key: Key,
+    /// One per tile, in order.
+    files: Vec<String>,
+    tiles: Vec<Tile>,
+}
+
+fn key(job: &Job, total_frames: usize, opts: &Options) -> Result<Key> {
+    let path = Path::new(&job.video_path);
+    let meta = opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(path))?;
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    Ok(Key {
+        size: meta.len(),
+        modified,
+        frame_format: job.frame_extension().to_string(),
+        width: job.tile_width(),
+        segments: job.segments(total_frames, opts),
+    })
+}
+
+/// Put the frames kept under `dir` in place as the job's tiles, if they were
+/// taken from this same video for these same segments.
+pub fn load(dir: &Path, job: &Job, total_frames: usize, opts: &Options) -> Result<Option<Vec<Tile>>> {
+    let kept = naming::video_dir(dir, Path::new(&job.video_path));
+    let json = match fs::read_to_string(kept.join(RECORD)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", kept.join(RECORD).display())),
+    };
+    // A record from another version or a half-written one is just ignored.
+    let Ok(record) = serde_json::from_str::<Record>(&json) else {
+        return Ok(None);
+    };
+    let complete = record.files.len() == record.tiles.len() && record.files.iter().all(|f| kept.join(f).is_file());
+    if !complete || record.key != key(job, total_frames, opts)? {
+        return Ok(None);
+    }
+    for (i, file) in record.files.iter().enumerate() {
+        fs::copy(kept.join(file), job.tile_path(i))?;
+    }
+    Ok(Some(record.tiles))
+}
+
+/// Keep the job's tiles under `dir`, named by position and time, replacing
+/// any frames kept of the video before.
+pub fn save(dir: &Path, job: &Job, tiles: &[Tile], opts: &Options) -> Result<()> {
+    let kept = naming::video_dir(dir, Path::new(&job.video_path));
+    let record = Record {
+        key: key(job, tiles.len(), opts)?,
+        files: tiles.iter().enumerate()
+            .map(|(i, tile)| {
+                let ms = (tile.shown.max(0.0) * 1000.0).round() as u64;
+                format!(
+                    "{:03}_{:02}-{:02}-{:02}.{:03}.{}",
+                    i + 1, ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000, job.frame_extension()
+                )
+            })
+            .collect(),
+        tiles: tiles.to_vec(),
+    };
+    let _ = fs::remove_dir_all(&kept);
+    fs::create_dir_all(&kept)?;
+    for (i, file) in record.files.iter().enumerate() {
+        fs::copy(job.tile_path(i), kept.join(file))?;
+    }
+    fs::write(kept.join(RECORD), serde_json::to_string_pretty(&record)? + "\n")?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+/// An exclusive, cross-process lock on an output path, held until dropped.
+///
+/// Backed by an OS file lock on `.<name>.lock` next to the output, so two
+/// instances (say cron and a manual run) never write the same file at once,
+/// and a crashed instance can't leave a stale lock behind.
+pub struct OutputLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Try to lock `target`. Returns `None` if another process holds the lock.
+    pub fn try_acquire(target: &Path) -> Result<Option<OutputLock>> {
+        let path = lock_path(target);
+        loop {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Ok(None),
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+                }
+            }
+
+            // The previous holder removes the file on release; if that
+            // happened between our open and lock, we locked an orphaned file
+            // and have to start over.
+            if !still_linked(&file, &path) {
+                continue;
+            }
+
+            let _ = file.set_len(0);
+            let _ = writeln!(file, "{}", std::process::id());
+            return Ok(Some(OutputLock { file, path }));
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+fn lock_path(target: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".lock");
+    target.with_file_name(name)
+}
+
+#[cfg(unix)]
+fn still_linked(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(held), Ok(current)) => held.dev() == current.dev() && held.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn still_linked(_file: &File, _path: &Path) -> bool {
+    // Open files can't be deleted on Windows, so the race can't happen.
+    true
+}
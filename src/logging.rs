@@ -0,0 +1,85 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use chrono::Local;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use crate::progress;
+
+/// Whether standard output carries the image (`-o -`), so progress goes to
+/// standard error instead.
+static IMAGE_ON_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// Progress on standard output (or standard error once it's reserved for
+/// the image or `--json`), problems on standard error, and everything down to debug
+/// level, timestamped, in the log file if there is one.
+struct Logger {
+    console: LevelFilter,
+    file: Option<Mutex<File>>,
+    /// Most detailed level written to the file.
+    file_level: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.console || (self.file.is_some() && metadata.level() <= self.file_level)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.console {
+            let line = match record.level() {
+                Level::Error | Level::Info => format!("{}", record.args()),
+                Level::Warn => format!("Warning: {}", record.args()),
+                Level::Debug | Level::Trace => format!("[{}] {}", record.level().as_str().to_lowercase(), record.args()),
+            };
+            progress::suspend(|| {
+                if record.level() == Level::Info && !IMAGE_ON_STDOUT.load(Ordering::Relaxed) {
+                    println!("{}", line);
+                } else {
+                    eprintln!("{}", line);
+                }
+            });
+        }
+        if let Some(file) = self.file.as_ref().filter(|_| record.level() <= self.file_level) {
+            let mut file = file.lock().unwrap();
+            let _ = writeln!(file, "{} {:<5} {}", Local::now().format("%Y-%m-%d %H:%M:%S%.3f"), record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stdout().flush();
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Set up logging: warnings and errors only with `verbosity` below 0 (`-q`),
+/// progress at 0, debug details (commands run, their stderr, timings) at 1
+/// (`-v`) and everything at 2 (`-vv`). A `log_file` is appended to, at
+/// debug level or more.
+pub fn init(verbosity: i8, log_file: Option<&Path>) -> Result<()> {
+    let console = match verbosity {
+        ..=-1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let file = match log_file {
+        Some(path) => Some(Mutex::new(OpenOptions::new().create(true).append(true).open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?)),
+        None => None,
+    };
+    let file_level = console.max(LevelFilter::Debug);
+    log::set_max_level(if file.is_some() { file_level } else { console });
+    log::set_boxed_logger(Box::new(Logger { console, file, file_level })).context("Logging was already set up")?;
+    Ok(())
+}
+
+/// Send progress to standard error from now on, leaving standard output to
+/// the image (or the `--json` records).
+pub fn reserve_stdout() {
+    IMAGE_ON_STDOUT.store(true, Ordering::Relaxed);
+}
@@ -1,218 +1,1303 @@
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use anyhow::{Context, Result};
-use tempfile::tempdir;
-use std::env;
-
-/// Find a default system font path for use in FFmpeg's drawtext.
-fn find_default_font() -> Option<String> {
-    let font_paths = vec![
-        // Linux
-        "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
-        "/usr/share/fonts/truetype/freefont/FreeSans.ttf",
-        // macOS
-        "/System/Library/Fonts/SFNSDisplay.ttf",
-        "/Library/Fonts/Arial.ttf",
-        // Windows
-        "C:/Windows/Fonts/arial.ttf",
-        "C:/Windows/Fonts/segoeui.ttf",
-    ];
-
-    font_paths.into_iter().find(|path| fs::metadata(path).is_ok()).map(String::from)
-}
-
-/// Get file size in megabytes.
-fn get_filesize_mb(path: &str) -> Result<f64> {
-    let size_bytes = fs::metadata(path)?.len();
-    Ok(size_bytes as f64 / 1_000_000.0)
-}
-
-/// Get video duration in seconds using ffprobe.
-fn get_video_duration(video_path: &str) -> Result<f64> {
-    let output = Command::new("ffprobe")
-        .args([
-            "-v", "error",
-            "-show_entries", "format=duration",
-            "-of", "default=noprint_wrappers=1:nokey=1",
-            video_path,
-        ])
-        .output()
-        .with_context(|| "Failed to get video duration with ffprobe")?;
-
-    let duration_str = String::from_utf8_lossy(&output.stdout);
-    let duration: f64 = duration_str.trim().parse()
-        .with_context(|| format!("Failed to parse video duration: {}", duration_str))?;
-
-    Ok(duration)
-}
-
-/// Check if the frame extracted at a timestamp is black using FFmpeg's blackframe filter.
-fn is_black_frame(video_path: &str, timestamp: f64) -> Result<bool> {
-    let output = Command::new("ffmpeg")
-        .args([
-            "-ss", &format!("{:.3}", timestamp),
-            "-i", video_path,
-            "-t", "1",
-            "-vf", "blackframe=99:32",
-            "-an",
-            "-f", "null",
-            "-",
-        ])
-        .output()
-        .with_context(|| "Failed to run ffmpeg for blackframe detection")?;
-
-    Ok(String::from_utf8_lossy(&output.stderr).contains("blackframe"))
-}
-
-/// Escape text for FFmpeg drawtext filter.
-fn escape_ffmpeg_drawtext_text(text: &str) -> String {
-    text.replace('\\', "\\\\")
-        .replace(':', "\\:")
-        .replace('(', "\\(")
-        .replace(')', "\\)")
-}
-
-/// Main entry point.
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        eprintln!("Please provide a file or directory.");
-        std::process::exit(1);
-    }
-
-    let input_path = Path::new(&args[1]);
-
-    if input_path.is_dir() {
-        for entry in fs::read_dir(input_path)? {
-            let path = entry?.path();
-            if path.is_file() && is_video_file(&path) {
-                let output_image = path.with_extension("jpg");
-                println!("Processing: {}", path.display());
-                if let Err(e) = create_thumbnail_mosaic(
-                    path.to_str().unwrap(),
-                    output_image.to_str().unwrap(),
-                    3, 3, 9
-                ) {
-                    eprintln!("Failed to process {}: {}", path.display(), e);
-                }
-            }
-        }
-    } else if input_path.is_file() {
-        let output_image = format!("{}_tn.jpg", input_path.to_string_lossy());
-        println!("Processing: {}", input_path.display());
-        create_thumbnail_mosaic(&args[1], &output_image, 3, 3, 9)?;
-    } else {
-        eprintln!("Invalid input path.");
-        std::process::exit(1);
-    }
-
-    Ok(())
-}
-
-/// Check if a file is a video based on extension.
-fn is_video_file(path: &Path) -> bool {
-    matches!(
-        path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase().as_str(),
-        "mp4" | "mov" | "avi" | "mkv" | "webm" | "m4v" | "wmv" | "mpg" | "mpeg" | "ts"
-    )
-}
-
-/// Create a thumbnail mosaic from video and overlay metadata text.
-fn create_thumbnail_mosaic(
-    video_path: &str,
-    output_image: &str,
-    rows: usize,
-    cols: usize,
-    total_frames: usize,
-) -> Result<()> {
-    let temp_dir = tempdir()?;
-    let duration = get_video_duration(video_path)?;
-    let interval = duration / total_frames as f64;
-
-    // === Extract evenly spaced thumbnails with retry ===
-    for i in 0..total_frames {
-        let mut timestamp = interval * i as f64;
-        let max_attempts = 5;
-        let mut attempt = 0;
-
-        let output_file = temp_dir.path().join(format!("thumb_{:03}.jpg", i));
-        let output_file_str = output_file.to_str().unwrap();
-
-        loop {
-            Command::new("ffmpeg")
-                .args([
-                    "-ss", &format!("{:.3}", timestamp),
-                    "-i", video_path,
-                    "-frames:v", "1",
-                    "-q:v", "2",
-                    "-y",
-                    output_file_str,
-                ])
-                .status()
-                .with_context(|| format!("Failed to extract thumbnail at {:.3}s", timestamp))?;
-
-            if !is_black_frame(video_path, timestamp)? || attempt >= max_attempts {
-                break;
-            }
-
-            attempt += 1;
-            timestamp += 2.0; // Try 2s later
-        }
-    }
-
-    // === Create mosaic ===
-    let mosaic_temp = temp_dir.path().join("mosaic_raw.jpg");
-    let input_pattern = temp_dir.path().join("thumb_%03d.jpg");
-
-    Command::new("ffmpeg")
-        .args([
-            "-f", "image2",
-            "-i", input_pattern.to_str().unwrap(),
-            "-filter_complex",
-            &format!("tile={}x{}", cols, rows),
-            "-y",
-            mosaic_temp.to_str().unwrap(),
-        ])
-        .status()
-        .with_context(|| "Failed to create mosaic with ffmpeg")?;
-
-    // === Metadata ===
-    let ffprobe_output = Command::new("ffprobe")
-        .args([
-            "-v", "error",
-            "-select_streams", "v:0",
-            "-show_entries", "stream=width,height",
-            "-of", "csv=s=x:p=0",
-            video_path,
-        ])
-        .output()
-        .with_context(|| "Failed to run ffprobe for resolution")?;
-
-    let resolution = String::from_utf8_lossy(&ffprobe_output.stdout).trim().to_string();
-    let filename = Path::new(video_path).file_name().unwrap().to_string_lossy();
-    let font_path = find_default_font().ok_or_else(|| anyhow::anyhow!("No usable system font found for drawtext"))?;
-    let filesize_mb = get_filesize_mb(video_path)?;
-
-    // === Text Overlay ===
-    let raw_text = format!("File:{} Size:{:.2} MB Resolution:({})", filename, filesize_mb, resolution);
-    let escaped_text = escape_ffmpeg_drawtext_text(&raw_text);
-    let escaped_font_path = escape_ffmpeg_drawtext_text(&font_path);
-
-    let drawtext_filter = format!(
-        "drawtext=fontfile='{}':text='{}':x=10:y=10:fontsize=96:fontcolor=white:box=1:boxcolor=black@0.5",
-        escaped_font_path, escaped_text
-    );
-
-    Command::new("ffmpeg")
-        .args([
-            "-i", mosaic_temp.to_str().unwrap(),
-            "-vf", &drawtext_filter,
-            "-y", output_image,
-        ])
-        .status()
-        .with_context(|| "Failed to overlay text on mosaic")?;
-
-    Ok(())
-}
+mod archive;
+mod audio;
+mod blurhash;
+mod budget;
+mod capabilities;
+mod cli;
+mod combine;
+mod compare;
+mod config;
+mod dedupe;
+mod detect;
+mod diskspace;
+mod edl;
+mod exec;
+mod filter;
+mod font;
+mod frames;
+mod gallery;
+mod growing;
+mod html;
+mod i18n;
+mod index;
+mod jobs;
+mod kept;
+#[cfg(feature = "gui")]
+mod gui;
+mod lock;
+mod logging;
+mod markdown;
+mod mosaic;
+mod naming;
+mod palette;
+mod pick;
+mod planner;
+mod post;
+mod poster;
+mod preview;
+mod probe;
+mod progress;
+mod quarantine;
+mod report;
+mod results;
+mod retry;
+mod sandbox;
+mod schedule;
+mod serve;
+mod stats;
+mod storyboard;
+mod text;
+mod theme;
+mod thumbnails;
+mod throttle;
+mod timecode;
+mod verify;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use chrono::Local;
+use clap::{CommandFactory, Parser};
+use crate::cli::{Cli, Command};
+use crate::config::{Config, DirConfigs};
+use crate::dedupe::FrameHash;
+use crate::detect::{SeenFiles, Symlinks};
+use crate::filter::{ExtrasFilter, Glob, PathFilter};
+use crate::i18n::Labels;
+use crate::index::Condition;
+use crate::lock::OutputLock;
+use crate::mosaic::{create_thumbnail_mosaic, Artifact, Chroma, CornerLabel, FrameFormat, GridMode, Layout, SeekMode, Sheet, TextFallback, Tile};
+use crate::palette::Swatch;
+use crate::naming::{Existing, OutputClaims, SafeNames, Template};
+use crate::post::PostProcess;
+use crate::poster::SquareCrop;
+use crate::preview::PreviewAudio;
+use crate::probe::MediaInfo;
+use crate::retry::{PersistentIoError, RetryPolicy};
+use crate::sandbox::Sandbox;
+use crate::schedule::Schedule;
+use crate::theme::{Banner, Theme, ThemeOverrides};
+use crate::thumbnails::TrackLayout;
+use walkdir::WalkDir;
+
+/// What happened to a single input file.
+pub enum Outcome {
+    Created(Box<Created>),
+    Skipped(String),
+}
+
+/// A mosaic that was written, and what went into it.
+pub struct Created {
+    pub video: PathBuf,
+    pub output: PathBuf,
+    pub info: MediaInfo,
+    pub tiles: Vec<Tile>,
+    /// Things worth a second look.
+    pub warnings: Vec<String>,
+    /// Dominant colors of the poster frame, most common first (`--colors`).
+    pub colors: Vec<Swatch>,
+    /// Blurhash of the poster frame (`--blurhash`).
+    pub blurhash: Option<String>,
+    /// Perceptual hashes of the tiles, when they're recorded in the index.
+    pub frame_hashes: Vec<FrameHash>,
+}
+
+/// Settings that apply to every file processed in a run.
+#[derive(Clone)]
+pub struct Options {
+    /// Directory levels searched for videos, counting the input directory's
+    /// own files as 1.
+    pub max_depth: usize,
+    /// Patterns files in directory mode must match (any of), if given.
+    pub include: Vec<Glob>,
+    /// Patterns of files and directories left out in directory mode.
+    pub exclude: Vec<Glob>,
+    /// Name of per-directory ignore files to honor.
+    pub ignore_file: Option<String>,
+    /// Extensions of the files taken as videos (lowercase, without dots).
+    pub extensions: Vec<String>,
+    /// Look inside files without a video extension in directory mode.
+    pub probe_all: bool,
+    /// Whether directory mode takes, follows or skips symbolic links.
+    pub symlinks: Symlinks,
+    /// Directory sheets are written under instead of next to their videos.
+    pub output_dir: Option<PathBuf>,
+    /// Write every sheet directly in `output_dir`, not mirroring the input tree.
+    pub flat: bool,
+    /// How sheet names are made safe for any filesystem, if they are.
+    pub safe_names: Option<SafeNames>,
+    /// Where failed files' job files and errors are kept.
+    pub debug_dir: Option<PathBuf>,
+    /// Where jobs' scratch directories are made, instead of the system's.
+    pub temp_dir: Option<PathBuf>,
+    /// Leave jobs' scratch directories behind, for debugging.
+    pub keep_temp: bool,
+    /// Where extracted tiles are kept, and reused from on later runs.
+    pub keep_frames: Option<PathBuf>,
+    /// Print what would be done instead of doing it.
+    pub dry_run: bool,
+    /// What happens to sheets that are already there.
+    pub existing: Existing,
+    /// Sheet files still being written as far as they've got (`--recordings`).
+    pub recordings: bool,
+    /// This file is a recording still being written; its last
+    /// `growing::RECORDING_TAIL` seconds are left off.
+    pub partial: bool,
+    /// Encoder of preview videos: libx264, or FFmpeg's own mpeg4 when it
+    /// lacks that.
+    pub preview_codec: &'static str,
+    /// What the run asked for that this system can't do, with the fallback
+    /// taken; reported at the end.
+    pub degraded: Vec<String>,
+    /// The config's `[overrides.*]` sections, for `for_media`.
+    pub media_overrides: BTreeMap<String, Config>,
+    /// The stats file batch runs add to; `None` with `--no-stats`.
+    pub stats: Option<PathBuf>,
+    /// Template sheets are named after instead of `<video>_tn.jpg`.
+    pub name_template: Option<Template>,
+    /// Overlay text instead of the file name, size and resolution (from a job spec).
+    pub title: Option<String>,
+    /// Grid of a sheet, before it's re-shaped for portrait video.
+    pub rows: usize,
+    pub cols: usize,
+    /// Tiles on a sheet; fewer than `rows * cols` leaves the last cells empty.
+    pub frames: usize,
+    /// Frame numbers to show instead of evenly spaced frames.
+    pub at_frames: Option<Vec<u64>>,
+    /// Cuts of an edit decision list to show instead of evenly spaced frames.
+    pub edl: Option<Vec<edl::Cut>>,
+    pub sandbox: bool,
+    /// Whether the grid is `rows x cols` or sized by each video's duration.
+    pub grid: GridMode,
+    /// Fewest and most rows and columns of a grid sized by duration.
+    pub auto_grid_sides: (usize, usize),
+    /// Whether tiles go in a grid or a single row or column.
+    pub layout: Layout,
+    /// Height of a filmstrip's frames (width, for a vertical one).
+    pub strip_size: u32,
+    /// Use the requested grid as-is, even for portrait video.
+    pub keep_grid: bool,
+    /// How long a file must go unmodified before it's taken as fully written.
+    pub settle: Duration,
+    /// Time allowed per tile before it's abandoned; `None` waits forever.
+    pub frame_timeout: Option<Duration>,
+    /// Time allowed per file before it fails; `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// Where the first segment starts, in seconds; `None` picks a small
+    /// fraction of the duration.
+    pub start_offset: Option<f64>,
+    /// Time ranges (in seconds) never sampled.
+    pub skip: Vec<(f64, f64)>,
+    /// Retries for stat/read/probe operations that fail transiently.
+    pub retry: RetryPolicy,
+    /// Longest filename (in characters) shown in the overlay before it's shortened.
+    pub max_name_length: usize,
+    /// Words used in the overlay.
+    pub labels: &'static Labels,
+    /// Colors and decoration of the sheets.
+    pub theme: Theme,
+    /// Burn each tile's SMPTE timecode into it.
+    pub timecode: bool,
+    /// Burn each tile's time in the video into its corner.
+    pub tile_timestamps: bool,
+    /// Burn each tile's number and position in the video into its corner.
+    pub tile_index: bool,
+    /// Where and how the time and number go.
+    pub tile_label: CornerLabel,
+    /// Write the title of the chapter each tile falls in under it.
+    pub chapter_titles: bool,
+    /// Draw a bar under the grid marking where each tile was sampled.
+    pub timeline: bool,
+    /// Add a footer listing the audio tracks and their loudness.
+    pub audio_summary: bool,
+    /// Draw no text at all, so no font is needed.
+    pub no_text: bool,
+    /// Write the text to a transparent PNG next to the sheet instead of onto it.
+    pub text_layer: bool,
+    /// Font for all text drawn on sheets, instead of a discovered one.
+    pub font_file: Option<PathBuf>,
+    /// Extra attempts at each composition pass (tiling, labels, overlay).
+    pub pass_retries: u32,
+    /// What to do when drawing text keeps failing.
+    pub text_fallback: TextFallback,
+    /// Widths of scaled copies written next to each sheet and poster.
+    pub sizes: Vec<u32>,
+    /// Width frames are scaled to as they're extracted.
+    pub tile_width: Option<u32>,
+    /// Width sheets are kept within, by extracting narrower frames.
+    pub max_width: Option<u32>,
+    /// How the thumbnail track (`--outputs thumbnails`) is taken and tiled.
+    pub thumbnail_track: TrackLayout,
+    /// Steps run on each sheet once it's composed.
+    pub post: Vec<Arc<dyn PostProcess>>,
+    /// What's made from each video's frames (in `--outputs` order).
+    pub outputs: Vec<Artifact>,
+    /// Dominant colors worked out for each video's poster frame (0 = none).
+    pub colors: usize,
+    /// Work out a blurhash of each video's poster frame.
+    pub blurhash: bool,
+    /// Hash the tiles for the index (`--index`), for `dedupe`.
+    pub hash_frames: bool,
+    /// Side of the square poster written next to each sheet, if any.
+    pub square: Option<u32>,
+    pub square_crop: SquareCrop,
+    /// How extracted frames are kept until they're tiled.
+    pub frame_format: FrameFormat,
+    pub seek: SeekMode,
+    /// FFmpeg `-hwaccel` method frames are decoded with, if any.
+    pub hwaccel: Option<String>,
+    /// Chroma subsampling of the sheets' JPEGs.
+    pub chroma: Chroma,
+    /// Audio track of the preview video, and whether its loudness is normalized.
+    pub preview_audio: PreviewAudio,
+    pub preview_loudnorm: bool,
+    /// Most bytes a sheet may take; its quality is lowered until it fits.
+    pub target_size: Option<u64>,
+}
+
+impl Options {
+    /// A short hash of the settings that shape a sheet, recorded in the index
+    /// so sheets made with other settings can be found.
+    pub fn fingerprint(&self) -> String {
+        let settings = format!("{:?}", (
+            (self.rows, self.cols, self.frames, &self.at_frames, &self.edl, self.grid, self.auto_grid_sides, self.keep_grid),
+            (self.layout, self.strip_size),
+            (self.start_offset, &self.skip, self.max_name_length, self.labels),
+            (&self.theme, self.timecode, self.chapter_titles, self.timeline, self.audio_summary),
+            (self.tile_timestamps, self.tile_index, self.tile_label),
+            (self.no_text, self.text_layer, &self.font_file, &self.sizes, self.square, self.square_crop),
+            (self.tile_width, self.max_width, self.thumbnail_track),
+            (self.frame_format, self.seek, self.chroma, &self.outputs, self.target_size),
+            (&self.preview_audio, self.preview_loudnorm),
+            &self.media_overrides,
+        ));
+        format!("{:016x}", naming::fnv1a(settings.as_bytes()))
+    }
+
+    /// These settings with a directory's config (or a job's options) applied on top.
+    pub fn with_overrides(&self, config: &Config) -> Result<Options> {
+        let rows = config.rows.unwrap_or(self.rows);
+        let cols = config.cols.unwrap_or(self.cols);
+        let regrid = config.rows.is_some() || config.cols.is_some();
+        let frames = config.frames.or((!regrid).then_some(self.frames));
+        let (rows, cols, frames) = grid(rows, cols, frames, self.shot_count())?;
+        let grid_mode = match config.grid {
+            Some(mode) => mode,
+            None if regrid || config.frames.is_some() => GridMode::Fixed,
+            None => self.grid,
+        };
+        let theme = match config.theme.base {
+            Some(base) => base.theme(),
+            None => self.theme.clone(),
+        };
+        Ok(Options {
+            rows,
+            cols,
+            frames,
+            grid: grid_mode,
+            timecode: config.timecode.unwrap_or(self.timecode),
+            tile_timestamps: config.tile_timestamps.unwrap_or(self.tile_timestamps),
+            tile_index: config.tile_index.unwrap_or(self.tile_index),
+            chapter_titles: config.chapter_titles.unwrap_or(self.chapter_titles),
+            timeline: config.timeline.unwrap_or(self.timeline),
+            frame_format: config.frame_format.unwrap_or(self.frame_format),
+            seek: config.seek.unwrap_or(self.seek),
+            hwaccel: config.hwaccel.clone().or(self.hwaccel.clone()),
+            chroma: config.chroma.unwrap_or(self.chroma),
+            tile_width: config.tile_width.or(self.tile_width),
+            max_width: config.max_width.or(self.max_width),
+            font_file: config.font_file.clone().or(self.font_file.clone()),
+            name_template: config.name_template.clone().or(self.name_template.clone()),
+            theme: config.theme.apply(theme),
+            media_overrides: {
+                let mut overrides = self.media_overrides.clone();
+                config::merge_overrides(&mut overrides, &config.overrides);
+                overrides
+            },
+            ..self.clone()
+        })
+    }
+
+    /// These settings with the `[overrides.*]` sections that apply to
+    /// `video` on top, and their keys; `None` when none do.
+    pub fn for_media(&self, video: &Path, info: &MediaInfo) -> Result<Option<(Options, Vec<String>)>> {
+        let mut merged = Config::default();
+        let mut applied = Vec::new();
+        for key in config::media_keys(video, info) {
+            for (name, config) in self.media_overrides.iter().filter(|(name, _)| name.eq_ignore_ascii_case(&key)) {
+                merged.merge(config);
+                applied.push(name.clone());
+            }
+        }
+        if applied.is_empty() {
+            return Ok(None);
+        }
+        let opts = self.with_overrides(&merged)
+            .with_context(|| format!("[overrides.{}]", applied.join("], [overrides.")))?;
+        Ok(Some((opts, applied)))
+    }
+
+    /// The first file written for a sheet at `sheet`, which tells whether
+    /// it's already there: the sheet itself, unless it's left out.
+    pub fn primary_output(&self, sheet: &Path) -> PathBuf {
+        self.outputs.first().map_or(sheet.to_path_buf(), |a| a.path(sheet))
+    }
+
+    /// Tiles a shot list (`--at-frames` or `--edl`) asks for, if there is one.
+    fn shot_count(&self) -> Option<usize> {
+        self.at_frames.as_ref().map(Vec::len).or(self.edl.as_ref().map(Vec::len))
+    }
+
+    /// A `rows x cols` sheet of `frames` tiles laid out per the theme and,
+    /// for a filmstrip, scaled to `strip_size`.
+    pub fn sheet(&self, rows: usize, cols: usize, frames: usize) -> Sheet {
+        let sheet = Sheet::grid(rows, cols, frames);
+        let sheet = match self.layout {
+            Layout::Grid | Layout::Hero => sheet,
+            Layout::Filmstrip => sheet.filmstrip(false, self.strip_size),
+            Layout::VerticalFilmstrip => sheet.filmstrip(true, self.strip_size),
+        };
+        Sheet { spacing: self.theme.spacing, margin: self.theme.margin, ..sheet }
+    }
+}
+
+/// Check a grid and settle its tile count (`rows * cols` unless given), adding
+/// rows when a shot list needs more tiles than the grid has.
+fn grid(rows: usize, cols: usize, frames: Option<usize>, shots: Option<usize>) -> Result<(usize, usize, usize)> {
+    if rows == 0 || cols == 0 {
+        anyhow::bail!("--rows and --cols must be at least 1");
+    }
+    let frames = frames.or(shots).unwrap_or(rows * cols);
+    if shots.is_none() && (frames == 0 || frames > rows * cols) {
+        anyhow::bail!("--frames must be from 1 to {} (--rows x --cols)", rows * cols);
+    }
+    Ok((rows.max(frames.div_ceil(cols)), cols, frames))
+}
+
+/// Main entry point.
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    logging::init(if cli.quiet { -1 } else { cli.verbose as i8 }, cli.log_file.as_deref())?;
+    if !cli.quiet && !cli.no_progress {
+        progress::enable();
+    }
+
+    if cli.sandbox {
+        sandbox::check_available()?;
+    }
+    throttle::set(throttle::Limits {
+        bandwidth: cli.bandwidth_limit.map(|mb| mb * 1_000_000.0),
+        connections: cli.max_connections.map(|n| n as usize),
+    })?;
+    // Not `requires`: clap drops that when --files-from's conflicts are given.
+    if cli.null && cli.files_from.is_none() {
+        anyhow::bail!("-0 only applies to --files-from");
+    }
+    if cli.json {
+        if cli.output.as_deref() == Some(Path::new("-")) {
+            anyhow::bail!("--json needs standard output, so the sheet can't be written there too");
+        }
+        logging::reserve_stdout();
+        results::enable();
+    }
+    let config = config::load_defaults(cli.config.as_deref(), cli.config_dir.as_deref())?;
+    let font_file = cli.font_file.clone().or(config.font_file.clone());
+    if let Some(font_file) = &font_file {
+        font::check_file(font_file)?;
+    }
+    let edl = cli.edl.as_deref().map(edl::read).transpose()?;
+    let (rows, cols, frames) = grid(
+        cli.rows.or(config.rows).unwrap_or(3),
+        cli.cols.or(config.cols).unwrap_or(3),
+        cli.frames.or(config.frames),
+        cli.at_frames.as_ref().map(Vec::len).or(edl.as_ref().map(Vec::len)),
+    )?;
+    // A grid given on the command line beats the config file's `grid`.
+    let fixed_grid = cli.rows.is_some() || cli.cols.is_some() || cli.frames.is_some() || cli.at_frames.is_some() || edl.is_some();
+    let grid_mode = cli.grid.or(config.grid.filter(|_| !fixed_grid)).unwrap_or(GridMode::Fixed);
+    if cli.min_grid == 0 || cli.min_grid > cli.max_grid {
+        anyhow::bail!("--min-grid must be at least 1 and at most --max-grid");
+    }
+    if let Some(dir) = cli.output_dir.as_ref().filter(|_| !cli.dry_run) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    if let Some(dir) = &cli.temp_dir {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut opts = Options {
+        max_depth: if cli.recursive { cli.max_depth.map_or(usize::MAX, |d| d.saturating_add(1)) } else { 1 },
+        include: cli.include.clone(),
+        exclude: cli.exclude.clone(),
+        ignore_file: cli.ignore_file.clone(),
+        extensions: detect::extensions(
+            (!cli.extensions.is_empty()).then_some(&cli.extensions[..]).or(config.extensions.as_deref()),
+            &[&cli.extra_extensions[..], config.extra_extensions.as_deref().unwrap_or_default()].concat(),
+        ),
+        probe_all: cli.probe_all,
+        symlinks: match (cli.follow_symlinks, cli.no_follow_symlinks) {
+            (true, _) => Symlinks::Follow,
+            (_, true) => Symlinks::Skip,
+            _ => Symlinks::Files,
+        },
+        output_dir: cli.output_dir.clone(),
+        flat: cli.flat,
+        safe_names: cli.safe_names,
+        debug_dir: cli.debug_dir.clone(),
+        keep_frames: cli.keep_frames.clone(),
+        temp_dir: cli.temp_dir.clone(),
+        keep_temp: cli.keep_temp,
+        dry_run: cli.dry_run,
+        media_overrides: config.overrides.clone(),
+        stats: if cli.no_stats || cli.dry_run || config.stats == Some(false) {
+            None
+        } else {
+            stats::path(cli.config_dir.as_deref())
+        },
+        existing: if cli.skip_existing {
+            Existing::Skip
+        } else if cli.if_newer {
+            Existing::IfNewer
+        } else {
+            Existing::Overwrite
+        },
+        name_template: cli.name_template.clone().or(config.name_template.clone()),
+        title: None,
+        rows,
+        cols,
+        frames,
+        at_frames: cli.at_frames.clone(),
+        edl,
+        sandbox: cli.sandbox,
+        grid: grid_mode,
+        auto_grid_sides: (cli.min_grid, cli.max_grid),
+        layout: cli.layout,
+        strip_size: cli.strip_size,
+        keep_grid: cli.keep_grid,
+        max_name_length: cli.max_name_length,
+        labels: cli.lang.labels(),
+        theme: ThemeOverrides {
+            background: cli.background_color.clone(),
+            border: cli.tile_border.as_ref().and_then(|(_, color)| color.clone()),
+            border_width: cli.tile_border.as_ref().map(|(width, _)| *width),
+            shadow: cli.tile_shadow.then_some(true),
+            banner: cli.header.then_some(Banner::Header),
+            banner_color: cli.header_color.clone(),
+            banner_height: cli.header_height,
+            spacing: cli.gutter,
+            margin: cli.margin,
+            ..ThemeOverrides::default()
+        }
+        .apply(config.theme.resolve(cli.theme)),
+        timecode: cli.timecode || config.timecode.unwrap_or(false),
+        tile_timestamps: cli.tile_timestamps || config.tile_timestamps.unwrap_or(false),
+        tile_index: cli.tile_index || config.tile_index.unwrap_or(false),
+        tile_label: CornerLabel {
+            corner: cli.tile_label_corner,
+            size: cli.tile_label_size,
+            opacity: cli.tile_label_opacity,
+        },
+        chapter_titles: cli.chapter_titles || config.chapter_titles.unwrap_or(false),
+        timeline: cli.timeline || config.timeline.unwrap_or(false),
+        audio_summary: cli.audio_summary,
+        no_text: cli.no_text,
+        text_layer: cli.text_layer,
+        font_file,
+        pass_retries: cli.pass_retries,
+        text_fallback: cli.text_fallback,
+        sizes: cli.sizes.clone(),
+        tile_width: cli.tile_width.or(config.tile_width),
+        max_width: cli.max_width.or(config.max_width),
+        thumbnail_track: TrackLayout {
+            interval: cli.thumbnail_interval,
+            width: cli.thumbnail_width,
+            cols: cli.thumbnail_grid.0,
+            rows: cli.thumbnail_grid.1,
+        },
+        post: cli.post_cmd.iter().map(|cmd| Arc::new(cmd.clone()) as Arc<dyn PostProcess>).collect(),
+        outputs: cli.outputs.clone(),
+        colors: cli.colors.map_or(0, |n| n as usize),
+        blurhash: cli.blurhash,
+        hash_frames: cli.index.is_some(),
+        square: cli.square,
+        square_crop: cli.square_crop,
+        frame_format: cli.frame_format.or(config.frame_format).unwrap_or(FrameFormat::Yuv420),
+        seek: cli.seek.or(config.seek).unwrap_or(SeekMode::Fast),
+        hwaccel: cli.hwaccel.clone().or(config.hwaccel.clone()),
+        chroma: cli.chroma.or(config.chroma).unwrap_or(Chroma::Yuv420),
+        target_size: cli.target_size,
+        preview_audio: cli.preview_audio.clone(),
+        preview_loudnorm: cli.preview_loudnorm,
+        recordings: cli.recordings,
+        partial: false,
+        preview_codec: "libx264",
+        degraded: Vec::new(),
+        settle: Duration::from_secs_f64(cli.settle),
+        frame_timeout: (cli.frame_timeout > 0.0).then(|| Duration::from_secs_f64(cli.frame_timeout)),
+        timeout: (cli.timeout > 0.0).then(|| Duration::from_secs_f64(cli.timeout)),
+        start_offset: cli.start_offset,
+        skip: cli.skip.clone(),
+        retry: RetryPolicy {
+            retries: cli.io_retries,
+            delay: Duration::from_millis(cli.io_retry_delay),
+        },
+    };
+
+    exec::install_interrupt_handler()?;
+
+    let quick = matches!(cli.command, Some(Command::Completions { .. } | Command::Stats { .. } | Command::Verify { .. }));
+    if !cli.dry_run && !quick {
+        opts.degraded = capabilities::degrade(&mut opts);
+    }
+    let opts = opts;
+
+    if let Some(command) = &cli.command {
+        if cli.dry_run && !matches!(command, Command::Regen { .. }) {
+            anyhow::bail!("--dry-run only applies to making sheets and to regen");
+        }
+        let (video, result) = match command {
+            Command::Completions { shell } => {
+                let mut script = Vec::new();
+                clap_complete::generate(*shell, &mut Cli::command(), "thumbnailer", &mut script);
+                return io::stdout().write_all(&script).context("Failed to write the completion script");
+            }
+            Command::Stats { reset } => {
+                let path = stats::path(cli.config_dir.as_deref())
+                    .context("No config directory to keep stats in; give --config-dir")?;
+                if *reset {
+                    return stats::reset(&path);
+                }
+                return stats::show(&path);
+            }
+            Command::Report { dir, output } => return exit_on_stop(report::run(dir, output.as_deref(), &opts)),
+            Command::Dedupe { dir } => return exit_on_stop(dedupe::run(dir, cli.index.as_deref(), &opts)),
+            Command::Serve { dir, listen, cache_mb, workers, background_workers } => {
+                let workers = workers.or(config.workers).unwrap_or(4);
+                let background_workers = background_workers.or(config.background_workers).unwrap_or(1);
+                return exit_on_stop(serve::run(dir, listen, *cache_mb, workers, background_workers, &opts));
+            }
+            Command::Regen { conditions } => return exit_on_stop(run_regen(&cli, conditions, &opts)),
+            Command::Verify { dir, list } => {
+                return match verify::run(dir, cli.index.as_deref(), list.as_deref(), &opts) {
+                    Ok(true) => Ok(()),
+                    Ok(false) => exec::exit(1),
+                    Err(e) => exit_on_stop(Err(e)),
+                };
+            }
+            Command::Pick { video, candidates, preview } => (video, pick::run(video, *candidates, *preview, &opts)),
+            Command::Compare { a, b, metrics } => (a, compare::run(a, b, metrics, &opts)),
+            Command::Combine { videos, out } => (&videos[0], combine::run(videos, out.as_deref(), &opts)),
+            Command::Frames { video, out, count } => (video, frames::run(video, out.as_deref(), count.unwrap_or(opts.frames), &opts)),
+            #[cfg(feature = "gui")]
+            Command::Gui { video } => (video, gui::run(video, &opts)),
+        };
+        match result {
+            Ok(Outcome::Created(created)) => print_warnings(video, &created.warnings),
+            Ok(Outcome::Skipped(reason)) => log::info!("Skipped {}: {}", video.display(), reason),
+            Err(e) => return exit_on_stop(Err(e)),
+        }
+        return Ok(());
+    }
+
+    let extras = cli.skip_extras.then(|| ExtrasFilter::new(&cli.extras_words, &cli.extras_dirs));
+    if let Some(expr) = &cli.schedule {
+        return run_scheduled(&cli, &Schedule::parse(expr)?, extras.as_ref(), &opts);
+    }
+    let summary = run_once(&cli, extras.as_ref(), &opts)?;
+
+    if summary.total > 1 || summary.stopped.is_some() {
+        summary.report("Done");
+    }
+    match summary.exit_code() {
+        0 => Ok(()),
+        code => exec::exit(code),
+    }
+}
+
+/// Process the input once, writing the gallery, reports and quarantine list
+/// asked for.
+fn run_once(cli: &Cli, extras: Option<&ExtrasFilter>, opts: &Options) -> Result<BatchSummary> {
+    let mut summary = BatchSummary { degraded: opts.degraded.clone(), ..BatchSummary::default() };
+    let mut root = None;
+
+    if let Some(list) = &cli.retry_failed {
+        let videos: Vec<PathBuf> = quarantine::read(list)?.into_iter().map(|e| e.path).collect();
+        log::info!("Retrying {} previously failed files from {}", videos.len(), list.display());
+        run_batch(&videos, None, extras, None, opts, &mut summary);
+    } else if let Some(list) = &cli.files_from {
+        let videos = read_file_list(list, cli.null)?;
+        run_batch(&videos, None, extras, None, opts, &mut summary);
+    } else if let Some(path) = &cli.jobs {
+        let specs = jobs::read(path)?;
+        log::info!("Running {} jobs from {}", specs.len(), path.display());
+        run_jobs(&specs, opts, &mut summary);
+    } else {
+        let input_path = cli.input.as_deref().unwrap();
+        let input_meta = match opts.retry.io(format_args!("stat {}", input_path.display()), || fs::metadata(input_path)) {
+            Ok(meta) => Some(meta),
+            Err(e) if e.is::<PersistentIoError>() => return Err(e),
+            Err(_) => None,
+        };
+
+        if input_meta.as_ref().is_some_and(|m| m.is_dir()) {
+            if cli.output.is_some() {
+                anyhow::bail!("--output needs a single video file as the input");
+            }
+            root = Some(input_path);
+            let videos = list_videos(input_path, opts, &mut summary)?;
+            run_batch(&videos, Some(input_path), extras, None, opts, &mut summary);
+        } else if input_meta.as_ref().is_some_and(|m| m.is_file()) {
+            let video = input_path.to_path_buf();
+            match cli.output.as_deref() {
+                Some(output) if output == Path::new("-") => write_to_stdout(&video, opts, &mut summary)?,
+                output => run_batch(&[video], None, None, output, opts, &mut summary),
+            }
+        } else {
+            anyhow::bail!("Invalid input path: {}", input_path.display());
+        }
+    }
+
+    if let Some(dir) = &cli.gallery {
+        gallery::write(dir, &summary.created, root, cli.gallery_base_url.as_deref(), &opts.retry)?;
+    }
+
+    if let Some(output) = &cli.markdown {
+        markdown::write(output, &summary.created, &opts.retry)?;
+    }
+
+    if let Some(index) = &cli.index {
+        record(index, &summary, opts)?;
+    }
+
+    if let Some(list) = &cli.quarantine {
+        quarantine::write(list, &summary.failures)?;
+        if !summary.failures.is_empty() {
+            log::info!("Wrote {} failed files to {}", summary.failures.len(), list.display());
+        }
+    }
+
+    if let Some(archive) = &cli.archive {
+        let base = opts.output_dir.as_deref().or(root);
+        archive::write(archive, &summary.created, &summary.failures, base, opts)?;
+    }
+    summary.record_stats(opts);
+
+    Ok(summary)
+}
+
+/// Make each job's sheet with its own settings, where it asks for it.
+fn run_jobs(specs: &[jobs::Spec], opts: &Options, summary: &mut BatchSummary) {
+    for (done, spec) in specs.iter().enumerate() {
+        if summary.stopped.is_some() {
+            summary.remaining = specs.len() - done;
+            break;
+        }
+        let job_opts = match opts.with_overrides(&spec.options) {
+            Ok(job_opts) => Options { title: spec.title.clone(), ..job_opts },
+            Err(e) => {
+                summary.total += 1;
+                summary.fail(&spec.source, &anyhow::anyhow!("job {}: {}", done + 1, e));
+                continue;
+            }
+        };
+        run_batch(std::slice::from_ref(&spec.source), None, None, spec.output.as_deref(), &job_opts, summary);
+    }
+}
+
+/// Record the files `summary` processed in the index at `path`.
+fn record(path: &Path, summary: &BatchSummary, opts: &Options) -> Result<()> {
+    let fingerprint = opts.fingerprint();
+    let created = summary.created.iter()
+        .map(|c| index::Entry {
+            frames: c.frame_hashes.clone(),
+            ..index::Entry::new(&c.video, Some(&c.output), index::Status::Created, &fingerprint)
+        });
+    let failed = summary.failures.iter()
+        .map(|f| index::Entry::new(&f.path, None, index::Status::Failed, &fingerprint));
+    index::update(path, created.chain(failed).collect())
+}
+
+/// Process again the files in the index that meet every condition, writing
+/// each sheet where it was written before.
+fn run_regen(cli: &Cli, conditions: &[Condition], opts: &Options) -> Result<()> {
+    let Some(index_path) = &cli.index else {
+        anyhow::bail!("regen needs --index");
+    };
+    let fingerprint = opts.fingerprint();
+    let entries: Vec<index::Entry> = index::read(index_path)?.into_iter()
+        .filter(|e| conditions.iter().all(|c| c.matches(e, &fingerprint)))
+        .collect();
+    log::info!("Regenerating {} files from {}", entries.len(), index_path.display());
+
+    let mut summary = BatchSummary { degraded: opts.degraded.clone(), ..BatchSummary::default() };
+    for (done, entry) in entries.iter().enumerate() {
+        if summary.stopped.is_some() {
+            summary.remaining = entries.len() - done;
+            break;
+        }
+        run_batch(std::slice::from_ref(&entry.path), None, None, entry.sheet.as_deref(), opts, &mut summary);
+    }
+    record(index_path, &summary, opts)?;
+    summary.record_stats(opts);
+
+    summary.report("Regenerated");
+    match summary.exit_code() {
+        0 => Ok(()),
+        code => exec::exit(code),
+    }
+}
+
+/// Keep sweeping the input directory on `schedule` until Ctrl-C. A sweep
+/// that fails is logged, and the next one runs as scheduled.
+///
+/// Sweeps never overlap: one still running when the next is due makes that
+/// run skip, and a lock in the directory keeps other instances' scheduled
+/// sweeps of it from running at the same time.
+fn run_scheduled(cli: &Cli, schedule: &Schedule, extras: Option<&ExtrasFilter>, opts: &Options) -> Result<()> {
+    let dir = cli.input.as_deref()
+        .filter(|p| p.is_dir())
+        .ok_or_else(|| anyhow::anyhow!("--schedule needs a directory to sweep"))?;
+    let lock_target = dir.join("thumbnailer-sweep");
+
+    loop {
+        let Some(next) = schedule.next_after(Local::now()) else {
+            anyhow::bail!("The schedule never fires");
+        };
+        log::info!("Next sweep of {} at {}", dir.display(), next.format("%Y-%m-%d %H:%M"));
+        while Local::now() < next {
+            if exec::interrupted() {
+                exec::exit(EXIT_INTERRUPTED);
+            }
+            let left = (next - Local::now()).to_std().unwrap_or_default();
+            std::thread::sleep(left.min(Duration::from_secs(1)));
+        }
+
+        let started = Local::now();
+        let sweep = OutputLock::try_acquire(&lock_target)
+            .and_then(|lock| lock.map(|_lock| exit_on_stop(run_once(cli, extras, opts))).transpose());
+        let summary = match sweep {
+            Ok(Some(summary)) => summary,
+            Ok(None) => {
+                log::info!("Skipping sweep: another sweep of {} is still running", dir.display());
+                continue;
+            }
+            // The directory may be back by the next one (a share remounted).
+            Err(e) => {
+                log::error!("Sweep of {} failed: {:#}", dir.display(), e);
+                continue;
+            }
+        };
+        summary.report("Sweep done");
+        match summary.stopped {
+            Some(Stop::Interrupted) => exec::exit(EXIT_INTERRUPTED),
+            // Every later sweep would stop the same way.
+            Some(Stop::ToolMissing) => exec::exit(EXIT_TOOL_MISSING),
+            _ => {}
+        }
+        if schedule.next_after(started).is_some_and(|due| due < Local::now()) {
+            log::info!("Sweep ran past the next scheduled time; skipped the runs due meanwhile.");
+        }
+    }
+}
+
+/// Exit with the status for Ctrl-C or for a missing tool if `result` was
+/// cut short by one.
+fn exit_on_stop<T>(result: Result<T>) -> Result<T> {
+    match result {
+        Err(e) if exec::is_interrupted(&e) => exec::exit(EXIT_INTERRUPTED),
+        Err(e) if exec::is_tool_missing(&e) => {
+            log::error!("Error: {:#}", e);
+            exec::exit(EXIT_TOOL_MISSING);
+        }
+        result => result,
+    }
+}
+
+/// Exit status when some files failed and others worked.
+const EXIT_SOME_FAILED: i32 = 3;
+/// Exit status when every file failed.
+const EXIT_ALL_FAILED: i32 = 4;
+/// Exit status when FFmpeg (or another tool it runs) isn't installed.
+const EXIT_TOOL_MISSING: i32 = 5;
+/// The usual exit status for Ctrl-C.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Why a batch run stopped before processing every file.
+enum Stop {
+    Interrupted,
+    LowDiskSpace,
+    /// FFmpeg or ffprobe isn't installed, so every file would fail.
+    ToolMissing,
+}
+
+/// Tally of a batch run.
+#[derive(Default)]
+struct BatchSummary {
+    total: usize,
+    succeeded: usize,
+    skipped: usize,
+    /// Of the skipped files, those whose sheet was already there.
+    kept: usize,
+    /// Time spent on the mosaics written.
+    creating: Duration,
+    /// Files not attempted because the run stopped early.
+    remaining: usize,
+    /// What was given up for this system's FFmpeg (`Options::degraded`).
+    degraded: Vec<String>,
+    failures: Vec<quarantine::Entry>,
+    /// The mosaics written.
+    created: Vec<Created>,
+    stopped: Option<Stop>,
+}
+
+impl BatchSummary {
+    fn fail(&mut self, path: &Path, err: &anyhow::Error) {
+        let failure = quarantine::Entry::new(path, err);
+        log::error!("Failed to process {}: {}", path.display(), failure.reason);
+        if exec::is_tool_missing(err) {
+            self.stopped = Some(Stop::ToolMissing);
+        }
+        results::failed(&failure);
+        self.failures.push(failure);
+    }
+
+    fn skip(&mut self, path: &Path, reason: &str) {
+        log::info!("Skipped {}: {}", path.display(), reason);
+        results::skipped(path, reason);
+        self.skipped += 1;
+    }
+
+    /// Log the tally under `heading`, why the run stopped early if it did,
+    /// and each failed file with its reason, together at the end rather
+    /// than only among the rest of the output.
+    fn report(&self, heading: &str) {
+        let left = match self.remaining {
+            0 => String::new(),
+            n => format!(", {} files not processed", n),
+        };
+        match self.stopped {
+            Some(Stop::Interrupted) => log::warn!("Interrupted{}.", left),
+            Some(Stop::LowDiskSpace) => log::error!("Stopped: out of disk space{}.", left),
+            Some(Stop::ToolMissing) => log::error!("Stopped: FFmpeg isn't available{}.", left),
+            None => {}
+        }
+        log::info!(
+            "{}: {} succeeded, {} skipped, {} failed (of {} files).",
+            heading, self.succeeded, self.skipped, self.failures.len(), self.total
+        );
+        for degradation in &self.degraded {
+            log::warn!("Degraded: {}", degradation);
+        }
+        if !self.failures.is_empty() {
+            let mut kinds: Vec<(String, usize)> = Vec::new();
+            for failure in &self.failures {
+                let kind = failure.category.map_or("other".to_string(), |c| c.to_string());
+                match kinds.iter_mut().find(|(k, _)| *k == kind) {
+                    Some((_, count)) => *count += 1,
+                    None => kinds.push((kind, 1)),
+                }
+            }
+            kinds.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            let kinds: Vec<String> = kinds.iter().map(|(kind, count)| format!("{} {}", count, kind)).collect();
+            log::error!("Failed files ({}):", kinds.join(", "));
+            for failure in &self.failures {
+                log::error!("  {}: {}", failure.path.display(), failure.reason);
+            }
+        }
+    }
+
+    /// What the run adds to the usage stats.
+    fn tally(&self) -> stats::Tally {
+        stats::Tally {
+            created: self.succeeded as u64,
+            kept: self.kept as u64,
+            skipped: (self.skipped - self.kept) as u64,
+            failures: self.failures.iter()
+                .map(|f| f.category.map_or("other".to_string(), |c| c.to_string()))
+                .collect(),
+            creating: self.creating,
+        }
+    }
+
+    /// Add the run to the usage stats in `opts.stats`, if kept; a stats
+    /// file that can't be written only warns.
+    fn record_stats(&self, opts: &Options) {
+        if let Some(path) = &opts.stats {
+            if let Err(e) = stats::record(path, &self.tally()) {
+                log::warn!("usage stats not updated: {:#}", e);
+            }
+        }
+    }
+
+    /// Exit status for how the run went: 0 when no file failed.
+    fn exit_code(&self) -> i32 {
+        match self.stopped {
+            Some(Stop::Interrupted) => EXIT_INTERRUPTED,
+            Some(Stop::ToolMissing) => EXIT_TOOL_MISSING,
+            Some(Stop::LowDiskSpace) => 1,
+            None if self.failures.is_empty() => 0,
+            None if self.succeeded + self.skipped == 0 => EXIT_ALL_FAILED,
+            None => EXIT_SOME_FAILED,
+        }
+    }
+}
+
+/// List the video files inside `dir`, down to `opts.max_depth` levels, in
+/// name order, each file once however many links lead to it. Files that
+/// can't even be stat'ed (and directories that can't be read) are recorded
+/// as failures.
+fn list_videos(dir: &Path, opts: &Options, summary: &mut BatchSummary) -> Result<Vec<PathBuf>> {
+    opts.retry.io(format_args!("list {}", dir.display()), || fs::read_dir(dir).map(drop))?;
+
+    let mut videos = Vec::new();
+    let mut seen = SeenFiles::default();
+    let mut filter = PathFilter::new(&opts.include, &opts.exclude, opts.ignore_file.as_deref());
+    let walk = WalkDir::new(dir)
+        .min_depth(1)
+        .max_depth(opts.max_depth)
+        .follow_links(opts.symlinks == Symlinks::Follow)
+        .sort_by_file_name();
+    let entries = walk.into_iter().filter_entry(|e| {
+        !(opts.symlinks == Symlinks::Skip && e.path_is_symlink())
+            && filter.allows(dir, e.path(), e.file_type().is_dir())
+    });
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.into_path(),
+            Err(e) if e.loop_ancestor().is_some() => {
+                log::warn!(
+                    "Not following {}: it links back to {}",
+                    e.path().unwrap_or(dir).display(),
+                    e.loop_ancestor().unwrap().display()
+                );
+                continue;
+            }
+            Err(e) => {
+                // An unreadable subdirectory fails on its own; the rest still runs.
+                let path = e.path().unwrap_or(dir).to_path_buf();
+                summary.total += 1;
+                summary.fail(&path, &anyhow::Error::new(e));
+                continue;
+            }
+        };
+        if !detect::is_video(&path, opts) {
+            continue;
+        }
+        match opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(&path)) {
+            Ok(meta) if meta.is_file() => match seen.earlier(&path, &meta) {
+                Some(first) => log::info!("Skipping {}: the same file as {}", path.display(), first.display()),
+                None => videos.push(path),
+            },
+            Ok(_) => {}
+            Err(e) => {
+                summary.total += 1;
+                summary.fail(&path, &e);
+            }
+        }
+    }
+    Ok(videos)
+}
+
+/// Make the sheet of `video` in a scratch directory and copy it to
+/// standard output.
+fn write_to_stdout(video: &Path, opts: &Options, summary: &mut BatchSummary) -> Result<()> {
+    if opts.square.is_some() || !opts.sizes.is_empty() || opts.text_layer {
+        anyhow::bail!("--square, --sizes and --text-layer can't be combined with writing the sheet to standard output");
+    }
+    if opts.outputs.iter().any(|&a| a != Artifact::Sheet) || !opts.outputs.contains(&Artifact::Sheet) {
+        anyhow::bail!("Writing to standard output only makes the sheet; use --outputs sheet (or leave it out) with -o -");
+    }
+    logging::reserve_stdout();
+    let scratch = tempfile::tempdir()?;
+    let output_image = scratch.path().join("sheet.jpg");
+    run_batch(&[video.to_path_buf()], None, None, Some(&output_image), opts, summary);
+
+    if summary.succeeded > 0 {
+        let image = fs::read(&output_image)?;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&image).and_then(|_| stdout.flush())
+            .map_err(|e| anyhow::anyhow!("Failed to write the sheet to standard output: {}", e))?;
+    }
+    // The sheet only existed to be piped out.
+    summary.created.clear();
+    Ok(())
+}
+
+/// Generate mosaics for `videos`, stopping early on Ctrl-C or a full disk.
+/// Each sheet goes next to its video (or under the output directory,
+/// mirroring the tree below `root`), or to `output` when given (for a single
+/// video).
+///
+/// `root` is the directory the videos were found in, if any; extras are only
+/// recognized by folders below it, and its `.thumbnailer.toml` files only
+/// apply below it.
+fn run_batch(
+    videos: &[PathBuf],
+    root: Option<&Path>,
+    extras: Option<&ExtrasFilter>,
+    output: Option<&Path>,
+    opts: &Options,
+    summary: &mut BatchSummary,
+) {
+    summary.total += videos.len();
+
+    let mut claims = OutputClaims::new();
+    for path in videos {
+        claims.add_source(path);
+    }
+    let mut dir_configs = DirConfigs::default();
+    let mut progress = progress::Batch::new();
+
+    // Files still being written go to the back, with how often they were.
+    let mut queue: VecDeque<(&PathBuf, u32)> = videos.iter().map(|path| (path, 0)).collect();
+    while let Some((path, deferrals)) = queue.pop_front() {
+        if exec::interrupted() {
+            summary.stopped = Some(Stop::Interrupted);
+        }
+        if summary.stopped.is_some() {
+            summary.remaining = queue.len() + 1;
+            return;
+        }
+
+        if let Some(reason) = extras.and_then(|f| f.matches(root, path)) {
+            summary.skip(path, &format!("looks like an extra ({})", reason));
+            continue;
+        }
+        let growing = match growing::is_growing(path, opts.settle, &opts.retry) {
+            Ok(false) => false,
+            Ok(true) if opts.recordings => true,
+            Ok(true) if deferrals < growing::MAX_DEFERRALS => {
+                log::info!("Deferred {}: still being written; trying again after the others", path.display());
+                queue.push_back((path, deferrals + 1));
+                continue;
+            }
+            Ok(true) => {
+                summary.skip(path, "still being written; left for a later run");
+                continue;
+            }
+            Err(e) => {
+                summary.fail(path, &e);
+                continue;
+            }
+        };
+        let overrides = root.map_or(Ok(None), |root| dir_configs.for_file(root, path))
+            .and_then(|config| config.map(|config| {
+                opts.with_overrides(&config).map_err(|e| anyhow::anyhow!("{}: {}", config::DIR_CONFIG, e))
+            }).transpose());
+        let dir_opts = match overrides {
+            Ok(dir_opts) => dir_opts,
+            Err(e) => {
+                summary.fail(path, &e);
+                continue;
+            }
+        };
+        let opts = dir_opts.as_ref().unwrap_or(opts);
+        let partial_opts = growing.then(|| Options { partial: true, ..opts.clone() });
+        let opts = partial_opts.as_ref().unwrap_or(opts);
+        let wanted = match output {
+            Some(output) => output.to_path_buf(),
+            None => match templated_output_path(path, root, opts) {
+                Ok(wanted) => wanted,
+                Err(e) => {
+                    summary.fail(path, &e);
+                    continue;
+                }
+            },
+        };
+        let output_image = match claims.claim(path, wanted) {
+            Ok(output_image) => output_image,
+            Err(e) => {
+                summary.fail(path, &e);
+                continue;
+            }
+        };
+        // FFmpeg is handed the paths as text.
+        let (Some(video_path), Some(sheet_path)) = (path.to_str(), output_image.to_str()) else {
+            summary.fail(path, &anyhow::anyhow!("the path of the video or its sheet isn't valid UTF-8"));
+            continue;
+        };
+        // A sheet of part of a recording is made again once there's more of it.
+        let kept = match growing::partial_size(&output_image).filter(|_| opts.recordings) {
+            Some(size) if growing && fs::metadata(path).is_ok_and(|m| m.len() == size) => {
+                Ok(Some("the recording hasn't grown since its sheet was made".to_string()))
+            }
+            Some(_) => Ok(None),
+            None => opts.existing.keep_reason(path, &opts.primary_output(&output_image), opts),
+        };
+        match kept {
+            Ok(Some(reason)) => {
+                summary.skip(path, &reason);
+                summary.kept += 1;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                summary.fail(path, &e);
+                continue;
+            }
+        }
+        if opts.dry_run {
+            match mosaic::plan(video_path, &output_image, opts.rows, opts.cols, opts.frames, opts) {
+                Ok(None) => summary.succeeded += 1,
+                Ok(Some(reason)) => {
+                    log::info!("Would skip {}: {}", path.display(), reason);
+                    summary.skipped += 1;
+                }
+                Err(e) => summary.fail(path, &e),
+            }
+            continue;
+        }
+        if let Some(dir) = output_image.parent().filter(|_| opts.output_dir.is_some()) {
+            if let Err(e) = fs::create_dir_all(dir) {
+                summary.fail(path, &anyhow::anyhow!("Failed to create {}: {}", dir.display(), e));
+                continue;
+            }
+        }
+        log::info!("Processing: {}", path.display());
+        progress.next(&path.file_name().unwrap_or_default().to_string_lossy(), queue.len());
+        if let Some(dir) = &opts.debug_dir {
+            // What an earlier run kept would be mistaken for this one's.
+            let _ = fs::remove_dir_all(naming::video_dir(dir, path));
+        }
+        let started = Instant::now();
+        let result = create_thumbnail_mosaic(
+            video_path,
+            sheet_path,
+            opts.rows, opts.cols, opts.frames,
+            opts,
+        );
+        log::debug!("{}: done in {:.1}s", path.display(), started.elapsed().as_secs_f64());
+        match result {
+            Ok(Outcome::Created(created)) => {
+                print_warnings(path, &created.warnings);
+                results::created(&created, started.elapsed());
+                if opts.recordings {
+                    if let Err(e) = growing::mark(&output_image, path, opts.partial) {
+                        log::warn!("{}: {:#}", path.display(), e);
+                    }
+                }
+                summary.succeeded += 1;
+                summary.creating += started.elapsed();
+                summary.created.push(*created);
+            }
+            Ok(Outcome::Skipped(reason)) => summary.skip(path, &reason),
+            Err(e) if exec::is_interrupted(&e) => {
+                summary.stopped = Some(Stop::Interrupted);
+                summary.remaining = queue.len() + 1;
+                return;
+            }
+            Err(e) if diskspace::is_low_disk_space(&e) => {
+                summary.fail(path, &e);
+                summary.stopped = Some(Stop::LowDiskSpace);
+            }
+            Err(e) => {
+                summary.fail(path, &e);
+                if let Some(dir) = &opts.debug_dir {
+                    save_error(&naming::video_dir(dir, path), summary.failures.last().unwrap());
+                }
+            }
+        }
+    }
+}
+
+/// Write why a file failed to `error.txt` in its debug directory.
+fn save_error(dir: &Path, failure: &quarantine::Entry) {
+    let mut report = format!("{}\n{}\n", failure.path.display(), failure.reason);
+    if !failure.stderr_tail.is_empty() {
+        report += &format!("\nLast lines of FFmpeg's stderr:\n{}\n", failure.stderr_tail);
+    }
+    let saved = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join("error.txt"), report));
+    match saved {
+        Ok(()) => log::info!("Kept what's left of {} in {}", failure.path.display(), dir.display()),
+        Err(e) => log::warn!("couldn't write to {}: {}", dir.display(), e),
+    }
+}
+
+/// Where `video`'s sheet goes, named after `--name-template` if given.
+fn templated_output_path(video: &Path, root: Option<&Path>, opts: &Options) -> Result<PathBuf> {
+    let default = naming::output_path(video, root.filter(|_| !opts.flat), opts.output_dir.as_deref());
+    let name = match &opts.name_template {
+        Some(template) => {
+            let info = if template.needs_probe(opts) {
+                let sandbox = opts.sandbox.then(|| Sandbox::new(Vec::new()).reading(video));
+                Some(probe::probe(&video.to_string_lossy(), sandbox.as_ref(), &opts.retry)?)
+            } else {
+                None
+            };
+            template.render(video, info.as_ref(), opts)?
+        }
+        None => default.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+    };
+    Ok(match opts.safe_names {
+        Some(safe) => default.with_file_name(safe.apply(&name, video)),
+        None => default.with_file_name(name),
+    })
+}
+
+fn print_warnings(path: &Path, warnings: &[String]) {
+    for warning in warnings {
+        log::warn!("{}: {}", path.display(), warning);
+    }
+}
+
+/// The paths listed in `list` (standard input for `-`), one per line or
+/// separated by NULs. Blank entries are ignored.
+fn read_file_list(list: &Path, nul: bool) -> Result<Vec<PathBuf>> {
+    // Bytes, not text: names needn't be UTF-8, which is what -0 is for.
+    let bytes = if list == Path::new("-") {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).context("Failed to read the file list from standard input")?;
+        bytes
+    } else {
+        fs::read(list).with_context(|| format!("Failed to read file list {}", list.display()))?
+    };
+    let separator = if nul { b'\0' } else { b'\n' };
+    Ok(bytes.split(|&b| b == separator)
+        .map(|line| if nul { line } else { line.strip_suffix(b"\r").unwrap_or(line) })
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// A path from its bytes as listed; on Windows, where paths aren't bytes,
+/// from the text they hold.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The videos a batch run over `dir` would process (down to `--max-depth`
+/// with `--recursive`, through the include and exclude filters), for
+/// commands that check a library made that way. What can't be listed is
+/// logged and passed over.
+fn library_videos(dir: &Path, opts: &Options) -> Result<Vec<PathBuf>> {
+    list_videos(dir, opts, &mut BatchSummary::default())
+}
+
+/// The video files directly inside `dir`, sorted, for commands that scan a
+/// library without generating sheets.
+fn video_files(dir: &Path, opts: &Options) -> Result<Vec<PathBuf>> {
+    let mut paths = opts.retry.io(format_args!("list {}", dir.display()), || {
+        fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    paths.sort();
+    let mut seen = SeenFiles::default();
+    paths.retain(|p| {
+        detect::has_video_extension(p, &opts.extensions)
+            && !(opts.symlinks == Symlinks::Skip && p.is_symlink())
+            && fs::metadata(p).is_ok_and(|meta| meta.is_file() && seen.earlier(p, &meta).is_none())
+    });
+    Ok(paths)
+}
+
@@ -0,0 +1,70 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::html;
+use crate::report::format_size;
+use crate::retry::RetryPolicy;
+use crate::text;
+use crate::Created;
+
+/// Write a Markdown report on `sheets` to `output`: a section per video with
+/// its sheet, a metadata table and the timestamp of every tile, ready to
+/// paste into a wiki page, PR description or release notes.
+pub fn write(output: &Path, sheets: &[Created], retry: &RetryPolicy) -> Result<()> {
+    let base = output.parent().unwrap_or(Path::new(""));
+    let mut doc = String::from("# Thumbnails\n");
+
+    for sheet in sheets {
+        let name = sheet.video.file_name().unwrap_or_default().to_string_lossy();
+        let info = &sheet.info;
+        let _ = write!(
+            doc,
+            "\n## {name}\n\n![{name}]({image})\n\n| | |\n|---|---|\n",
+            name = escape(&name),
+            image = html::href(&sheet.output, base),
+        );
+
+        let mut row = |label: &str, value: String| {
+            if !value.is_empty() {
+                let _ = writeln!(doc, "| {} | {} |", label, escape(&value));
+            }
+        };
+        row("Duration", info.duration().map(|(d, _)| text::format_time(d)).unwrap_or_default());
+        row("Resolution", info.resolution());
+        row("Video", info.video_stream().map(|s| s.codec_name.clone()).unwrap_or_default());
+        row("Audio", info.streams.iter()
+            .filter(|s| s.codec_type == "audio")
+            .map(|s| s.codec_name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "));
+        if let Ok(meta) = retry.io(format_args!("stat {}", sheet.video.display()), || fs::metadata(&sheet.video)) {
+            row("Size", format_size(meta.len()));
+        }
+
+        let tiles: Vec<String> = sheet.tiles.iter().enumerate()
+            .map(|(i, tile)| if tile.blank {
+                format!("{}. (blank)", i + 1)
+            } else {
+                format!("{}. {}", i + 1, text::format_time(tile.shown))
+            })
+            .collect();
+        let _ = writeln!(doc, "\nTiles: {}", tiles.join(" · "));
+    }
+
+    fs::write(output, doc).with_context(|| format!("Failed to write Markdown report {}", output.display()))?;
+    log::info!("Wrote Markdown report on {} sheets to {}", sheets.len(), output.display());
+    Ok(())
+}
+
+/// Escape characters Markdown would otherwise treat as formatting or table syntax.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' | '!') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
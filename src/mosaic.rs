@@ -0,0 +1,1884 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tempfile::{tempdir, TempDir};
+use crate::audio;
+use crate::blurhash;
+use crate::dedupe::{self, FrameHash};
+use crate::budget;
+use crate::diskspace;
+use crate::edl;
+use crate::exec::{self, Category};
+use crate::font;
+use crate::growing;
+use crate::i18n::Labels;
+use crate::kept;
+use crate::lock::OutputLock;
+use crate::naming;
+use crate::palette::{self, Swatch};
+use crate::planner::{self, EvenSelector, FixedSelector, PlanInput, Segment, Selector};
+use crate::post;
+use crate::poster;
+use crate::preview;
+use crate::probe::{self, MediaInfo};
+use crate::progress;
+use crate::retry::RetryPolicy;
+use crate::sandbox::{self, Sandbox};
+use crate::storyboard;
+use crate::text;
+use crate::thumbnails;
+use crate::theme::{Banner, Theme};
+use crate::timecode::Timecode;
+use crate::{Created, Options, Outcome};
+
+/// The video being processed and how to run tools against it.
+pub struct Source<'a> {
+    pub path: &'a str,
+    /// Index of the real video stream (not cover art) frames are taken from.
+    pub stream: usize,
+    /// Timestamp of the first packet; seeks are relative to this.
+    pub start_time: f64,
+    pub sandbox: Option<&'a Sandbox>,
+    /// How frames extracted from it are written.
+    pub frame_format: FrameFormat,
+    /// Width frames are scaled to as they're extracted; full size if `None`.
+    pub width: Option<u32>,
+    pub seek: SeekMode,
+    /// FFmpeg `-hwaccel` method frames are decoded with, if any.
+    pub hwaccel: Option<&'a str>,
+}
+
+impl Source<'_> {
+    /// `-map` argument selecting the video stream.
+    pub fn map(&self) -> String {
+        format!("0:{}", self.stream)
+    }
+
+    /// FFmpeg arguments opening the video, positioned at `timestamp`.
+    pub fn input_args(&self, timestamp: f64) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(method) = self.hwaccel {
+            args.extend(["-hwaccel".to_string(), method.to_string()]);
+        }
+        match self.seek {
+            SeekMode::Fast => args.extend(["-ss".to_string(), format!("{:.3}", timestamp), "-i".to_string(), self.path.to_string()]),
+            // Output timestamps keep the start time under -copyts.
+            SeekMode::Accurate => args.extend(["-i".to_string(), self.path.to_string(), "-ss".to_string(), format!("{:.3}", timestamp + self.start_time)]),
+        }
+        args
+    }
+}
+
+/// How FFmpeg gets to the time of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeekMode {
+    /// Jump to the keyframe before it and decode from there.
+    Fast,
+    /// Decode from the start, for files whose index or timestamps can't be
+    /// trusted (MPEG-TS captures); slow on long videos.
+    Accurate,
+}
+
+/// How extracted frames are kept until they're tiled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    /// 8-bit JPEG with 4:2:0 chroma.
+    Yuv420,
+    /// 8-bit JPEG with full chroma.
+    Yuv444,
+    /// Lossless 8-bit PNG.
+    Rgb24,
+    /// Lossless 16-bit PNG, keeping the gradations of 10-bit and HDR sources.
+    Rgb48,
+}
+
+impl FrameFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Yuv420 | FrameFormat::Yuv444 => "jpg",
+            FrameFormat::Rgb24 | FrameFormat::Rgb48 => "png",
+        }
+    }
+
+    pub fn pix_fmt(self) -> &'static str {
+        match self {
+            FrameFormat::Yuv420 => "yuvj420p",
+            FrameFormat::Yuv444 => "yuvj444p",
+            FrameFormat::Rgb24 => "rgb24",
+            FrameFormat::Rgb48 => "rgb48be",
+        }
+    }
+
+    /// Rough size of a frame on disk, in bytes per pixel. Deliberately pessimistic.
+    fn bytes_per_pixel(self) -> f64 {
+        match self {
+            FrameFormat::Yuv420 => 0.5,
+            FrameFormat::Yuv444 => 0.8,
+            FrameFormat::Rgb24 => 3.0,
+            FrameFormat::Rgb48 => 6.0,
+        }
+    }
+}
+
+/// Chroma subsampling of a sheet's JPEG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum Chroma {
+    #[value(name = "420")]
+    #[serde(rename = "420")]
+    Yuv420,
+    #[value(name = "422")]
+    #[serde(rename = "422")]
+    Yuv422,
+    #[value(name = "444")]
+    #[serde(rename = "444")]
+    Yuv444,
+}
+
+impl Chroma {
+    pub fn pix_fmt(self) -> &'static str {
+        match self {
+            Chroma::Yuv420 => "yuvj420p",
+            Chroma::Yuv422 => "yuvj422p",
+            Chroma::Yuv444 => "yuvj444p",
+        }
+    }
+}
+
+/// A kind of file made from a video's extracted frames (`--outputs`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Artifact {
+    /// The contact sheet itself.
+    Sheet,
+    /// The most detailed frame as extracted (<sheet>_poster.jpg).
+    Poster,
+    /// A sprite of small thumbnails and a WebVTT track for scrubbing
+    /// previews (<sheet>_storyboard.jpg and .vtt).
+    Storyboard,
+    /// A short video of a clip at each tile's frame (<sheet>_preview.mp4).
+    Preview,
+    /// Numbered sprites of thumbnails at a fixed interval, and the DASH
+    /// and HLS manifest entries for them as a trick-play track
+    /// (<sheet>_thumbnails/).
+    Thumbnails,
+}
+
+impl Artifact {
+    /// Where this is written for a sheet at `sheet` (the image, for a
+    /// storyboard; the description, for a thumbnail track).
+    pub fn path(self, sheet: &Path) -> PathBuf {
+        match self {
+            Artifact::Sheet => sheet.to_path_buf(),
+            Artifact::Poster => poster::poster_output_path(sheet),
+            Artifact::Storyboard => storyboard::image_path(sheet),
+            Artifact::Preview => preview::path(sheet),
+            Artifact::Thumbnails => thumbnails::metadata_path(sheet),
+        }
+    }
+}
+
+/// A frame picked for the mosaic.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Tile {
+    /// Where we asked FFmpeg to seek to, in seconds.
+    pub requested: f64,
+    /// Presentation time of the frame that was actually extracted.
+    pub shown: f64,
+    /// A placeholder standing in for a frame that couldn't be decoded.
+    pub blank: bool,
+}
+
+/// How far a tile's frame may be from the requested time before it's worth a warning.
+const MAX_TILE_DRIFT: f64 = 2.0;
+
+/// How a sheet's grid is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GridMode {
+    /// `--rows` x `--cols`, whatever the video's length.
+    Fixed,
+    /// A square grid that grows with the video's duration.
+    Auto,
+}
+
+/// Side of the square grid `--grid auto` gives a video of `duration`
+/// seconds: one more row and column each time the duration triples, from
+/// 2x2 at two minutes to 6x6 at three hours, kept within `min..=max`.
+pub fn auto_grid_side(duration: f64, (min, max): (usize, usize)) -> usize {
+    let minutes = (duration / 60.0).max(1.0 / 60.0);
+    let side = (1.4 + minutes.log(3.0)).round().max(1.0) as usize;
+    side.clamp(min, max)
+}
+
+/// Re-shape the grid for portrait video so the sheet doesn't end up absurdly tall.
+///
+/// Picks the `rows x cols` whose sheet aspect ratio is closest to what the
+/// requested grid would give for 16:9 frames, with a similar number of cells.
+/// When the requested grid was full, the frame count follows the new grid.
+pub fn fit_grid_to_aspect(rows: usize, cols: usize, total_frames: usize, aspect: f64) -> (usize, usize, usize) {
+    if aspect >= 1.0 || rows == 0 || cols == 0 || total_frames == 0 {
+        return (rows, cols, total_frames);
+    }
+
+    let target = cols as f64 * (16.0 / 9.0) / rows as f64;
+    let cells_wanted = (rows * cols) as f64;
+    let mut best = (rows, cols, f64::INFINITY);
+    for c in 1..=2 * rows * cols {
+        for r in 1..=rows * cols {
+            let cells = (r * c) as f64;
+            if cells < cells_wanted * 0.75 || cells > cells_wanted * 4.0 / 3.0 {
+                continue;
+            }
+            let sheet_aspect = c as f64 * aspect / r as f64;
+            let score = (sheet_aspect / target).ln().abs() + 0.02 * (cells - cells_wanted).abs();
+            if score < best.2 {
+                best = (r, c, score);
+            }
+        }
+    }
+
+    let (r, c, _) = best;
+    let frames = if total_frames == rows * cols { r * c } else { total_frames.min(r * c) };
+    (r, c, frames)
+}
+
+/// Get file size in megabytes.
+fn get_filesize_mb(path: &str, retry: &RetryPolicy) -> Result<f64> {
+    let size_bytes = retry.io(format_args!("stat {}", path), || fs::metadata(path))?.len();
+    Ok(size_bytes as f64 / 1_000_000.0)
+}
+
+/// Presentation time of the frame FFmpeg's showinfo filter saw, parsed from its log.
+fn parse_showinfo_pts(stderr: &str) -> Option<f64> {
+    stderr.lines()
+        .filter(|line| line.contains("showinfo"))
+        .find_map(|line| {
+            let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        })
+}
+
+/// What came of extracting a frame.
+pub struct Extraction {
+    /// Presentation time of the frame that was written, relative to the start
+    /// of the file. With variable frame rate sources this can differ from the
+    /// requested time, so it is what tiles should be labeled with.
+    pub shown: Option<f64>,
+    /// What FFmpeg's stderr says went wrong, when it failed.
+    pub failure: Option<Category>,
+}
+
+/// Extract the frame at `timestamp` into `output_file`.
+pub fn extract_frame(
+    src: &Source,
+    timestamp: f64,
+    output_file: &Path,
+    timeout: Option<Duration>,
+) -> Result<Extraction> {
+    let output = exec::output_with_timeout(&mut frame_command(src, timestamp, output_file), timeout)
+        .with_context(|| format!("Failed to extract thumbnail at {:.3}s", timestamp))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let pts = parse_showinfo_pts(&stderr);
+    Ok(Extraction {
+        shown: pts.map(|pts| (pts - src.start_time).max(0.0)),
+        failure: (!output.status.success()).then(|| exec::classify(&stderr)).flatten(),
+    })
+}
+
+/// The ffmpeg command `extract_frame` runs.
+fn frame_command(src: &Source, timestamp: f64, output_file: &Path) -> Command {
+    let mut cmd = sandbox::command("ffmpeg", src.sandbox);
+    // -copyts keeps the decoder's timestamps instead of resetting them to 0
+    // at the seek point, so showinfo reports where the frame really is.
+    let filter = match src.width {
+        Some(width) => format!("showinfo,scale={}:-2", width),
+        None => "showinfo".to_string(),
+    };
+    cmd.args(src.input_args(timestamp)).args([
+        "-map", &src.map(),
+        "-copyts",
+        "-vf", &filter,
+        "-frames:v", "1",
+        "-pix_fmt", src.frame_format.pix_fmt(),
+        "-q:v", "2",
+        "-y",
+    ]).arg(output_file);
+    cmd
+}
+
+/// Result of trying to extract one tile.
+pub enum TileAttempt {
+    Extracted(Tile),
+    /// The time budget ran out before any frame was extracted.
+    TimedOut,
+    /// FFmpeg ran but produced no image anywhere we looked (seek past the
+    /// end, decode errors), with what its stderr said last.
+    Missing(Option<Category>),
+}
+
+/// Extract one tile at `timestamp` within `segment`, retrying a bit later
+/// while the frame is black and elsewhere in the segment if no frame comes out.
+///
+/// `budget` caps the time spent on the tile, so a file that makes FFmpeg seek
+/// forever costs one budget per tile, not a hang.
+pub fn extract_tile(
+    src: &Source,
+    timestamp: f64,
+    segment: (f64, f64),
+    output_file: &Path,
+    budget: Option<Duration>,
+) -> Result<TileAttempt> {
+    let deadline = budget.map(|b| Instant::now() + b);
+    let remaining = || deadline.map(|d| d.saturating_duration_since(Instant::now()));
+    // Each attempt extracts to a scratch file first, so a later attempt that
+    // fails can't clobber an earlier (merely black) frame.
+    let candidate = output_file.with_extension(format!("next.{}", src.frame_format.extension()));
+    let mut resample = planner::resample_times(segment);
+
+    let mut attempt = 0;
+    let mut extracted = None;
+    let mut failure = None;
+    let mut timestamp = timestamp;
+
+    loop {
+        let _ = fs::remove_file(&candidate);
+        let shown = match extract_frame(src, timestamp, &candidate, remaining()) {
+            Ok(extraction) => {
+                failure = extraction.failure.or(failure);
+                extraction.shown.unwrap_or(timestamp)
+            }
+            Err(e) if exec::is_timed_out(&e) => {
+                if extracted.is_none() {
+                    return Ok(TileAttempt::TimedOut);
+                }
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if !candidate.is_file() {
+            match resample.next() {
+                Some(t) if extracted.is_none() => {
+                    timestamp = t;
+                    continue;
+                }
+                _ => break,
+            }
+        }
+        fs::rename(&candidate, output_file)?;
+        extracted = Some(Tile { requested: timestamp, shown, blank: false });
+
+        match is_black_frame(src, shown, remaining()) {
+            Ok(true) if attempt < planner::MAX_BLACK_RETRIES => {}
+            Ok(_) => break,
+            Err(e) if exec::is_timed_out(&e) => break,
+            Err(e) => return Err(e),
+        }
+
+        attempt += 1;
+        // Black frames are retried a little later, but never out of the segment.
+        match planner::black_retry(timestamp, segment) {
+            Some(next) => timestamp = next,
+            None => break,
+        }
+    }
+
+    Ok(match extracted {
+        Some(tile) => TileAttempt::Extracted(tile),
+        None => TileAttempt::Missing(failure),
+    })
+}
+
+/// Write a plain black tile to stand in for a frame that couldn't be extracted.
+pub fn write_blank_tile(output_file: &Path, (width, height): (u32, u32), sandbox: Option<&Sandbox>) -> Result<()> {
+    exec::run(sandbox::command("ffmpeg", sandbox)
+        .args([
+            "-f", "lavfi",
+            "-i", &format!("color=c=black:s={}x{}", width, height),
+            "-frames:v", "1",
+            "-y",
+        ])
+        .arg(output_file))
+        .with_context(|| "Failed to create placeholder tile")?;
+    Ok(())
+}
+
+/// Check if the frame extracted at a timestamp is black using FFmpeg's blackframe filter.
+pub fn is_black_frame(src: &Source, timestamp: f64, timeout: Option<Duration>) -> Result<bool> {
+    let output = exec::output_with_timeout(sandbox::command("ffmpeg", src.sandbox)
+        .args(src.input_args(timestamp))
+        .args([
+            "-map", &src.map(),
+            "-t", "1",
+            "-vf", "blackframe=99:32",
+            "-an",
+            "-f", "null",
+            "-",
+        ]), timeout)
+        .with_context(|| "Failed to run ffmpeg for blackframe detection")?;
+
+    Ok(String::from_utf8_lossy(&output.stderr).contains("blackframe"))
+}
+
+/// Write copies of `image` scaled to each of `widths` next to it, all from
+/// one decode of it.
+pub fn write_sizes(image: &Path, widths: &[u32], sandbox: Option<&Sandbox>) -> Result<()> {
+    if widths.is_empty() {
+        return Ok(());
+    }
+    let labels: String = (0..widths.len()).map(|i| format!("[s{}]", i)).collect();
+    let mut graph = format!("[0:v]split={}{}", widths.len(), labels);
+    for (i, width) in widths.iter().enumerate() {
+        graph += &format!(";[s{}]scale={}:-2[o{}]", i, width, i);
+    }
+
+    let mut cmd = sandbox::command("ffmpeg", sandbox);
+    cmd.args(["-v", "error", "-i"]).arg(image).args(["-filter_complex", &graph]);
+    for (i, &width) in widths.iter().enumerate() {
+        cmd.args(["-map", &format!("[o{}]", i), "-q:v", "2", "-update", "1", "-y"])
+            .arg(naming::sized_output_path(image, width));
+    }
+    exec::run(&mut cmd).with_context(|| format!("Failed to write scaled copies of {}", image.display()))?;
+    Ok(())
+}
+
+/// Smallest output size that can plausibly be a real mosaic.
+const MIN_OUTPUT_BYTES: u64 = 1024;
+
+/// Check that a finished output exists, isn't trivially small and decodes
+/// cleanly as an image, so an FFmpeg pass that "succeeded" without producing
+/// anything usable is reported as a failure.
+pub fn validate_output(output_image: &Path, sandbox: Option<&Sandbox>) -> Result<()> {
+    let size = fs::metadata(output_image)
+        .with_context(|| format!("Output {} was not written", output_image.display()))?
+        .len();
+    if size < MIN_OUTPUT_BYTES {
+        anyhow::bail!("Output {} is only {} bytes", output_image.display(), size);
+    }
+    check_decodes(output_image, sandbox)
+}
+
+/// Check that `output_image` decodes without errors.
+fn check_decodes(output_image: &Path, sandbox: Option<&Sandbox>) -> Result<()> {
+    let decode = exec::run(sandbox::command("ffmpeg", sandbox)
+        .args(["-v", "error", "-i"])
+        .arg(output_image)
+        .args(["-f", "null", "-"]))
+        .with_context(|| format!("Output {} does not decode as an image", output_image.display()))?;
+    let errors = exec::tail(&decode.stderr, 3);
+    if !errors.is_empty() {
+        anyhow::bail!("Output {} is damaged: {}", output_image.display(), errors);
+    }
+
+    Ok(())
+}
+
+/// Error for files that are deliberately not processed, with the reason.
+#[derive(Debug)]
+pub struct Skip(pub String);
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Skip {}
+
+/// Turn a `Skip` error into `Outcome::Skipped`, passing other errors through.
+pub fn skipped(err: anyhow::Error) -> Result<Outcome> {
+    match err.downcast::<Skip>() {
+        Ok(Skip(reason)) => Ok(Outcome::Skipped(reason)),
+        Err(e) => Err(e),
+    }
+}
+
+/// A video being turned into a sheet: locked, probed, and with scratch space
+/// (and a sandbox, if enabled) set up.
+pub struct Job {
+    pub video_path: String,
+    pub output_image: PathBuf,
+    pub info: MediaInfo,
+    /// Duration that is safe to sample within.
+    pub duration: f64,
+    pub warnings: Vec<String>,
+    /// Dominant colors of the poster frame, when asked for.
+    pub colors: Vec<Swatch>,
+    /// Blurhash of the poster frame, when asked for.
+    pub blurhash: Option<String>,
+    /// Perceptual hashes of the tiles, when they're recorded in the index.
+    pub frame_hashes: Vec<FrameHash>,
+    stream: usize,
+    frame_format: FrameFormat,
+    seek: SeekMode,
+    hwaccel: Option<String>,
+    /// Width tiles are extracted at, when narrower than the video.
+    tile_width: Option<u32>,
+    /// Times of the frames asked for with `--at-frames`.
+    frame_times: Option<Vec<f64>>,
+    // Field order matters: the sandbox binds the temp dir, and the lock must
+    // outlive everything that writes the output.
+    sandbox: Option<Sandbox>,
+    temp_dir: Scratch,
+    _lock: OutputLock,
+}
+
+/// A job's scratch directory, removed when it's dropped unless it's kept
+/// for debugging (`--keep-temp`).
+struct Scratch {
+    dir: TempDir,
+    keep: bool,
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        if self.keep {
+            self.dir.disable_cleanup(true);
+            log::info!("Scratch files kept in {}", self.dir.path().display());
+        }
+    }
+}
+
+impl Job {
+    /// Prepare `video_path` for processing into `output_image`. Files that
+    /// shouldn't be processed fail with a `Skip` error.
+    pub fn open(video_path: &str, output_image: &Path, opts: &Options) -> Result<Job> {
+        let Some(lock) = OutputLock::try_acquire(output_image)? else {
+            return Err(Skip(format!("{} is being generated by another instance", output_image.display())).into());
+        };
+
+        let temp_dir = match &opts.temp_dir {
+            Some(dir) => tempfile::Builder::new().prefix("thumbnailer-").tempdir_in(dir)
+                .with_context(|| format!("Failed to create a scratch directory in {}", dir.display()))?,
+            None => tempdir()?,
+        };
+        let sandbox = if opts.sandbox {
+            let mut sandbox = Sandbox::new(vec![
+                temp_dir.path().to_path_buf(),
+                sandbox::output_dir(output_image)?,
+            ])
+            .reading(Path::new(video_path));
+            if let Some(font) = (!opts.no_text).then(|| font::path(opts).ok()).flatten() {
+                sandbox = sandbox.reading(Path::new(&font));
+            }
+            Some(sandbox)
+        } else {
+            None
+        };
+
+        let info = probe::probe(video_path, sandbox.as_ref(), &opts.retry)?;
+        if let Some(reason) = info.encryption() {
+            return Err(Skip(format!("encrypted/DRM-protected content ({})", reason)).into());
+        }
+        let stream = match info.video_stream() {
+            Some(stream) => stream.index,
+            // ffprobe printing no streams at all is it failing to read the
+            // file, which fails it when we can tell why rather than skipping it.
+            None => match exec::classify(&info.diagnostics).filter(|_| info.streams.is_empty()) {
+                Some(category) => return Err(anyhow::Error::new(category).context("ffprobe couldn't read the file")),
+                None => return Err(Skip(info.missing_video_reason()).into()),
+            },
+        };
+        let mut warnings = Vec::new();
+        let (duration, duration_warning) = info.duration().ok_or_else(|| {
+            let error = anyhow::anyhow!("Failed to get video duration with ffprobe: {}", info.diagnostics.trim());
+            match exec::classify(&info.diagnostics) {
+                Some(category) => anyhow::Error::new(category).context(error.to_string()),
+                None => error,
+            }
+        })?;
+        warnings.extend(duration_warning);
+        let duration = if opts.partial {
+            let recorded = (duration - growing::RECORDING_TAIL).max(duration / 2.0);
+            warnings.push(format!("still being recorded: sheeted up to {}", text::format_time(recorded)));
+            recorded
+        } else {
+            duration
+        };
+        let frame_times = match (&opts.at_frames, &opts.edl) {
+            (Some(frames), _) => Some(frame_times(&info, frames, duration, &mut warnings)?),
+            (None, Some(cuts)) => Some(cut_times(&info, cuts, duration, &mut warnings)?),
+            (None, None) => None,
+        };
+
+        Ok(Job {
+            video_path: video_path.to_string(),
+            output_image: output_image.to_path_buf(),
+            info,
+            duration,
+            warnings,
+            colors: Vec::new(),
+            blurhash: None,
+            frame_hashes: Vec::new(),
+            stream,
+            frame_format: opts.frame_format,
+            seek: opts.seek,
+            hwaccel: opts.hwaccel.clone(),
+            tile_width: None,
+            frame_times,
+            sandbox,
+            temp_dir: Scratch { dir: temp_dir, keep: opts.keep_temp },
+            _lock: lock,
+        })
+    }
+
+    /// How to run tools against the video.
+    pub fn source(&self) -> Source<'_> {
+        Source {
+            path: &self.video_path,
+            stream: self.stream,
+            start_time: self.info.start_time(),
+            sandbox: self.sandbox.as_ref(),
+            frame_format: self.frame_format,
+            width: self.tile_width,
+            seek: self.seek,
+            hwaccel: self.hwaccel.as_deref(),
+        }
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    /// Let the job's tools read `path` too, under `--sandbox`.
+    pub fn allow_reading(&mut self, path: &Path) {
+        self.sandbox = self.sandbox.take().map(|sandbox| sandbox.reading(path));
+    }
+
+    /// Scratch directory, removed when the job is dropped.
+    pub fn temp_path(&self) -> &Path {
+        self.temp_dir.dir.path()
+    }
+
+    /// Where tile `i` of the final sequence lives. Tiles must be numbered
+    /// without gaps, or the tile pass silently stops at the first hole.
+    pub fn tile_path(&self, i: usize) -> PathBuf {
+        self.temp_path().join(format!("tile_{:03}.{}", i, self.frame_format.extension()))
+    }
+
+    /// Sequence pattern matching every `tile_path`.
+    pub fn tile_pattern(&self) -> PathBuf {
+        self.temp_path().join(format!("tile_%03d.{}", self.frame_format.extension()))
+    }
+
+    /// `opts` with the config's `[overrides.*]` for this video applied, if
+    /// any are; frames are then extracted in the format, and with the seek
+    /// and decoding, they ask for.
+    pub fn media_options(&mut self, opts: &Options) -> Result<Option<Options>> {
+        let Some((file_opts, applied)) = opts.for_media(Path::new(&self.video_path), &self.info)? else {
+            return Ok(None);
+        };
+        log::debug!("{}: using [overrides.{}]", self.video_path, applied.join("], [overrides."));
+        self.frame_format = file_opts.frame_format;
+        self.seek = file_opts.seek;
+        self.hwaccel = file_opts.hwaccel.clone();
+        Ok(Some(file_opts))
+    }
+
+    /// Extension of the frames extracted for this job.
+    pub fn frame_extension(&self) -> &'static str {
+        self.frame_format.extension()
+    }
+
+    /// Re-shape the requested grid for the video's orientation, unless
+    /// disabled, after sizing it for the video's duration with `--grid auto`;
+    /// or lay its frames out in a single row or column as a filmstrip.
+    pub fn fit_grid(&self, rows: usize, cols: usize, total_frames: usize, opts: &Options) -> (usize, usize, usize) {
+        let (rows, cols, total_frames) = match self.info.duration() {
+            Some((duration, _)) if opts.grid == GridMode::Auto && self.frame_times.is_none() => {
+                let side = auto_grid_side(duration, opts.auto_grid_sides);
+                (side, side, side * side)
+            }
+            _ => (rows, cols, total_frames),
+        };
+        match opts.layout {
+            Layout::Filmstrip => return (1, total_frames, total_frames),
+            Layout::VerticalFilmstrip => return (total_frames, 1, total_frames),
+            Layout::Grid | Layout::Hero => {}
+        }
+        let (rows, cols, frames) = match self.info.display_size() {
+            Some((w, h)) if !opts.keep_grid && h > 0 => {
+                fit_grid_to_aspect(rows, cols, total_frames, w as f64 / h as f64)
+            }
+            _ => (rows, cols, total_frames),
+        };
+        if self.frame_times.is_some() {
+            // Every frame asked for is shown, however the grid was re-shaped.
+            return (rows.max(total_frames.div_ceil(cols)), cols, total_frames);
+        }
+        (rows, cols, frames)
+    }
+
+    /// Extract tiles for `sheet` at `--tile-width`, narrowed to keep the
+    /// sheet within `--max-width`, unless the video is narrower.
+    pub fn scale_tiles(&mut self, sheet: &Sheet, opts: &Options) {
+        let own = self.info.display_size().map(|(w, _)| w);
+        self.tile_width = fitted_tile_width(sheet, opts.tile_width, opts.max_width, own);
+    }
+
+    /// Width tiles are extracted at, if they're scaled.
+    pub fn tile_width(&self) -> Option<u32> {
+        self.tile_width
+    }
+
+    /// Size of the tiles as extracted.
+    pub fn tile_size(&self) -> (u32, u32) {
+        let (w, h) = self.info.display_size().unwrap_or((320, 180));
+        match self.tile_width {
+            Some(width) => (width, ((h as f64 * width as f64 / w as f64 / 2.0).round() as u32).max(1) * 2),
+            None => (w, h),
+        }
+    }
+
+    /// Fail early if there isn't room for `total_frames` tiles, at the size
+    /// they're extracted (so after `scale_tiles`), and the sheet.
+    pub fn check_disk_space(&self, total_frames: usize) -> Result<()> {
+        if self.info.display_size().is_some() {
+            let (w, h) = self.tile_size();
+            diskspace::check(
+                self.temp_path(),
+                &sandbox::output_dir(&self.output_image)?,
+                diskspace::estimate(w, h, total_frames, self.frame_format.bytes_per_pixel()),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Copy everything in the scratch directory (extracted frames, tiles,
+    /// intermediate mosaics and drawn text) into `dir`.
+    pub fn save_artifacts(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(self.temp_path())? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), dir.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The outcome for a job whose sheet, made of `tiles`, has been written.
+    pub fn finish(self, tiles: Vec<Tile>) -> Outcome {
+        Outcome::Created(Box::new(Created {
+            video: PathBuf::from(self.video_path),
+            output: self.output_image,
+            info: self.info,
+            tiles,
+            warnings: self.warnings,
+            colors: self.colors,
+            blurhash: self.blurhash,
+            frame_hashes: self.frame_hashes,
+        }))
+    }
+
+    /// The `count` segments the video's tiles are sampled from.
+    pub fn segments(&self, count: usize, opts: &Options) -> Vec<Segment> {
+        if let Some(times) = &self.frame_times {
+            return FixedSelector(times).segments(&PlanInput {
+                duration: self.duration,
+                count,
+                start_offset: None,
+                skip: &[],
+                chapters: &[],
+            });
+        }
+        let chapters: Vec<f64> = if opts.chapter_titles {
+            self.info.chapters.iter()
+                .filter_map(|c| Some(c.start_time? - self.info.start_time()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        EvenSelector.segments(&PlanInput {
+            duration: self.duration,
+            count,
+            start_offset: opts.start_offset,
+            skip: &opts.skip,
+            chapters: &chapters,
+        })
+    }
+}
+
+/// Times of frame numbers `frames` (counted from 0) of the video, from its
+/// frame rate.
+///
+/// Each time is a quarter frame early, so the seek lands on the frame itself
+/// whichever way the time is rounded.
+fn frame_times(info: &MediaInfo, frames: &[u64], duration: f64, warnings: &mut Vec<String>) -> Result<Vec<f64>> {
+    let stream = info.video_stream();
+    let Some(rate) = stream.and_then(|s| s.frame_rate()) else {
+        anyhow::bail!("--at-frames needs the video's frame rate, which ffprobe doesn't report");
+    };
+    if stream.is_some_and(|s| !s.avg_frame_rate.is_empty() && s.avg_frame_rate != s.r_frame_rate) {
+        warnings.push(format!(
+            "variable frame rate video: frame numbers are converted at {:.3} fps and may be off", rate
+        ));
+    }
+    let last = (duration * rate).floor() as u64;
+    if let Some(&past) = frames.iter().find(|&&f| f > last) {
+        warnings.push(format!("frame {} is past the end (about frame {}); the last frame is shown", past, last));
+    }
+    Ok(frames.iter().map(|&f| ((f as f64 - 0.25) / rate).max(0.0)).collect())
+}
+
+/// Times of the in points of an edit decision list's `cuts`, timecodes
+/// counted from the video's start timecode.
+fn cut_times(info: &MediaInfo, cuts: &[edl::Cut], duration: f64, warnings: &mut Vec<String>) -> Result<Vec<f64>> {
+    let timecode = Timecode::for_video(info);
+    let mut times = Vec::with_capacity(cuts.len());
+    for cut in cuts {
+        let time = match (cut.seconds(), &timecode) {
+            (Some(secs), _) => secs,
+            (None, Some(timecode)) => timecode.seek_time(&cut.at)
+                .with_context(|| format!("--edl: {} isn't a timecode of this video", cut.at))?,
+            (None, None) => anyhow::bail!("--edl timecodes need the video's frame rate, which ffprobe doesn't report"),
+        };
+        times.push(time);
+    }
+    if let Some(i) = times.iter().position(|&t| t < -0.5 || t > duration) {
+        warnings.push(format!(
+            "cut {} at {} is outside the video (does the EDL use its timecode?); the nearest frame is shown",
+            cuts[i].label, cuts[i].at
+        ));
+    }
+    Ok(times.into_iter().map(|t| t.max(0.0)).collect())
+}
+
+/// Create a thumbnail mosaic from video and overlay metadata text.
+pub fn create_thumbnail_mosaic(
+    video_path: &str,
+    output_image: &str,
+    rows: usize,
+    cols: usize,
+    total_frames: usize,
+    opts: &Options,
+) -> Result<Outcome> {
+    exec::with_deadline(opts.timeout, || {
+        let mut job = match Job::open(video_path, Path::new(output_image), opts) {
+            Ok(job) => job,
+            Err(e) => return skipped(e),
+        };
+        let file_opts = job.media_options(opts)?;
+        let (opts, rows, cols, total_frames) = match &file_opts {
+            Some(file_opts) => (file_opts, file_opts.rows, file_opts.cols, file_opts.frames),
+            None => (opts, rows, cols, total_frames),
+        };
+
+        match build_sheet(&mut job, Path::new(output_image), rows, cols, total_frames, opts) {
+            Ok(tiles) => Ok(job.finish(tiles)),
+            Err(e) => {
+                if let Some(dir) = opts.debug_dir.as_deref().filter(|_| keeps_artifacts(&e)) {
+                    let debug = naming::video_dir(dir, Path::new(video_path));
+                    if let Err(copy_error) = job.save_artifacts(&debug) {
+                        log::warn!("{}: couldn't keep the job's files in {}: {:#}", video_path, debug.display(), copy_error);
+                    }
+                }
+                Err(e)
+            }
+        }
+    })
+}
+
+/// Width to extract `sheet`'s tiles at: `tile_width`, narrowed so its
+/// columns, gaps and margins fit in `max_width`, and even (as 4:2:0 JPEG
+/// needs). `None`, for full size, when that's no narrower than the video's
+/// `own` width.
+fn fitted_tile_width(sheet: &Sheet, tile_width: Option<u32>, max_width: Option<u32>, own: Option<u32>) -> Option<u32> {
+    let cols = sheet.cols.max(1) as u32;
+    let fitted = max_width.map(|max| {
+        let frame = 2 * sheet.margin + sheet.spacing * (cols - 1);
+        max.saturating_sub(frame) / cols
+    });
+    let width = [tile_width, fitted].into_iter().flatten().min()?;
+    let width = (width / 2 * 2).max(16);
+    match own {
+        Some(own) if width >= own => None,
+        _ => Some(width),
+    }
+}
+
+/// Whether a failure is worth keeping the job's files for; not when the
+/// user stopped the run or the disk is full.
+fn keeps_artifacts(err: &anyhow::Error) -> bool {
+    !exec::is_interrupted(err) && !diskspace::is_low_disk_space(err)
+}
+
+fn build_sheet(job: &mut Job, output_image: &Path, rows: usize, cols: usize, total_frames: usize, opts: &Options) -> Result<Vec<Tile>> {
+    let (rows, cols, total_frames) = job.fit_grid(rows, cols, total_frames, opts);
+    job.scale_tiles(&opts.sheet(rows, cols, total_frames), opts);
+    job.check_disk_space(total_frames)?;
+
+    let tiles = match &opts.keep_frames {
+        Some(dir) => match kept::load(dir, job, total_frames, opts)? {
+            Some(tiles) => {
+                log::debug!("{}: reusing the frames kept in {}", job.video_path, dir.display());
+                tiles
+            }
+            None => {
+                let tiles = extract_tiles(job, total_frames, opts)?;
+                kept::save(dir, job, &tiles, opts)?;
+                tiles
+            }
+        },
+        None => extract_tiles(job, total_frames, opts)?,
+    };
+    if opts.colors > 0 {
+        progress::stage("colors");
+        job.colors = palette::dominant(job, &tiles, opts.colors)?;
+    }
+    if opts.blurhash {
+        progress::stage("blurhash");
+        job.blurhash = Some(blurhash::of_poster(job, &tiles)?);
+    }
+    if opts.hash_frames {
+        progress::stage("hashing");
+        job.frame_hashes = dedupe::tile_hashes(job, &tiles)?;
+    }
+    if opts.outputs.contains(&Artifact::Sheet) {
+        compose_sheet(job, &opts.sheet(rows, cols, total_frames), &tiles, output_image, opts)?;
+        if !opts.post.is_empty() {
+            progress::stage("post-processing");
+            post::run_all(&opts.post, output_image)?;
+        }
+        write_sizes(output_image, &opts.sizes, job.sandbox())?;
+    }
+    if opts.outputs.contains(&Artifact::Poster) {
+        progress::stage("poster");
+        poster::write_poster(job, &tiles, &poster::poster_output_path(output_image))?;
+    }
+    if opts.outputs.contains(&Artifact::Storyboard) {
+        progress::stage("storyboard");
+        storyboard::write(job, &tiles, cols, output_image)?;
+    }
+    if opts.outputs.contains(&Artifact::Preview) {
+        progress::stage("preview");
+        preview::write(job, &tiles, output_image, opts)?;
+    }
+    if opts.outputs.contains(&Artifact::Thumbnails) {
+        progress::stage("thumbnails");
+        thumbnails::write(job, &opts.thumbnail_track, output_image)?;
+    }
+    if let Some(size) = opts.square {
+        let square = poster::square_output_path(output_image);
+        poster::write_square(job, &tiles, size, opts.square_crop, &square)?;
+        write_sizes(&square, &opts.sizes, job.sandbox())?;
+    }
+    Ok(tiles)
+}
+
+/// Print the commands a sheet of `video_path` would start with, without
+/// running FFmpeg: the ffprobe command (which does run, since the frame
+/// times depend on the duration) and the extraction of each tile's first
+/// attempt. Returns why the video would be skipped, if it would.
+pub fn plan(video_path: &str, output_image: &Path, rows: usize, cols: usize, total_frames: usize, opts: &Options) -> Result<Option<String>> {
+    let files: Vec<String> = opts.outputs.iter().map(|a| a.path(output_image).display().to_string()).collect();
+    println!("Would write {} from {}", files.join(", "), video_path);
+    // Output directories aren't created in a dry run; the job is set up
+    // beside a scratch file instead.
+    let scratch = tempdir()?;
+    let job_output = match output_image.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => scratch.path().join("sheet.jpg"),
+        _ => output_image.to_path_buf(),
+    };
+    let mut job = match Job::open(video_path, &job_output, opts) {
+        Ok(job) => job,
+        Err(e) => return skipped(e).map(|outcome| match outcome {
+            Outcome::Skipped(reason) => Some(reason),
+            Outcome::Created(_) => None,
+        }),
+    };
+
+    println!("  {}", exec::command_line(&probe::command(video_path, job.sandbox())));
+    let file_opts = job.media_options(opts)?;
+    let (opts, rows, cols, total_frames) = match &file_opts {
+        Some(file_opts) => (file_opts, file_opts.rows, file_opts.cols, file_opts.frames),
+        None => (opts, rows, cols, total_frames),
+    };
+    let (rows, cols, total_frames) = job.fit_grid(rows, cols, total_frames, opts);
+    job.scale_tiles(&opts.sheet(rows, cols, total_frames), opts);
+    println!("  {} tiles on a {}x{} grid", total_frames, cols, rows);
+    let src = job.source();
+    for (i, segment) in job.segments(total_frames, opts).iter().enumerate() {
+        let thumb = job.temp_path().join(format!("thumb_{:03}.{}", i, job.frame_extension()));
+        println!("  {}", exec::command_line(&frame_command(&src, segment.0, &thumb)));
+    }
+    println!("  then the composition passes, which depend on the frames that come out");
+    for step in opts.post.iter().filter(|_| opts.outputs.contains(&Artifact::Sheet)) {
+        println!("  then {}", step.describe(output_image));
+    }
+    Ok(None)
+}
+
+/// Extract evenly spaced tiles into the job's tile sequence.
+pub fn extract_tiles(job: &mut Job, total_frames: usize, opts: &Options) -> Result<Vec<Tile>> {
+    let segments = job.segments(total_frames, opts);
+    let src = job.source();
+
+    // === Extract evenly spaced thumbnails with retry ===
+    let thumb_path = |i: usize| job.temp_path().join(format!("thumb_{:03}.{}", i, job.frame_extension()));
+    let mut slots = Vec::with_capacity(total_frames);
+    for (i, &segment) in segments.iter().enumerate() {
+        progress::stage(format!("frame {}/{}", i + 1, segments.len()));
+        slots.push(extract_tile(&src, segment.0, segment, &thumb_path(i), opts.frame_timeout)?);
+    }
+
+    // Every grid position needs an image. Timed-out tiles borrow their
+    // nearest neighbor's frame; tiles with nothing decodable are padded.
+    let extracted: Vec<usize> = (0..slots.len())
+        .filter(|&i| matches!(slots[i], TileAttempt::Extracted(_)))
+        .collect();
+    if extracted.is_empty() {
+        let failure = slots.iter().find_map(|slot| match slot {
+            TileAttempt::Missing(failure) => *failure,
+            _ => None,
+        });
+        return Err(match failure {
+            Some(category) => anyhow::Error::new(category).context("No frames could be extracted"),
+            None => anyhow::anyhow!("No frames could be extracted"),
+        });
+    }
+    let tile_size = job.tile_size();
+
+    for &i in &extracted {
+        fs::rename(thumb_path(i), job.tile_path(i))?;
+    }
+
+    let mut warnings = Vec::new();
+    let mut tiles = Vec::with_capacity(total_frames);
+    for (i, slot) in slots.iter().enumerate() {
+        match slot {
+            TileAttempt::Extracted(tile) => tiles.push(*tile),
+            TileAttempt::TimedOut => {
+                warnings.push(format!(
+                    "tile {} abandoned after {:.0}s without a frame; using a neighboring frame",
+                    i + 1, opts.frame_timeout.unwrap_or_default().as_secs_f64()
+                ));
+                let nearest = *extracted.iter().min_by_key(|&&j| j.abs_diff(i)).unwrap();
+                fs::copy(job.tile_path(nearest), job.tile_path(i))?;
+                if let TileAttempt::Extracted(tile) = slots[nearest] {
+                    tiles.push(tile);
+                }
+            }
+            TileAttempt::Missing(failure) => {
+                let why = failure.map(|c| format!(" ({})", c)).unwrap_or_default();
+                warnings.push(format!("tile {}: no frame could be decoded{}; padded with a blank tile", i + 1, why));
+                write_blank_tile(&job.tile_path(i), tile_size, src.sandbox)?;
+                let t = segments[i].0;
+                tiles.push(Tile { requested: t, shown: t, blank: true });
+            }
+        }
+    }
+
+    for (i, tile) in tiles.iter().enumerate() {
+        // Sparse or variable frame rate sources (screen recordings) can have no
+        // frame anywhere near the requested time.
+        if !tile.blank && (tile.shown - tile.requested).abs() > MAX_TILE_DRIFT {
+            warnings.push(format!(
+                "tile {} shows the frame at {:.2}s instead of the requested {:.2}s",
+                i + 1, tile.shown, tile.requested
+            ));
+        }
+    }
+
+    job.warnings.extend(warnings);
+    Ok(tiles)
+}
+
+/// How a sheet's tiles are laid out and labeled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sheet {
+    pub rows: usize,
+    pub cols: usize,
+    /// Number of tiles; fewer than `rows * cols` leaves the last cells empty.
+    pub frames: usize,
+    /// Gap between tiles, in pixels.
+    pub spacing: u32,
+    /// Gap between the tiles and the edge of the sheet, in pixels.
+    pub margin: u32,
+    /// Whether the file name, size and resolution are drawn on the sheet.
+    pub overlay: bool,
+    pub font_size: u32,
+    /// Width and height frames are scaled to before they're tiled (-2 for
+    /// the side kept in proportion); full size when `None`.
+    pub tile_scale: Option<(i32, i32)>,
+    /// The tile shown large above the grid, if any.
+    pub hero: Option<usize>,
+}
+
+impl Sheet {
+    /// A `rows x cols` sheet of `frames` tiles in the default style.
+    pub fn grid(rows: usize, cols: usize, frames: usize) -> Sheet {
+        Sheet { rows, cols, frames, spacing: 0, margin: 0, overlay: true, font_size: 96, tile_scale: None, hero: None }
+    }
+
+    /// This sheet styled as a filmstrip: its frames scaled to `size` pixels
+    /// across the strip (tall, unless it's `vertical`), and no overlay.
+    pub fn filmstrip(self, vertical: bool, size: u32) -> Sheet {
+        let size = (size as i32 / 2 * 2).max(2);
+        let tile_scale = if vertical { (size, -2) } else { (-2, size) };
+        Sheet { overlay: false, font_size: (size as u32 / 5).max(12), tile_scale: Some(tile_scale), ..self }
+    }
+}
+
+/// How a sheet's tiles are arranged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    /// Rows and columns of full-size frames.
+    Grid,
+    /// One row of small frames, for file listings and galleries.
+    Filmstrip,
+    /// One column of small frames.
+    VerticalFilmstrip,
+    /// The most detailed frame large at the top, above the grid.
+    Hero,
+}
+
+/// Tile the job's extracted frames (showing `tiles`) into `sheet`, overlay
+/// the metadata text and write the validated result to `output_image`.
+pub fn compose_sheet(job: &mut Job, sheet: &Sheet, tiles: &[Tile], output_image: &Path, opts: &Options) -> Result<()> {
+    let title = if sheet.overlay {
+        overlay_text(&job.video_path, &job.info, opts)?
+    } else {
+        String::new()
+    };
+    let hero = match opts.layout {
+        Layout::Hero => Some(poster::poster_tile(job, tiles)?),
+        _ => None,
+    };
+    let sheet = &Sheet { hero, ..*sheet };
+    let texts = tile_texts(&job.info, tiles, opts);
+    let timeline = timeline(&job.info, job.duration, tiles, opts);
+    compose_titled(job, sheet, &title, &texts, timeline.as_ref(), output_image, opts)
+}
+
+/// Where a sheet's tiles (and the video's chapters) sit in the video, drawn
+/// as a bar under the grid.
+pub struct Timeline {
+    pub duration: f64,
+    /// Times the tiles show, in seconds.
+    pub tiles: Vec<f64>,
+    /// Chapter starts after the first, in seconds.
+    pub chapters: Vec<f64>,
+}
+
+/// The timeline for `tiles` of a video, when one was asked for.
+pub fn timeline(info: &MediaInfo, duration: f64, tiles: &[Tile], opts: &Options) -> Option<Timeline> {
+    if !opts.timeline || duration <= 0.0 {
+        return None;
+    }
+    Some(Timeline {
+        duration,
+        tiles: tiles.iter().filter(|t| !t.blank).map(|t| t.shown).collect(),
+        chapters: info.chapters.iter()
+            .filter_map(|c| Some(c.start_time? - info.start_time()))
+            .filter(|&t| t > 0.0 && t < duration)
+            .collect(),
+    })
+}
+
+/// Chapter boundaries stand out from the tile markers in this color.
+const CHAPTER_MARK_COLOR: &str = "#f0a030";
+
+impl Timeline {
+    /// Filters that add the bar below the image, in `theme`'s colors. The
+    /// text layer only gets the room for it.
+    ///
+    /// Sizes are expressions of the image width, as the sheet's size isn't
+    /// known until FFmpeg has tiled it: the bar takes `A` = 2.5% of the width
+    /// in height, and positions run between `A/2` from either edge.
+    fn filters(&self, theme: &Theme, paint: Paint) -> String {
+        let area = "2*trunc(iw/80)";
+        let at = |t: f64| format!("{a}/2+(iw-{a})*{:.5}", (t / self.duration).clamp(0.0, 1.0), a = area);
+        let text = text::escape_filter_value(&theme.text);
+        // The track is the text color, faded; an alpha already in it is replaced.
+        let track = text::escape_filter_value(&format!("{}@0.3", theme.text.split('@').next().unwrap_or_default()));
+
+        let pad = format!("pad=iw:ih+{a}:0:0:color={}", paint.fill(&theme.background), a = area);
+        if !paint.draws_frames() {
+            return pad;
+        }
+        let mut filters = vec![
+            pad,
+            format!("drawbox=x={a}/2:y=ih-{a}*5/8:w=iw-{a}:h={a}/4:color={}:t=fill", track, a = area),
+        ];
+        for &t in &self.chapters {
+            filters.push(format!(
+                "drawbox=x={}:y=ih-{a}*7/8:w={a}/16+2:h={a}*3/4:color={}:t=fill",
+                at(t), CHAPTER_MARK_COLOR, a = area
+            ));
+        }
+        for &t in &self.tiles {
+            filters.push(format!(
+                "drawbox=x={}-{a}/8:y=ih-{a}*3/4:w={a}/4:h={a}/2:color={}:t=fill",
+                at(t), text, a = area
+            ));
+        }
+        filters.join(",")
+    }
+}
+
+/// Text added to one tile of a sheet.
+#[derive(Clone, Debug, Default)]
+pub struct TileText {
+    /// Burned into the bottom of the frame (the timecode).
+    pub label: String,
+    /// Burned into a corner of the frame (the time it was taken from, and
+    /// its number and position).
+    pub stamp: String,
+    /// Written in a band under the frame (the chapter title).
+    pub caption: String,
+}
+
+/// A corner of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Where and how a tile's time and number are burned in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CornerLabel {
+    pub corner: Corner,
+    /// Text height, as a percentage of the frame's.
+    pub size: f64,
+    /// Of the text, and (halved) of the box behind it.
+    pub opacity: f64,
+}
+
+impl CornerLabel {
+    /// drawtext's position and look for the label; at the top of its side
+    /// when the bottom is `taken` by the timecode.
+    fn style(self, taken: bool) -> String {
+        let margin = "h/30";
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin.to_string(),
+            Corner::TopRight | Corner::BottomRight => format!("w-tw-{}", margin),
+        };
+        let y = match self.corner {
+            Corner::BottomLeft | Corner::BottomRight if !taken => format!("h-th-{}", margin),
+            _ => margin.to_string(),
+        };
+        format!(
+            "fontsize=h*{:.4}:fontcolor=white@{:.2}:box=1:boxcolor=black@{:.2}:boxborderw=4:x={}:y={}",
+            self.size / 100.0, self.opacity, self.opacity / 2.0, x, y
+        )
+    }
+}
+
+/// Longest chapter title (in characters) under a tile before it's shortened.
+const MAX_CAPTION_LENGTH: usize = 40;
+
+/// The text added to each of `tiles`, or nothing when tiles go unlabeled.
+pub fn tile_texts(info: &MediaInfo, tiles: &[Tile], opts: &Options) -> Vec<TileText> {
+    let timecode = opts.timecode.then(|| Timecode::for_video(info)).flatten();
+    let stamped = opts.tile_timestamps || opts.tile_index;
+    if timecode.is_none() && !stamped && !opts.chapter_titles && opts.edl.is_none() {
+        return Vec::new();
+    }
+    let texts: Vec<TileText> = tiles.iter().enumerate()
+        .map(|(i, tile)| TileText {
+            label: match &timecode {
+                Some(timecode) if !tile.blank => timecode.at(tile.shown),
+                _ => String::new(),
+            },
+            stamp: match tile.blank {
+                false if stamped => stamp(tile, i, tiles.len(), info, opts),
+                _ => String::new(),
+            },
+            // An EDL's tiles are its cuts, in order.
+            caption: match (opts.edl.as_ref().and_then(|cuts| cuts.get(i)), info.chapter_at(tile.shown)) {
+                (Some(cut), _) => text::display_name(&cut.label, MAX_CAPTION_LENGTH),
+                (None, Some(title)) if opts.chapter_titles => text::display_name(&title, MAX_CAPTION_LENGTH),
+                _ => String::new(),
+            },
+        })
+        .collect();
+    // Chapter titles were asked for but the video has none.
+    if texts.iter().all(|t| t.label.is_empty() && t.stamp.is_empty() && t.caption.is_empty()) {
+        return Vec::new();
+    }
+    texts
+}
+
+/// The time (`01:02:03`) and number and position (`5/9 — 55%`) of tile `i`
+/// of `count`, as asked for.
+fn stamp(tile: &Tile, i: usize, count: usize, info: &MediaInfo, opts: &Options) -> String {
+    let mut parts = Vec::new();
+    if opts.tile_timestamps {
+        parts.push(text::format_clock(tile.shown));
+    }
+    if opts.tile_index {
+        let mut index = format!("{}/{}", i + 1, count);
+        if let Some((duration, _)) = info.duration() {
+            index += &format!(" — {:.0}%", (tile.shown / duration * 100.0).clamp(0.0, 100.0));
+        }
+        parts.push(index);
+    }
+    parts.join("  ")
+}
+
+/// The metadata line drawn on a video's sheet.
+pub fn overlay_text(video_path: &str, info: &MediaInfo, opts: &Options) -> Result<String> {
+    if let Some(title) = &opts.title {
+        return Ok(title.clone());
+    }
+    let resolution = info.resolution();
+    let filename = text::display_name(
+        &Path::new(video_path).file_name().unwrap().to_string_lossy(),
+        opts.max_name_length,
+    );
+    let filesize_mb = get_filesize_mb(video_path, &opts.retry)?;
+    let labels = opts.labels;
+    if opts.theme.banner == Banner::Header {
+        return Ok(header_text(&filename, filesize_mb, info, labels));
+    }
+    Ok(format!(
+        "{}:{} {}:{} {} {}:({})",
+        labels.file, filename,
+        labels.size, labels.number(filesize_mb, 2), labels.megabytes,
+        labels.resolution, resolution
+    ))
+}
+
+/// The header panel's lines: the file, its size and duration, then the
+/// video and audio streams.
+fn header_text(filename: &str, filesize_mb: f64, info: &MediaInfo, labels: &Labels) -> String {
+    let mut lines = vec![format!("{}: {}", labels.file, filename)];
+    let mut facts = format!("{}: {} {}", labels.size, labels.number(filesize_mb, 2), labels.megabytes);
+    if let Some((duration, _)) = info.duration() {
+        facts += &format!("   {}: {}", labels.duration, text::format_time(duration));
+    }
+    lines.push(facts);
+    let mut streams = Vec::new();
+    if let Some(video) = info.video_stream() {
+        let mut details = vec![video.codec_name.clone(), info.resolution()];
+        if let Some(fps) = video.frame_rate() {
+            details.push(format!("{} fps", labels.number(fps, if fps.fract() == 0.0 { 0 } else { 2 })));
+        }
+        details.retain(|d| !d.is_empty());
+        streams.push(format!("{}: {}", labels.video, details.join(", ")));
+    }
+    if let Some(audio) = info.streams.iter().find(|s| s.codec_type == "audio") {
+        let mut details = vec![audio.codec_name.clone(), audio.channel_layout.clone()];
+        details.retain(|d| !d.is_empty());
+        streams.push(format!("{}: {}", labels.audio, details.join(", ")));
+    }
+    if !streams.is_empty() {
+        lines.push(streams.join("   "));
+    }
+    lines.join("\n")
+}
+
+/// Like `compose_sheet`, but with `title` as the overlay text and `texts`
+/// (one per tile, or none at all) added to the tiles.
+pub fn compose_titled(
+    job: &mut Job,
+    sheet: &Sheet,
+    title: &str,
+    texts: &[TileText],
+    timeline: Option<&Timeline>,
+    output_image: &Path,
+    opts: &Options,
+) -> Result<()> {
+    // === Create mosaic ===
+    let present = (0..sheet.frames).filter(|&i| job.tile_path(i).is_file()).count();
+    if present != sheet.frames {
+        anyhow::bail!("Only {} of {} tiles were extracted", present, sheet.frames);
+    }
+
+    let (sheet, texts) = if opts.no_text {
+        (&Sheet { overlay: false, ..*sheet }, &[][..])
+    } else {
+        (sheet, texts)
+    };
+    let footer = if opts.audio_summary && !opts.no_text { audio::summary(job, opts) } else { Vec::new() };
+    let pass = Pass {
+        paint: if opts.text_layer { Paint::Frames } else { Paint::All },
+        title,
+        timeline,
+        footer: &footer,
+    };
+
+    let composed = compose_pass(job, sheet, texts, &pass, output_image, opts).and_then(|()| match opts.target_size {
+        Some(budget) => budget::fit(job, output_image, budget, opts),
+        None => Ok(()),
+    });
+    if let Err(e) = composed {
+        // Don't leave a half-written or undecodable image behind.
+        let _ = fs::remove_file(output_image);
+        return Err(e);
+    }
+
+    if opts.text_layer {
+        let layer = text_layer_path(output_image);
+        if let Err(e) = compose_pass(job, sheet, texts, &Pass { paint: Paint::Text, ..pass }, &layer, opts) {
+            let _ = fs::remove_file(&layer);
+            if !can_drop_text(&e, opts) {
+                let _ = fs::remove_file(output_image);
+                return Err(e);
+            }
+            job.warnings.push(format!("text layer not written: {:#}", e));
+        }
+    }
+
+    Ok(())
+}
+
+/// `<sheet stem>_text.png` next to the sheet.
+pub fn text_layer_path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_text.png", stem))
+}
+
+/// Which parts of a sheet a composition pass draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Paint {
+    /// Frames and text together.
+    All,
+    /// The frames, with room left where the text goes but no text.
+    Frames,
+    /// Just the text, on a transparent canvas laid out like the sheet.
+    Text,
+}
+
+impl Paint {
+    fn draws_frames(self) -> bool {
+        self != Paint::Text
+    }
+
+    fn draws_text(self) -> bool {
+        self != Paint::Frames
+    }
+
+    /// `color` for backgrounds drawn in this pass, escaped; they're
+    /// transparent in the text layer.
+    fn fill(self, color: &str) -> String {
+        text::escape_filter_value(if self == Paint::Text { "black@0" } else { color })
+    }
+
+    /// Extension of the intermediate images the pass writes: the frames'
+    /// own, or PNG to keep the layer's transparency.
+    fn extension(self, job: &Job) -> &'static str {
+        if self == Paint::Text { "png" } else { job.frame_extension() }
+    }
+
+    /// Pixel format of an image the pass writes: the sheet's chroma
+    /// subsampling for the finished sheet, the frames' format before that.
+    fn pix_fmt(self, finished: bool, opts: &Options) -> &'static str {
+        match self {
+            Paint::Text => "rgba",
+            _ if finished => opts.chroma.pix_fmt(),
+            _ => opts.frame_format.pix_fmt(),
+        }
+    }
+}
+
+/// What a composition pass adds to the frames.
+#[derive(Clone, Copy)]
+struct Pass<'a> {
+    paint: Paint,
+    title: &'a str,
+    timeline: Option<&'a Timeline>,
+    /// Lines of the audio summary, if there is one.
+    footer: &'a [String],
+}
+
+/// What to do when drawing text on a sheet keeps failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TextFallback {
+    /// Write the sheet without the text, with a warning.
+    Plain,
+    /// Fail the file.
+    Fail,
+}
+
+/// Run a composition pass, again up to `opts.pass_retries` times while it fails.
+fn retry_pass<T>(opts: &Options, mut pass: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match pass() {
+            Err(e) if attempt < opts.pass_retries && !exec::is_interrupted(&e) => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Whether a failed text pass may be skipped, leaving the sheet without it.
+fn can_drop_text(err: &anyhow::Error, opts: &Options) -> bool {
+    opts.text_fallback == TextFallback::Plain && !exec::is_interrupted(err)
+}
+
+/// Label the tiles and tile them into `output_image`, painting what `pass` says.
+fn compose_pass(job: &mut Job, sheet: &Sheet, texts: &[TileText], pass: &Pass, output_image: &Path, opts: &Options) -> Result<()> {
+    let plain_pattern = job.tile_pattern();
+    let input_pattern = if pass.paint == Paint::Text {
+        // The layer always needs its own transparent copies of the tiles.
+        let blank = vec![TileText::default(); sheet.frames];
+        let texts = if texts.is_empty() { &blank } else { texts };
+        retry_pass(opts, || label_tiles(job, texts, pass.paint, opts))?
+    } else if texts.is_empty() {
+        plain_pattern
+    } else {
+        match retry_pass(opts, || label_tiles(job, texts, pass.paint, opts)) {
+            Ok(pattern) => pattern,
+            Err(e) if can_drop_text(&e, opts) => {
+                job.warnings.push(format!("tile labels left off: {:#}", e));
+                plain_pattern
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    tile_and_overlay(job, sheet, pass, &input_pattern, output_image, opts)
+}
+
+/// Color of the tiles' drop shadows, over the background.
+const SHADOW_COLOR: &str = "black@0.6";
+
+/// How far a tile's shadow falls, down and to the right: about 1/100 of
+/// its width, and even for 4:2:0 frames.
+const SHADOW_OFFSET: &str = "2+trunc(iw/200)*2";
+
+/// Filters casting a frame's shadow onto `background`. Their labels start with `name`, which must be
+/// unique in the graph. The text layer only makes the same room for it.
+fn drop_shadow(name: &str, paint: Paint, background: &str) -> String {
+    if !paint.draws_frames() {
+        return format!("pad=iw+{o}:ih+{o}:0:0:color={fill}", o = SHADOW_OFFSET, fill = paint.fill(background));
+    }
+    format!(
+        "split[{n}][{n}_cast];\
+         [{n}_cast]drawbox=x=0:y=0:w=iw:h=ih:color={fill}:t=fill,drawbox=x=0:y=0:w=iw:h=ih:color={shadow}:t=fill,\
+         pad=iw+{o}:ih+{o}:{o}:{o}:color={fill}[{n}_shadow];[{n}_shadow][{n}]overlay=0:0",
+        n = name,
+        o = SHADOW_OFFSET,
+        shadow = paint.fill(SHADOW_COLOR),
+        fill = paint.fill(background),
+    )
+}
+
+/// Tile the frames matching `input_pattern`, add the timeline and footer
+/// and draw the overlay on top.
+fn tile_and_overlay(job: &mut Job, sheet: &Sheet, pass: &Pass, input_pattern: &Path, output_image: &Path, opts: &Options) -> Result<()> {
+    let theme = &opts.theme;
+    let extension = pass.paint.extension(job);
+    let mosaic_temp = job.temp_path().join(format!("mosaic_raw.{}", extension));
+    // Without an overlay or footer the tiled image is the final one.
+    let tiled = if sheet.overlay || !pass.footer.is_empty() { mosaic_temp.as_path() } else { output_image };
+
+    let mut tile_filter = String::new();
+    if sheet.hero.is_some() {
+        tile_filter += "[0:v]";
+    }
+    if let Some((width, height)) = sheet.tile_scale {
+        tile_filter += &format!("scale={}:{},", width, height);
+    }
+    let border = format!(
+        "drawbox=x=0:y=0:w=iw:h=ih:color={}:t={}",
+        text::escape_filter_value(&theme.border), theme.border_width
+    );
+    let bordered = theme.border_width > 0 && pass.paint.draws_frames();
+    if bordered {
+        tile_filter += &border;
+        tile_filter += ",";
+    }
+    if theme.shadow {
+        tile_filter += &drop_shadow("frame", pass.paint, &theme.background);
+        tile_filter += ",";
+    }
+    tile_filter += &format!(
+        "tile={}x{}:padding={}:margin={}:color={}",
+        sheet.cols, sheet.rows, sheet.spacing, sheet.margin, pass.paint.fill(&theme.background)
+    );
+    if let Some(timeline) = pass.timeline {
+        tile_filter += ",";
+        tile_filter += &timeline.filters(theme, pass.paint);
+    }
+    if sheet.hero.is_some() {
+        // The hero frame scaled to the grid's width inside its margin, and stacked on top.
+        let margin = sheet.margin;
+        let mut canvas = if pass.paint.draws_frames() { "null" } else { "format=rgba,colorchannelmixer=aa=0" }.to_string();
+        let mut outline = if bordered { format!("{},", border) } else { String::new() };
+        if theme.shadow {
+            // Outlined and cast before scaling, so the shadow is inside the
+            // width the hero is fitted to.
+            canvas = format!("{},{}{}", canvas, outline, drop_shadow("lead", pass.paint, &theme.background));
+            outline.clear();
+        }
+        tile_filter += &format!(
+            "[grid];[1:v]{canvas}[hero];[hero][grid]scale2ref=w=main_w-{m2}:h=trunc((main_w-{m2})/a/2)*2[scaled][ref];\
+             [scaled]{border}pad=iw+{m2}:ih+{m}:{m}:{m}:color={fill}[top];[top][ref]vstack",
+            canvas = canvas,
+            m = margin,
+            m2 = margin * 2,
+            border = outline,
+            fill = pass.paint.fill(&theme.background),
+        );
+    }
+
+    progress::stage("tiling");
+    retry_pass(opts, || {
+        let mut cmd = sandbox::command("ffmpeg", job.sandbox());
+        cmd.args(["-f", "image2", "-i", input_pattern.to_str().unwrap()]);
+        if let Some(hero) = sheet.hero {
+            cmd.arg("-i").arg(job.tile_path(hero));
+        }
+        // -update 1 writes a single image, so a '%' in the output name isn't
+        // taken as an image sequence pattern.
+        exec::run(cmd
+            .args([
+                "-filter_complex",
+                &tile_filter,
+                "-pix_fmt", pass.paint.pix_fmt(tiled == output_image, opts),
+                "-update", "1",
+                "-y",
+            ])
+            .arg(tiled))
+            .with_context(|| "Failed to create mosaic with ffmpeg")
+    })?;
+
+    let mut mosaic = mosaic_temp;
+    if !pass.footer.is_empty() {
+        progress::stage("audio summary");
+        let footed = job.temp_path().join(format!("mosaic_footer.{}", extension));
+        match retry_pass(opts, || draw_footer(job, sheet, pass.footer, &mosaic, &footed, pass.paint, opts)) {
+            Ok(()) => mosaic = footed,
+            Err(e) if can_drop_text(&e, opts) => job.warnings.push(format!("audio summary left off: {:#}", e)),
+            Err(e) => return Err(e),
+        }
+    }
+
+    if sheet.overlay {
+        progress::stage("overlay");
+        if let Err(e) = retry_pass(opts, || draw_overlay(job, sheet, pass.title, &mosaic, output_image, pass.paint, opts)) {
+            if !can_drop_text(&e, opts) {
+                return Err(e);
+            }
+            job.warnings.push(format!("overlay left off: {:#}", e));
+            apply_filters(job, &[], &mosaic, output_image, Some(pass.paint.pix_fmt(true, opts)))
+                .with_context(|| format!("Failed to write {}", output_image.display()))?;
+        }
+    } else if !pass.footer.is_empty() {
+        apply_filters(job, &[], &mosaic, output_image, Some(pass.paint.pix_fmt(true, opts)))
+            .with_context(|| format!("Failed to write {}", output_image.display()))?;
+    }
+
+    if pass.paint == Paint::Text {
+        // A layer with little text on it compresses to almost nothing.
+        check_decodes(output_image, job.sandbox())
+    } else {
+        validate_output(output_image, job.sandbox())
+    }
+}
+
+/// Run `filters` over `input` into `output`. With a `pix_fmt` the output is
+/// always encoded in it (as finished sheets are); without, it's a scratch
+/// image in the input's own format, just copied when there are no filters.
+fn apply_filters(job: &Job, filters: &[String], input: &Path, output: &Path, pix_fmt: Option<&str>) -> Result<()> {
+    if filters.is_empty() && pix_fmt.is_none() {
+        fs::copy(input, output)?;
+        return Ok(());
+    }
+    let mut cmd = sandbox::command("ffmpeg", job.sandbox());
+    cmd.arg("-i").arg(input);
+    if !filters.is_empty() {
+        cmd.args(["-vf", &filters.join(",")]);
+    }
+    if let Some(pix_fmt) = pix_fmt {
+        cmd.args(["-pix_fmt", pix_fmt]);
+    }
+    exec::run(cmd.args(["-update", "1", "-y"]).arg(output))?;
+    Ok(())
+}
+
+/// Copy the tiles with `texts` added, returning the copies' sequence
+/// pattern. The extracted tiles stay as they were, so the sheet can be
+/// composed again.
+fn label_tiles(job: &Job, texts: &[TileText], paint: Paint, opts: &Options) -> Result<PathBuf> {
+    progress::stage("tile labels");
+    let font_path = if paint.draws_text() { font::path(opts)? } else { String::new() };
+    let theme = &opts.theme;
+    let name = if paint == Paint::Text { "layer" } else { "labeled" };
+    let labeled = |i: usize| job.temp_path().join(format!("{}_{:03}.{}", name, i, paint.extension(job)));
+    // Every tile needs the same size, so either all of them get a caption band or none.
+    let captioned = texts.iter().any(|t| !t.caption.is_empty());
+
+    for (i, tile_text) in texts.iter().enumerate() {
+        let mut filters = Vec::new();
+        if !paint.draws_frames() {
+            // A transparent canvas the size of the frame.
+            filters.push("format=rgba,colorchannelmixer=aa=0".to_string());
+        }
+        if !tile_text.label.is_empty() && paint.draws_text() {
+            let text_file = job.temp_path().join("label.txt");
+            fs::write(&text_file, &tile_text.label)?;
+            // White on a dark box, like a broadcast burn-in window, whatever the theme.
+            filters.push(format!(
+                "drawtext=fontfile={}:textfile={}:expansion=none:fontsize=h/14:fontcolor=white\
+                 :box=1:boxcolor=black@0.6:boxborderw=6:x=(w-tw)/2:y=h-th-h/30",
+                text::escape_filter_value(&font_path),
+                text::escape_filter_value(&text_file.to_string_lossy()),
+            ));
+        }
+        if !tile_text.stamp.is_empty() && paint.draws_text() {
+            let text_file = job.temp_path().join("stamp.txt");
+            fs::write(&text_file, &tile_text.stamp)?;
+            filters.push(format!(
+                "drawtext=fontfile={}:textfile={}:expansion=none:{}",
+                text::escape_filter_value(&font_path),
+                text::escape_filter_value(&text_file.to_string_lossy()),
+                opts.tile_label.style(!tile_text.label.is_empty()),
+            ));
+        }
+        if captioned {
+            // A band an eighth of the frame's height (kept even) below it.
+            filters.push(format!("pad=iw:2*trunc(ih*9/16):0:0:color={}", paint.fill(&theme.background)));
+            if !tile_text.caption.is_empty() && paint.draws_text() {
+                let text_file = job.temp_path().join("caption.txt");
+                fs::write(&text_file, &tile_text.caption)?;
+                filters.push(format!(
+                    "drawtext=fontfile={}:textfile={}:expansion=none:fontsize=h/16:fontcolor={}\
+                     :x=(w-tw)/2:y=h*8/9+(h/9-th)/2",
+                    text::escape_filter_value(&font_path),
+                    text::escape_filter_value(&text_file.to_string_lossy()),
+                    text::escape_filter_value(&theme.text),
+                ));
+            }
+        }
+
+        apply_filters(job, &filters, &job.tile_path(i), &labeled(i), None)
+            .with_context(|| format!("Failed to label tile {}", i + 1))?;
+    }
+    Ok(job.temp_path().join(format!("{}_%03d.{}", name, paint.extension(job))))
+}
+
+/// Add a band with `lines` of text below `mosaic`, writing `output_image`.
+fn draw_footer(
+    job: &Job,
+    sheet: &Sheet,
+    lines: &[String],
+    mosaic: &Path,
+    output_image: &Path,
+    paint: Paint,
+    opts: &Options,
+) -> Result<()> {
+    let theme = &opts.theme;
+    let font_size = sheet.font_size * 3 / 4;
+    // Tall enough for every line, kept even for 4:2:0 output.
+    let height = ((lines.len() as f64 * 1.2 + 0.6) * font_size as f64 / 2.0).ceil() as u32 * 2;
+    let mut filters = vec![format!("pad=iw:ih+{}:0:0:color={}", height, paint.fill(&theme.background))];
+
+    if paint.draws_text() {
+        let font_path = font::path(opts)?;
+        let text_file = job.temp_path().join("footer.txt");
+        fs::write(&text_file, lines.join("\n"))?;
+        filters.push(format!(
+            "drawtext=fontfile={}:textfile={}:expansion=none:x={}:y=h-{}+({}-th)/2:fontsize={}:fontcolor={}",
+            text::escape_filter_value(&font_path),
+            text::escape_filter_value(&text_file.to_string_lossy()),
+            sheet.margin.max(10), height, height,
+            font_size,
+            text::escape_filter_value(&theme.text),
+        ));
+    }
+
+    apply_filters(job, &filters, mosaic, output_image, None)
+        .with_context(|| "Failed to add the audio summary to the mosaic")
+}
+
+/// Draw `title` onto the tiled `mosaic`, writing `output_image`.
+fn draw_overlay(
+    job: &Job,
+    sheet: &Sheet,
+    title: &str,
+    mosaic: &Path,
+    output_image: &Path,
+    paint: Paint,
+    opts: &Options,
+) -> Result<()> {
+    let theme = &opts.theme;
+    let mut filters = Vec::new();
+    let (x, y, boxed, font_size) = match theme.banner {
+        Banner::Overlay => ("10".to_string(), "10".to_string(), true, sheet.font_size),
+        Banner::Plain => ("10".to_string(), "10".to_string(), false, sheet.font_size),
+        Banner::Strip | Banner::Header => {
+            // Tall enough for every line of the title (or the text shrunk to
+            // the height asked for), kept even for 4:2:0 output.
+            let lines = title.lines().count().max(1) as f64 * 1.2 + 0.6;
+            let (height, font_size) = match theme.banner_height {
+                Some(height) => (height.max(2) / 2 * 2, sheet.font_size.min((height as f64 / lines) as u32).max(1)),
+                None => ((lines * sheet.font_size as f64 / 2.0).ceil() as u32 * 2, sheet.font_size),
+            };
+            filters.push(format!("pad=iw:ih+{}:0:{}:color={}", height, height, paint.fill(&theme.banner_color)));
+            (sheet.margin.max(10).to_string(), format!("({}-th)/2", height), false, font_size)
+        }
+    };
+
+    if paint.draws_text() {
+        // === Text Overlay ===
+        // The text goes through a file with expansion disabled, so whatever is in
+        // the filename reaches drawtext verbatim; only the paths need escaping.
+        let font_path = font::path(opts)?;
+        let text_file = job.temp_path().join("overlay.txt");
+        fs::write(&text_file, title)?;
+        let mut filter = format!(
+            "drawtext=fontfile={}:textfile={}:expansion=none:x={}:y={}:fontsize={}:fontcolor={}",
+            text::escape_filter_value(&font_path),
+            text::escape_filter_value(&text_file.to_string_lossy()),
+            x, y,
+            font_size,
+            text::escape_filter_value(&theme.text),
+        );
+        if boxed {
+            filter += &format!(":box=1:boxcolor={}", text::escape_filter_value(&theme.banner_color));
+        }
+        filters.push(filter);
+    }
+
+    apply_filters(job, &filters, mosaic, output_image, Some(paint.pix_fmt(true, opts)))
+        .with_context(|| "Failed to overlay text on mosaic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(cols: usize, spacing: u32, margin: u32) -> Sheet {
+        Sheet { spacing, margin, ..Sheet::grid(3, cols, 3 * cols) }
+    }
+
+    #[test]
+    fn tile_width_keeps_the_sheet_within_max_width() {
+        // A 4K video's 3x3 sheet: 3840 * 3 wide unscaled.
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), None, Some(1920), Some(3840)), Some(640));
+        // Gaps and margins come out of the width first: (1920 - 2*10 - 2*5) / 3 = 630.
+        assert_eq!(fitted_tile_width(&sheet(3, 5, 10), None, Some(1920), Some(3840)), Some(630));
+    }
+
+    #[test]
+    fn tile_width_is_the_narrower_of_both_and_even() {
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), Some(500), Some(1920), Some(3840)), Some(500));
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), Some(999), Some(1920), Some(3840)), Some(640));
+        // 1000 / 3 = 333, rounded down to even.
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), None, Some(1000), Some(3840)), Some(332));
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), Some(401), None, None), Some(400));
+        // Never so narrow there's nothing left to see.
+        assert_eq!(fitted_tile_width(&sheet(3, 50, 50), None, Some(64), Some(3840)), Some(16));
+    }
+
+    #[test]
+    fn tiles_are_not_scaled_up() {
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), Some(1920), None, Some(1280)), None);
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), Some(1280), None, Some(1280)), None);
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), None, Some(8000), Some(1920)), None);
+        assert_eq!(fitted_tile_width(&sheet(3, 0, 0), None, None, Some(1920)), None);
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::Deserialize;
+use crate::mosaic::{self, GridMode};
+use crate::probe::MediaInfo;
+use crate::text;
+use crate::Options;
+
+/// Default output path for a video: `<file name>_tn.jpg` next to the source.
+///
+/// Keeping the source extension in the name means `clip.mkv` and `clip.mp4`
+/// never map to the same output, and a real `clip.jpg` is never touched.
+pub fn default_output_path(video_path: &Path) -> PathBuf {
+    let mut name = video_path.file_name().unwrap_or_default().to_os_string();
+    name.push("_tn.jpg");
+    video_path.with_file_name(name)
+}
+
+/// Where a video's sheet goes: `default_output_path`, or with `output_dir`
+/// the same name under it, in the video's directory relative to `root`.
+pub fn output_path(video_path: &Path, root: Option<&Path>, output_dir: Option<&Path>) -> PathBuf {
+    let sheet = default_output_path(video_path);
+    let Some(output_dir) = output_dir else {
+        return sheet;
+    };
+    let relative = root.and_then(|r| sheet.strip_prefix(r).ok())
+        .map_or_else(|| PathBuf::from(sheet.file_name().unwrap_or_default()), Path::to_path_buf);
+    output_dir.join(relative)
+}
+
+/// Output path for a comparison of `a` against `b`:
+/// `<a file name>_vs_<b file name>.jpg` next to `a`.
+pub fn compare_output_path(a: &Path, b: &Path) -> PathBuf {
+    let mut name = a.file_name().unwrap_or_default().to_os_string();
+    name.push("_vs_");
+    name.push(b.file_name().unwrap_or_default());
+    name.push(".jpg");
+    a.with_file_name(name)
+}
+
+/// Output path for a sheet combining several videos, the first `first`:
+/// `<first file name>_combined.jpg` next to it.
+pub fn combined_output_path(first: &Path) -> PathBuf {
+    let mut name = first.file_name().unwrap_or_default().to_os_string();
+    name.push("_combined.jpg");
+    first.with_file_name(name)
+}
+
+/// `dir/name.jpg` -> `dir/name_<width>.jpg`, for a scaled copy of an output.
+pub fn sized_output_path(path: &Path, width: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map_or("jpg".into(), |e| e.to_string_lossy());
+    path.with_file_name(format!("{}_{}.{}", stem, width, ext))
+}
+
+/// Default directory for a video's exported frames: `<file name>_frames` next to it.
+pub fn frames_dir(video_path: &Path) -> PathBuf {
+    let mut name = video_path.file_name().unwrap_or_default().to_os_string();
+    name.push("_frames");
+    video_path.with_file_name(name)
+}
+
+/// Directory under `dir` for what's kept of a video's job (its frames, or
+/// what was left when it failed): `<dir>/<file name>`.
+pub fn video_dir(dir: &Path, video_path: &Path) -> PathBuf {
+    dir.join(video_path.file_name().unwrap_or_default())
+}
+
+/// `dir/<file name>_<NNN>.<extension>` for frame `i` (from 0) of a video,
+/// numbered from 001.
+pub fn frame_path(dir: &Path, video_path: &Path, i: usize, extension: &str) -> PathBuf {
+    let mut name = video_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("_{:03}.{}", i + 1, extension));
+    dir.join(name)
+}
+
+/// How sheet names are made safe for any filesystem (`--safe-names`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SafeNames {
+    /// The name with only ASCII letters, digits, `.`, `_` and `-` kept.
+    Slug,
+    /// A hash of the video's path, keeping the extension.
+    Hash,
+}
+
+impl SafeNames {
+    /// `name` (a sheet's file name) made safe, for a sheet of `video_path`.
+    pub fn apply(self, name: &str, video_path: &Path) -> String {
+        match self {
+            SafeNames::Slug => text::slug(name),
+            SafeNames::Hash => {
+                let hash = fnv1a(video_path.to_string_lossy().as_bytes());
+                match Path::new(name).extension() {
+                    Some(ext) => format!("{:016x}.{}", hash, text::slug(&ext.to_string_lossy())),
+                    None => format!("{:016x}", hash),
+                }
+            }
+        }
+    }
+}
+
+/// Tracks the outputs claimed so far in a run so no two sources write the same file.
+#[derive(Default)]
+pub struct OutputClaims {
+    claimed: HashSet<String>,
+    sources: HashSet<String>,
+}
+
+impl OutputClaims {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a source file so it can never be chosen as an output.
+    pub fn add_source(&mut self, video_path: &Path) {
+        self.sources.insert(key(video_path));
+    }
+
+    /// Claim `wanted` for `video_path`, appending `-2`, `-3`, ... to the file
+    /// stem if another source in this run already claimed it.
+    ///
+    /// Paths are compared case-insensitively so the result is also safe on
+    /// case-insensitive filesystems.
+    pub fn claim(&mut self, video_path: &Path, wanted: PathBuf) -> Result<PathBuf> {
+        if key(&wanted) == key(video_path) || self.sources.contains(&key(&wanted)) {
+            bail!("Output {} would overwrite a source video", wanted.display());
+        }
+
+        let mut candidate = wanted.clone();
+        let mut n = 2;
+        while self.claimed.contains(&key(&candidate)) || self.sources.contains(&key(&candidate)) {
+            candidate = with_suffix(&wanted, n);
+            n += 1;
+        }
+
+        self.claimed.insert(key(&candidate));
+        Ok(candidate)
+    }
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// `dir/name.jpg` -> `dir/name-<n>.jpg`
+pub fn with_suffix(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// What to do about a sheet that's already there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Existing {
+    Overwrite,
+    Skip,
+    /// Replace it only if the video was modified after it.
+    IfNewer,
+}
+
+impl Existing {
+    /// Why `output` should be left as it is, if it should.
+    pub fn keep_reason(self, video_path: &Path, output: &Path, opts: &Options) -> Result<Option<String>> {
+        if self == Existing::Overwrite || !output.exists() {
+            return Ok(None);
+        }
+        if self == Existing::IfNewer {
+            let modified = |path: &Path| opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(path)?.modified());
+            if modified(video_path)? > modified(output)? {
+                return Ok(None);
+            }
+            return Ok(Some(format!("{} is newer than the video", output.display())));
+        }
+        Ok(Some(format!("{} already exists", output.display())))
+    }
+}
+
+/// Tokens a `--name-template` can use.
+const TOKENS: &[&str] = &[
+    "name", "stem", "source_ext", "ext", "rows", "cols", "frames", "resolution", "duration", "date", "hash",
+];
+
+/// Bytes read from each end of a video for the `{hash}` token.
+const HASH_SPAN: u64 = 1 << 20;
+
+/// An output file name with `{token}`s filled in per video.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template(String);
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(template: String) -> Result<Template, String> {
+        Template::parse(&template)
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        if template.contains(['/', '\\']) {
+            return Err("a name template is a file name, without directories".to_string());
+        }
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}') else {
+                return Err(format!("unclosed '{{' in '{}'", template));
+            };
+            let token = &rest[open + 1..open + close];
+            if !TOKENS.contains(&token) {
+                return Err(format!("unknown token {{{}}} (expected one of {})", token, TOKENS.join(", ")));
+            }
+            rest = &rest[open + close + 1..];
+        }
+        let lower = template.to_lowercase();
+        if !(lower.ends_with("{ext}") || lower.ends_with(".jpg") || lower.ends_with(".jpeg")) {
+            return Err("a name template must end in .jpg or {ext}".to_string());
+        }
+        Ok(Template(template.to_string()))
+    }
+
+    /// Whether rendering needs the video probed (for its resolution or
+    /// duration, which also settles the grid with `--grid auto`).
+    pub fn needs_probe(&self, opts: &Options) -> bool {
+        let grid = ["{rows}", "{cols}", "{frames}"].iter().any(|token| self.0.contains(token));
+        self.0.contains("{resolution}") || self.0.contains("{duration}") || (grid && opts.grid == GridMode::Auto)
+    }
+
+    /// The file name for `video_path`, with `info` when `needs_probe`.
+    ///
+    /// `{date}` is the video's modification date, so names stay the same
+    /// from run to run; `{hash}` identifies its content from its size and
+    /// first and last megabyte.
+    pub fn render(&self, video_path: &Path, info: Option<&MediaInfo>, opts: &Options) -> Result<String> {
+        let lossy = |s: Option<&std::ffi::OsStr>| s.unwrap_or_default().to_string_lossy().into_owned();
+        let auto_side = || {
+            let duration = info.and_then(|i| i.duration()).filter(|_| opts.grid == GridMode::Auto)?.0;
+            Some(mosaic::auto_grid_side(duration, opts.auto_grid_sides))
+        };
+        expand(&self.0, |token| Ok(match token {
+            "name" => lossy(video_path.file_name()),
+            "stem" => lossy(video_path.file_stem()),
+            "source_ext" => lossy(video_path.extension()),
+            "ext" => "jpg".to_string(),
+            "rows" => auto_side().unwrap_or(opts.rows).to_string(),
+            "cols" => auto_side().unwrap_or(opts.cols).to_string(),
+            "frames" => auto_side().map_or(opts.frames, |side| side * side).to_string(),
+            "resolution" => info.map(MediaInfo::resolution).unwrap_or_default(),
+            "duration" => {
+                let secs = info.and_then(|i| i.duration()).map_or(0, |(d, _)| d as u64);
+                format!("{:02}-{:02}-{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+            }
+            "date" => {
+                let modified = opts.retry.io(format_args!("stat {}", video_path.display()), || fs::metadata(video_path)?.modified())?;
+                DateTime::<Local>::from(modified).format("%Y-%m-%d").to_string()
+            }
+            "hash" => content_hash(video_path, opts)?,
+            other => unreachable!("{{{}}} passed Template::parse", other),
+        }))
+    }
+}
+
+/// `template` with each `{token}` replaced by `value(token)`, in one pass, so
+/// a value holding braces (a video named `a{stem}.mkv`) is left as it is.
+fn expand(template: &str, mut value: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut name = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        name += &rest[..open];
+        name += &value(&rest[open + 1..close])?;
+        rest = &rest[close + 1..];
+    }
+    name += rest;
+    Ok(name)
+}
+
+/// A short hash of a file's size and its first and last `HASH_SPAN` bytes.
+fn content_hash(path: &Path, opts: &Options) -> Result<String> {
+    let sample = opts.retry.io(format_args!("read {}", path.display()), || {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut sample = size.to_le_bytes().to_vec();
+        file.by_ref().take(HASH_SPAN).read_to_end(&mut sample)?;
+        if size > 2 * HASH_SPAN {
+            file.seek(SeekFrom::End(-(HASH_SPAN as i64)))?;
+            file.read_to_end(&mut sample)?;
+        }
+        Ok(sample)
+    })?;
+    Ok(format!("{:08x}", fnv1a(&sample) >> 32))
+}
+
+/// FNV-1a of `bytes`, whose value (unlike std's hasher) never changes
+/// between builds, for names and fingerprints that must stay the same.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, &b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_are_checked_when_parsed() {
+        assert!(Template::parse("{stem}.{rows}x{cols}.{ext}").is_ok());
+        assert!(Template::parse("{name}_sheet.JPG").is_ok());
+        assert!(Template::parse("sheets/{stem}.jpg").is_err());
+        assert!(Template::parse("{stem.jpg").is_err());
+        assert!(Template::parse("{title}.jpg").is_err());
+        assert!(Template::parse("{stem}.png").is_err());
+    }
+
+    #[test]
+    fn tokens_are_replaced_in_one_pass() {
+        let values = |token: &str| Ok(match token {
+            "name" => "a{stem}.mkv".to_string(),
+            "stem" => "a{stem}".to_string(),
+            "ext" => "jpg".to_string(),
+            other => format!("<{}>", other),
+        });
+        assert_eq!(expand("{name}.{ext}", values).unwrap(), "a{stem}.mkv.jpg");
+        assert_eq!(expand("{stem}-{stem}.{ext}", values).unwrap(), "a{stem}-a{stem}.jpg");
+        assert_eq!(expand("{stem}.{rows}x{cols}.{ext}", values).unwrap(), "a{stem}.<rows>x<cols>.jpg");
+        assert_eq!(expand("plain.jpg", values).unwrap(), "plain.jpg");
+    }
+
+    #[test]
+    fn failing_values_fail_the_name() {
+        assert!(expand("{hash}.jpg", |_| anyhow::bail!("unreadable")).is_err());
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use serde::Serialize;
+use crate::mosaic::{Job, Tile};
+use crate::poster;
+
+/// Width the poster frame is shrunk to before its colors are counted.
+const SAMPLE_WIDTH: u32 = 64;
+
+/// Rounds of k-means refinement after seeding.
+const ROUNDS: usize = 8;
+
+/// One of a frame's dominant colors.
+#[derive(Clone, Debug, Serialize)]
+pub struct Swatch {
+    /// `#rrggbb`.
+    pub color: String,
+    /// Fraction of the frame's pixels nearest this color.
+    pub share: f64,
+}
+
+/// The `count` dominant colors of the video's poster frame (the most
+/// detailed of `tiles`), most common first.
+pub fn dominant(job: &Job, tiles: &[Tile], count: usize) -> Result<Vec<Swatch>> {
+    let pixels: Vec<[f64; 3]> = poster::poster_pixels(job, tiles, SAMPLE_WIDTH)?.chunks_exact(3)
+        .map(|p| [p[0] as f64, p[1] as f64, p[2] as f64])
+        .collect();
+    Ok(clusters(&pixels, count))
+}
+
+/// k-means over `pixels`, seeded with the centers of the most crowded
+/// cells of a coarse RGB grid so the result is the same every run.
+fn clusters(pixels: &[[f64; 3]], count: usize) -> Vec<Swatch> {
+    let cell = |p: &[f64; 3]| ((p[0] as usize >> 4) << 8) | ((p[1] as usize >> 4) << 4) | (p[2] as usize >> 4);
+    let mut cells = vec![0usize; 4096];
+    for p in pixels {
+        cells[cell(p)] += 1;
+    }
+    let mut crowded: Vec<usize> = (0..cells.len()).filter(|&c| cells[c] > 0).collect();
+    crowded.sort_by_key(|&c| std::cmp::Reverse(cells[c]));
+    let mut centers: Vec<[f64; 3]> = crowded.iter().take(count)
+        .map(|&c| [((c >> 8) * 16 + 8) as f64, ((c >> 4 & 15) * 16 + 8) as f64, ((c & 15) * 16 + 8) as f64])
+        .collect();
+
+    let nearest = |centers: &[[f64; 3]], p: &[f64; 3]| {
+        let distance = |c: &[f64; 3]| (0..3).map(|i| (c[i] - p[i]).powi(2)).sum::<f64>();
+        (0..centers.len()).min_by(|&a, &b| distance(&centers[a]).total_cmp(&distance(&centers[b]))).unwrap()
+    };
+    let mut members = vec![0usize; centers.len()];
+    for _ in 0..ROUNDS {
+        let mut sums = vec![[0.0; 3]; centers.len()];
+        members = vec![0; centers.len()];
+        for p in pixels {
+            let i = nearest(&centers, p);
+            members[i] += 1;
+            (0..3).for_each(|c| sums[i][c] += p[c]);
+        }
+        for (i, center) in centers.iter_mut().enumerate() {
+            if members[i] > 0 {
+                *center = sums[i].map(|sum| sum / members[i] as f64);
+            }
+        }
+    }
+
+    let mut swatches: Vec<(usize, Swatch)> = centers.iter().zip(&members)
+        .filter(|(_, &n)| n > 0)
+        .map(|(c, &n)| (n, Swatch {
+            color: format!("#{:02x}{:02x}{:02x}", c[0].round() as u8, c[1].round() as u8, c[2].round() as u8),
+            share: (n as f64 / pixels.len() as f64 * 1000.0).round() / 1000.0,
+        }))
+        .collect();
+    swatches.sort_by_key(|(n, _)| std::cmp::Reverse(*n));
+    swatches.into_iter().map(|(_, swatch)| swatch).collect()
+}
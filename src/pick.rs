@@ -0,0 +1,383 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use base64::Engine;
+use clap::ValueEnum;
+use crate::exec;
+use crate::mosaic::{self, Job, Tile};
+use crate::naming;
+use crate::sandbox;
+use crate::text;
+use crate::{Options, Outcome};
+
+/// How candidate frames are drawn in the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preview {
+    /// Detect what the terminal supports.
+    Auto,
+    /// Kitty graphics protocol (kitty, WezTerm, Ghostty).
+    Kitty,
+    /// DEC sixel graphics (foot, mlterm, xterm -ti vt340, ...).
+    Sixel,
+    /// Plain characters, for any terminal.
+    Ascii,
+}
+
+impl Preview {
+    /// Resolve `Auto` from the terminal's environment.
+    fn detect(self) -> Preview {
+        if self != Preview::Auto {
+            return self;
+        }
+        let env = |name: &str| std::env::var(name).unwrap_or_default();
+        let term = env("TERM");
+        let program = env("TERM_PROGRAM");
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || program == "WezTerm"
+        {
+            Preview::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Preview::Sixel
+        } else {
+            Preview::Ascii
+        }
+    }
+}
+
+/// Width of kitty/sixel previews, in pixels.
+const PREVIEW_WIDTH: u32 = 320;
+
+/// Width of ASCII previews, in characters.
+const ASCII_WIDTH: u32 = 32;
+
+/// Characters from dark to light for ASCII previews.
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// A frame offered for one tile.
+struct Candidate {
+    path: PathBuf,
+    /// Presentation time of the frame, in seconds.
+    time: f64,
+}
+
+/// What the user chose for a tile.
+enum Choice {
+    Keep,
+    Pick(usize),
+    More,
+    Quit,
+}
+
+/// Let the user pick every tile of `video`'s sheet in the terminal, then
+/// compose and write it.
+///
+/// Each tile starts out as the frame the normal run would use; the user can
+/// keep it or swap in one of `candidates` other frames from the same segment,
+/// asking for more as often as they like.
+pub fn run(video: &Path, candidates: usize, preview: Preview, opts: &Options) -> Result<Outcome> {
+    let output_image = naming::output_path(video, None, opts.output_dir.as_deref());
+    let video_path = video.to_str().with_context(|| format!("{} isn't a valid UTF-8 path", video.display()))?;
+    let mut job = match Job::open(video_path, &output_image, opts) {
+        Ok(job) => job,
+        Err(e) => return mosaic::skipped(e),
+    };
+
+    let (rows, cols, total_frames) = job.fit_grid(opts.rows, opts.cols, opts.frames, opts);
+    job.scale_tiles(&opts.sheet(rows, cols, total_frames), opts);
+    job.check_disk_space(total_frames * (candidates + 1))?;
+
+    log::info!("Extracting tiles from {}...", video.display());
+    let mut tiles = mosaic::extract_tiles(&mut job, total_frames, opts)?;
+    let segments = job.segments(total_frames, opts);
+    let preview = preview.detect();
+    let aspect = match job.info.display_size() {
+        Some((w, h)) if h > 0 => w as f64 / h as f64,
+        _ => 16.0 / 9.0,
+    };
+    let mut input = io::stdin().lock().lines();
+
+    for (i, &segment) in segments.iter().enumerate() {
+        println!(
+            "\nTile {}/{} ({} - {})",
+            i + 1, total_frames, text::format_time(segment.0), text::format_time(segment.1)
+        );
+        let mut offered = vec![Candidate { path: job.tile_path(i), time: tiles[i].shown }];
+        show(&job, preview, aspect, &offered, 0)?;
+
+        let (mut round, mut attempts) = (0, 0);
+        loop {
+            let batch = extract_candidates(&job, i, segment, candidates, round, &mut attempts, opts)?;
+            show(&job, preview, aspect, &batch, offered.len())?;
+            offered.extend(batch);
+
+            match ask(&mut input, offered.len() - 1)? {
+                Choice::Keep => break,
+                Choice::Pick(k) => {
+                    fs::copy(&offered[k].path, job.tile_path(i))?;
+                    let time = offered[k].time;
+                    tiles[i] = Tile { requested: time, shown: time, blank: false };
+                    break;
+                }
+                Choice::More => round += 1,
+                Choice::Quit => return Ok(Outcome::Skipped("picking cancelled".to_string())),
+            }
+        }
+    }
+
+    mosaic::compose_sheet(&mut job, &opts.sheet(rows, cols, total_frames), &tiles, &output_image, opts)?;
+    Ok(job.finish(tiles))
+}
+
+/// Prompt until the user makes a valid choice among `count` candidates.
+fn ask(input: &mut impl Iterator<Item = io::Result<String>>, count: usize) -> Result<Choice> {
+    loop {
+        print!("Enter keeps current, 1-{} picks a candidate, m shows more, q quits: ", count);
+        io::stdout().flush()?;
+        let line = input.next().context("Input closed before every tile was picked")??;
+        exec::check_interrupted()?;
+
+        let choice = match line.trim() {
+            "" => Some(Choice::Keep),
+            "m" | "M" => Some(Choice::More),
+            "q" | "Q" => Some(Choice::Quit),
+            n => n.parse().ok().filter(|k| (1..=count).contains(k)).map(Choice::Pick),
+        };
+        match choice {
+            Some(choice) => return Ok(choice),
+            None => println!("Not a choice: {}", line.trim()),
+        }
+    }
+}
+
+/// Extract `count` frames spread evenly over `segment` for tile `tile`,
+/// numbering their files on from `attempts`, which counts every frame tried
+/// (extracted or not) so no round writes over another's.
+///
+/// Every round takes one frame in each of `count` equal slots of the
+/// segment: at its middle first, then at its quarters, eighths and so on,
+/// so asking for more never repeats a frame.
+fn extract_candidates(
+    job: &Job,
+    tile: usize,
+    segment: (f64, f64),
+    count: usize,
+    round: u32,
+    attempts: &mut usize,
+    opts: &Options,
+) -> Result<Vec<Candidate>> {
+    let src = job.source();
+    let offset = slot_offset(round);
+    let mut batch = Vec::with_capacity(count);
+    for j in 0..count {
+        let t = segment.0 + (segment.1 - segment.0) * (j as f64 + offset) / count as f64;
+        let path = job.temp_path().join(candidate_name(tile, *attempts, job.frame_extension()));
+        *attempts += 1;
+        let time = match mosaic::extract_frame(&src, t, &path, opts.frame_timeout) {
+            Ok(extraction) => extraction.shown.unwrap_or(t),
+            Err(e) if exec::is_timed_out(&e) => continue,
+            Err(e) => return Err(e),
+        };
+        if path.is_file() {
+            batch.push(Candidate { path, time });
+        }
+    }
+    Ok(batch)
+}
+
+/// File name of the `n`th frame tried for tile `tile`.
+fn candidate_name(tile: usize, n: usize, extension: &str) -> String {
+    format!("cand_{:03}_{:03}.{}", tile, n, extension)
+}
+
+/// Where in its slot round `round` takes a frame, as a fraction of the slot:
+/// 1/2, 1/4, 3/4, 1/8, 5/8, ... (the base-2 van der Corput sequence).
+fn slot_offset(round: u32) -> f64 {
+    let (mut n, mut offset, mut unit) = (round + 1, 0.0, 0.5);
+    while n > 0 {
+        if n & 1 == 1 {
+            offset += unit;
+        }
+        n >>= 1;
+        unit /= 2.0;
+    }
+    offset
+}
+
+/// Draw `candidates` labeled from `first` (0 being the current tile).
+fn show(job: &Job, preview: Preview, aspect: f64, candidates: &[Candidate], first: usize) -> Result<()> {
+    let label = |k: usize| match first + k {
+        0 => format!("current ({})", text::format_time(candidates[k].time)),
+        n => format!("{} ({})", n, text::format_time(candidates[k].time)),
+    };
+    let mut out = io::stdout().lock();
+
+    if preview == Preview::Ascii {
+        // Side by side, as many per row as fit the terminal.
+        let columns = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80);
+        let per_row = (columns / (ASCII_WIDTH as usize + 2)).max(1);
+        let height = ((ASCII_WIDTH as f64 / aspect / 2.0).round() as u32).max(1);
+        for (row, group) in candidates.chunks(per_row).enumerate() {
+            let art: Vec<Vec<String>> = group.iter()
+                .map(|c| render_ascii(job, &c.path, ASCII_WIDTH, height))
+                .collect::<Result<_>>()?;
+            let labels: Vec<String> = (0..group.len()).map(|k| label(row * per_row + k)).collect();
+            writeln!(out, "{}", join_columns(labels.iter().map(String::as_str)))?;
+            for line in 0..height as usize {
+                writeln!(out, "{}", join_columns(art.iter().map(|a| a[line].as_str())))?;
+            }
+        }
+        return Ok(());
+    }
+
+    let height = (((PREVIEW_WIDTH as f64 / aspect) / 2.0).round() as u32 * 2).max(2);
+    for (k, candidate) in candidates.iter().enumerate() {
+        writeln!(out, "{}", label(k))?;
+        match preview {
+            Preview::Kitty => write_kitty(&mut out, &render_png(job, &candidate.path, PREVIEW_WIDTH, height)?)?,
+            _ => write_sixel(&mut out, &render_rgb(job, &candidate.path, PREVIEW_WIDTH, height)?, PREVIEW_WIDTH, height)?,
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Pad each cell to the ASCII preview width and join them into one line.
+fn join_columns<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    let width = ASCII_WIDTH as usize;
+    cells.map(|c| format!("{:<width$}", c.chars().take(width).collect::<String>()))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Scale an image with FFmpeg and return the raw encoded output.
+fn scale(job: &Job, image: &Path, width: u32, height: u32, format: &[&str]) -> Result<Vec<u8>> {
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-v", "error", "-i"])
+        .arg(image)
+        .args(["-vf", &format!("scale={}:{}", width, height), "-frames:v", "1"])
+        .args(format)
+        .arg("-"))
+        .with_context(|| format!("Failed to render preview of {}", image.display()))?;
+    Ok(output.stdout)
+}
+
+fn render_png(job: &Job, image: &Path, width: u32, height: u32) -> Result<Vec<u8>> {
+    scale(job, image, width, height, &["-f", "image2pipe", "-c:v", "png"])
+}
+
+fn render_rgb(job: &Job, image: &Path, width: u32, height: u32) -> Result<Vec<u8>> {
+    let rgb = scale(job, image, width, height, &["-f", "rawvideo", "-pix_fmt", "rgb24"])?;
+    if rgb.len() != (width * height * 3) as usize {
+        anyhow::bail!("Preview of {} has an unexpected size", image.display());
+    }
+    Ok(rgb)
+}
+
+fn render_ascii(job: &Job, image: &Path, width: u32, height: u32) -> Result<Vec<String>> {
+    let gray = scale(job, image, width, height, &["-f", "rawvideo", "-pix_fmt", "gray"])?;
+    if gray.len() != (width * height) as usize {
+        anyhow::bail!("Preview of {} has an unexpected size", image.display());
+    }
+    Ok(gray.chunks(width as usize)
+        .map(|row| {
+            row.iter()
+                .map(|&v| ASCII_RAMP[v as usize * (ASCII_RAMP.len() - 1) / 255] as char)
+                .collect()
+        })
+        .collect())
+}
+
+/// Display a PNG with the kitty graphics protocol, which takes it base64
+/// encoded in chunks of at most 4096 bytes.
+fn write_kitty(out: &mut impl Write, png: &[u8]) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    for (n, chunk) in chunks.iter().enumerate() {
+        let more = (n + 1 < chunks.len()) as u8;
+        if n == 0 {
+            write!(out, "\x1b_Gf=100,a=T,m={};", more)?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Display raw RGB pixels as sixel graphics, quantized to a 6x6x6 color cube.
+fn write_sixel(out: &mut impl Write, rgb: &[u8], width: u32, height: u32) -> io::Result<()> {
+    let (width, height) = (width as usize, height as usize);
+    let level = |v: u8| v as usize * 6 / 256;
+    let colors: Vec<usize> = rgb.chunks(3)
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for c in 0..216 {
+        write!(out, "#{};2;{};{};{}", c, c / 36 * 20, c / 6 % 6 * 20, c % 6 * 20)?;
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used = [false; 216];
+        for y in rows.clone() {
+            for &c in &colors[y * width..(y + 1) * width] {
+                used[c] = true;
+            }
+        }
+        for c in (0..216).filter(|&c| used[c]) {
+            write!(out, "#{}", c)?;
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    let bits = rows.clone()
+                        .filter(|&y| colors[y * width + x] == c)
+                        .fold(0, |bits, y| bits | 1 << (y - band));
+                    63 + bits
+                })
+                .collect();
+            // Run-length encode repeated sixels.
+            let mut x = 0;
+            while x < sixels.len() {
+                let run = sixels[x..].iter().take_while(|&&s| s == sixels[x]).count();
+                if run > 3 {
+                    write!(out, "!{}{}", run, sixels[x] as char)?;
+                } else {
+                    out.write_all(&sixels[x..x + run])?;
+                }
+                x += run;
+            }
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn slot_offsets_halve_the_gaps_left() {
+        let offsets: Vec<f64> = (0..7).map(slot_offset).collect();
+        assert_eq!(offsets, [0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875]);
+        // A thousand rounds in, still no frame taken twice.
+        let distinct: HashSet<u64> = (0..1000).map(|round| slot_offset(round).to_bits()).collect();
+        assert_eq!(distinct.len(), 1000);
+        assert!((0..1000).map(slot_offset).all(|offset| offset > 0.0 && offset < 1.0));
+    }
+
+    #[test]
+    fn candidate_names_are_distinct() {
+        assert_eq!(candidate_name(2, 7, "jpg"), "cand_002_007.jpg");
+        let names: HashSet<String> = (0..10)
+            .flat_map(|tile| (0..2000).map(move |n| candidate_name(tile, n, "png")))
+            .collect();
+        assert_eq!(names.len(), 20_000);
+    }
+}
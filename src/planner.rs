@@ -0,0 +1,292 @@
+/// Default start offset as a fraction of the duration, when none is given.
+const AUTO_START_FRACTION: f64 = 0.02;
+
+/// Seeks stay this far before the end of the video.
+const END_MARGIN: f64 = 0.1;
+
+/// How much later a tile is re-sampled when its frame is black, in seconds.
+const BLACK_RETRY_STEP: f64 = 2.0;
+
+/// Re-samples of a tile tried while its frame is black.
+pub const MAX_BLACK_RETRIES: usize = 5;
+
+/// Where in its segment a tile is re-sampled when nothing was decoded at
+/// the planned timestamp, as fractions of the segment length.
+const RESAMPLE_POSITIONS: [f64; 3] = [0.5, 0.25, 0.75];
+
+/// A chapter start this close to a segment boundary, as a fraction of the
+/// average segment length, moves the boundary onto it.
+const CHAPTER_SNAP: f64 = 0.25;
+
+/// The window a tile is sampled from, in seconds from the start of the video.
+pub type Segment = (f64, f64);
+
+/// What a sheet's sampling is planned from.
+pub struct PlanInput<'a> {
+    pub duration: f64,
+    /// Tiles to plan.
+    pub count: usize,
+    /// Where sampling starts, in seconds; `None` picks a small fraction of the duration.
+    pub start_offset: Option<f64>,
+    /// Time ranges never sampled (intros, ad breaks), in seconds.
+    pub skip: &'a [(f64, f64)],
+    /// Chapter start times, which segment boundaries snap to when close.
+    pub chapters: &'a [f64],
+}
+
+/// Picks the segments a sheet's tiles are sampled from.
+pub trait Selector {
+    /// `input.count` segments, in order, each within the video.
+    fn segments(&self, input: &PlanInput) -> Vec<Segment>;
+}
+
+/// Splits the sampled range into equal segments, leaving out skipped time
+/// and lining boundaries up with nearby chapter starts.
+pub struct EvenSelector;
+
+impl Selector for EvenSelector {
+    fn segments(&self, input: &PlanInput) -> Vec<Segment> {
+        if input.count == 0 {
+            return Vec::new();
+        }
+        let (start, end) = sampled_range(input.duration, input.start_offset);
+        let mut allowed = subtract(start, end, input.skip);
+        let total: f64 = allowed.iter().map(|(a, b)| b - a).sum();
+        if total <= 0.0 {
+            // Skipping everything would leave nothing to show; ignore the skips.
+            allowed = vec![(start, end)];
+        }
+        let total: f64 = allowed.iter().map(|(a, b)| b - a).sum();
+
+        let interval = total / input.count as f64;
+        let mut bounds: Vec<f64> = (0..=input.count)
+            .map(|i| position(&allowed, interval * i as f64))
+            .collect();
+        snap_to_chapters(&mut bounds, input.chapters, &allowed, interval * CHAPTER_SNAP);
+
+        bounds.windows(2)
+            .map(|w| {
+                // A segment never reaches into skipped time past its own stretch.
+                let stretch_end = allowed.iter()
+                    .find(|(a, b)| *a <= w[0] && w[0] < *b)
+                    .map_or(w[0], |&(_, b)| b);
+                (w[0], w[1].min(stretch_end).max(w[0]))
+            })
+            .collect()
+    }
+}
+
+/// Samples exactly the given times (frames from a shot list, say), in order,
+/// kept within the video; as many segments as there are times.
+pub struct FixedSelector<'a>(pub &'a [f64]);
+
+impl Selector for FixedSelector<'_> {
+    fn segments(&self, input: &PlanInput) -> Vec<Segment> {
+        let last = (input.duration - END_MARGIN).max(0.0);
+        self.0.iter()
+            .take(input.count)
+            .map(|&t| {
+                let t = t.clamp(0.0, last);
+                (t, t)
+            })
+            .collect()
+    }
+}
+
+/// The part of a video tiles are sampled from: past the opening moments
+/// (black leaders, logos) and strictly before the end, since seeking to the
+/// very end yields no frame.
+pub fn sampled_range(duration: f64, start_offset: Option<f64>) -> (f64, f64) {
+    let duration = duration.max(0.0);
+    let start = start_offset.filter(|s| s.is_finite())
+        .unwrap_or(duration * AUTO_START_FRACTION)
+        .clamp(0.0, duration * 0.5);
+    let end = (duration - END_MARGIN).max(start);
+    (start, end)
+}
+
+/// `[start, end]` without the `skip` ranges, as ordered, disjoint stretches.
+fn subtract(start: f64, end: f64, skip: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut skip: Vec<(f64, f64)> = skip.iter()
+        .filter(|(a, b)| a.is_finite() && b.is_finite() && a < b)
+        .copied()
+        .collect();
+    skip.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut stretches = Vec::new();
+    let mut from = start;
+    for (a, b) in skip {
+        if a > from {
+            stretches.push((from, a.min(end)));
+        }
+        from = from.max(b);
+        if from >= end {
+            break;
+        }
+    }
+    if from < end {
+        stretches.push((from, end));
+    }
+    stretches.retain(|(a, b)| b > a);
+    stretches
+}
+
+/// The time `offset` seconds into the allowed stretches (clamped to their end).
+fn position(allowed: &[(f64, f64)], offset: f64) -> f64 {
+    let mut left = offset;
+    for &(a, b) in allowed {
+        if left < b - a {
+            return a + left;
+        }
+        left -= b - a;
+    }
+    allowed.last().map_or(0.0, |&(_, b)| b)
+}
+
+/// Move inner boundaries onto chapter starts within `tolerance`, keeping
+/// them in order and out of skipped time.
+fn snap_to_chapters(bounds: &mut [f64], chapters: &[f64], allowed: &[(f64, f64)], tolerance: f64) {
+    let n = bounds.len();
+    for i in 1..n.saturating_sub(1) {
+        let nearest = chapters.iter()
+            .copied()
+            .filter(|&c| (c - bounds[i]).abs() <= tolerance)
+            .filter(|&c| allowed.iter().any(|&(a, b)| a <= c && c < b))
+            .filter(|&c| c > bounds[i - 1] && c < bounds[i + 1])
+            .min_by(|x, y| (x - bounds[i]).abs().total_cmp(&(y - bounds[i]).abs()));
+        if let Some(chapter) = nearest {
+            bounds[i] = chapter;
+        }
+    }
+}
+
+/// Where a tile is tried next when nothing was decoded at its segment's start.
+pub fn resample_times(segment: Segment) -> impl Iterator<Item = f64> {
+    RESAMPLE_POSITIONS.into_iter().map(move |f| segment.0 + (segment.1 - segment.0) * f)
+}
+
+/// The time a black frame at `timestamp` is retried at, if the segment has
+/// room left. Steps are 2s, but smaller in short segments so every retry
+/// still fits.
+pub fn black_retry(timestamp: f64, segment: Segment) -> Option<f64> {
+    let step = BLACK_RETRY_STEP.min((segment.1 - segment.0) / (MAX_BLACK_RETRIES + 1) as f64);
+    let next = (timestamp + step).min(segment.1);
+    (next > timestamp).then_some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn plan(duration: f64, count: usize, start_offset: Option<f64>, skip: &[(f64, f64)], chapters: &[f64]) -> Vec<Segment> {
+        EvenSelector.segments(&PlanInput { duration, count, start_offset, skip, chapters })
+    }
+
+    fn skip_ranges() -> impl Strategy<Value = Vec<(f64, f64)>> {
+        prop::collection::vec((0.0..4000.0f64, 0.0..600.0f64).prop_map(|(a, len)| (a, a + len)), 0..4)
+    }
+
+    #[test]
+    fn zero_duration_plans_every_tile_at_zero() {
+        let segments = plan(0.0, 9, None, &[], &[]);
+        assert_eq!(segments.len(), 9);
+        assert!(segments.iter().all(|&s| s == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn skips_covering_everything_are_ignored() {
+        let segments = plan(100.0, 4, Some(0.0), &[(0.0, 100.0)], &[]);
+        assert_eq!(segments.len(), 4);
+        assert_eq!(segments[0].0, 0.0);
+    }
+
+    #[test]
+    fn boundaries_snap_to_nearby_chapters() {
+        // Boundaries fall at 25, 50 and 75s; chapters at 27 and 60s.
+        let segments = plan(100.1, 4, Some(0.0), &[], &[27.0, 60.0]);
+        assert_eq!(segments[1].0, 27.0);
+        assert_eq!(segments[0].1, 27.0);
+        // 60 is 10s from 50, past the 6.25s tolerance.
+        assert_eq!(segments[2].0, 50.0);
+    }
+
+    #[test]
+    fn fixed_times_are_kept_inside_the_video() {
+        let input = PlanInput { duration: 10.0, count: 3, start_offset: None, skip: &[], chapters: &[] };
+        let segments = FixedSelector(&[4.0, -1.0, 20.0]).segments(&input);
+        assert_eq!(segments, vec![(4.0, 4.0), (0.0, 0.0), (9.9, 9.9)]);
+    }
+
+    proptest! {
+        #[test]
+        fn segments_are_ordered_and_inside_the_video(
+            duration in 0.0..5000.0f64,
+            count in 0usize..40,
+            start_offset in prop::option::of(-100.0..6000.0f64),
+            skip in skip_ranges(),
+            chapters in prop::collection::vec(0.0..5000.0f64, 0..8),
+        ) {
+            let segments = plan(duration, count, start_offset, &skip, &chapters);
+            prop_assert_eq!(segments.len(), count);
+            let (start, end) = sampled_range(duration, start_offset);
+            let mut previous_end = start;
+            for &(a, b) in &segments {
+                prop_assert!(a <= b, "segment {:?} is reversed", (a, b));
+                prop_assert!(a >= start && b <= end, "segment {:?} leaves {:?}", (a, b), (start, end));
+                prop_assert!(a >= previous_end - 1e-9, "segments overlap at {}", a);
+                previous_end = b;
+            }
+        }
+
+        #[test]
+        fn skipped_time_is_never_sampled(
+            duration in 10.0..5000.0f64,
+            count in 1usize..40,
+            skip in skip_ranges(),
+        ) {
+            let (start, end) = sampled_range(duration, None);
+            let covers_all = subtract(start, end, &skip).is_empty();
+            for (a, b) in plan(duration, count, None, &skip, &[]) {
+                let inside = |t: f64| skip.iter().any(|&(s, e)| s < t && t < e);
+                prop_assert!(covers_all || (!inside(a) && !inside((a + b) / 2.0)),
+                    "segment {:?} samples skipped time", (a, b));
+            }
+        }
+
+        #[test]
+        fn start_is_clamped_into_the_first_half(duration in 0.0..5000.0f64, offset in prop::option::of(any::<f64>())) {
+            let (start, end) = sampled_range(duration, offset);
+            prop_assert!(start >= 0.0 && start <= duration * 0.5);
+            prop_assert!(end >= start && end <= duration.max(start));
+        }
+
+        #[test]
+        fn retries_stay_in_their_segment(a in 0.0..5000.0f64, len in 0.0..600.0f64, at in 0.0..1.0f64) {
+            let segment = (a, a + len);
+            for t in resample_times(segment) {
+                prop_assert!(t >= segment.0 && t <= segment.1);
+            }
+            let mut t = a + len * at;
+            let mut steps = 0;
+            while let Some(next) = black_retry(t, segment) {
+                prop_assert!(next > t && next <= segment.1);
+                t = next;
+                steps += 1;
+                prop_assert!(steps <= 1_000_000, "black retries never end");
+            }
+        }
+
+        #[test]
+        fn a_full_round_of_black_retries_fits_in_the_segment(a in 0.0..5000.0f64, len in 0.001..600.0f64) {
+            let segment = (a, a + len);
+            let mut t = a;
+            for _ in 0..MAX_BLACK_RETRIES {
+                let next = black_retry(t, segment);
+                prop_assert!(next.is_some());
+                t = next.unwrap();
+            }
+            prop_assert!(t < segment.1);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use crate::exec;
+
+/// A step run on each sheet once it's composed, such as an optimizer or a
+/// watermarking tool. Steps run in order, before the scaled copies are made.
+pub trait PostProcess: Send + Sync {
+    /// The step as shown in dry runs and errors.
+    fn describe(&self, image: &Path) -> String;
+
+    /// Process the sheet at `image` in place (or next to it).
+    fn run(&self, image: &Path) -> Result<()>;
+}
+
+/// `--post-cmd`: a `sh` command line run on each sheet, with `{}` standing for
+/// the sheet's path (appended when there's no `{}`).
+#[derive(Clone, Debug)]
+pub struct ShellCommand(String);
+
+impl ShellCommand {
+    pub fn parse(template: &str) -> Result<ShellCommand, String> {
+        if template.trim().is_empty() {
+            return Err("expected a command".to_string());
+        }
+        Ok(ShellCommand(template.to_string()))
+    }
+
+    fn line(&self, image: &Path) -> String {
+        let path = exec::shell_quote(image.as_os_str());
+        if self.0.contains("{}") {
+            self.0.replace("{}", &path)
+        } else {
+            format!("{} {}", self.0, path)
+        }
+    }
+}
+
+impl PostProcess for ShellCommand {
+    fn describe(&self, image: &Path) -> String {
+        self.line(image)
+    }
+
+    fn run(&self, image: &Path) -> Result<()> {
+        let line = self.line(image);
+        exec::run(Command::new("sh").arg("-c").arg(&line))
+            .with_context(|| format!("Post-processing command failed: {}", line))?;
+        Ok(())
+    }
+}
+
+/// Run each of `steps` on `image`, stopping at the first that fails.
+pub fn run_all(steps: &[Arc<dyn PostProcess>], image: &Path) -> Result<()> {
+    for step in steps {
+        step.run(image)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use crate::exec;
+use crate::mosaic::{Job, Tile};
+use crate::sandbox;
+
+/// Width frames are shrunk to for judging detail.
+const ANALYSIS_WIDTH: u32 = 64;
+
+/// How a frame is cut down to a square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SquareCrop {
+    /// Keep the middle of the frame.
+    Center,
+    /// Keep the part of the frame with the most detail (faces, text, action),
+    /// rather than, say, an empty sky.
+    Smart,
+}
+
+/// `<sheet stem>_square.jpg` next to the sheet.
+pub fn square_output_path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_square.jpg", stem))
+}
+
+/// `<sheet stem>_poster.jpg` next to the sheet.
+pub fn poster_output_path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_poster.jpg", stem))
+}
+
+/// The index of the most detailed of `tiles`, with its pixels shrunk to
+/// `ANALYSIS_WIDTH` x `analysis_height` grey.
+fn best_tile(job: &Job, tiles: &[Tile], analysis_height: u32) -> Result<(usize, Vec<u8>)> {
+    // The busiest frame makes the best poster; black and flat frames lose.
+    let mut best: Option<(u64, usize, Vec<u8>)> = None;
+    for (i, tile) in tiles.iter().enumerate() {
+        if tile.blank {
+            continue;
+        }
+        let pixels = gray_pixels(job, i, ANALYSIS_WIDTH, analysis_height)?;
+        let score = detail(&pixels, ANALYSIS_WIDTH as usize).iter().sum();
+        if best.as_ref().is_none_or(|(s, _, _)| score > *s) {
+            best = Some((score, i, pixels));
+        }
+    }
+    match best {
+        Some((_, index, pixels)) => Ok((index, pixels)),
+        None => anyhow::bail!("No extracted frame to make a poster from"),
+    }
+}
+
+/// Height of a frame shrunk to `ANALYSIS_WIDTH`.
+fn analysis_height(job: &Job) -> u32 {
+    let (width, height) = job.info.display_size().unwrap_or((16, 9));
+    ((ANALYSIS_WIDTH as f64 * height as f64 / width as f64).round() as u32).max(1)
+}
+
+/// The index of the most detailed of `tiles`, the one a poster is made from.
+pub fn poster_tile(job: &Job, tiles: &[Tile]) -> Result<usize> {
+    Ok(best_tile(job, tiles, analysis_height(job))?.0)
+}
+
+/// The poster frame (see `poster_tile`) shrunk to `width` pixels across, as
+/// rows of RGB bytes.
+pub fn poster_pixels(job: &Job, tiles: &[Tile], width: u32) -> Result<Vec<u8>> {
+    let index = poster_tile(job, tiles)?;
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-v", "error", "-i"])
+        .arg(job.tile_path(index))
+        .args([
+            "-vf", &format!("scale={}:-2:flags=area", width),
+            "-pix_fmt", "rgb24",
+            "-f", "rawvideo",
+            "-",
+        ]))
+        .with_context(|| format!("Failed to read the pixels of tile {}", index + 1))?;
+    if output.stdout.is_empty() || output.stdout.len() % (3 * width as usize) != 0 {
+        anyhow::bail!("Unexpected pixel data for tile {}", index + 1);
+    }
+    Ok(output.stdout)
+}
+
+/// Write the most detailed of `tiles` at the size it was extracted, as a
+/// poster for the video.
+pub fn write_poster(job: &Job, tiles: &[Tile], output: &Path) -> Result<()> {
+    let index = poster_tile(job, tiles)?;
+    exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .arg("-i")
+        .arg(job.tile_path(index))
+        .args(["-q:v", "2", "-update", "1", "-y"])
+        .arg(output))
+        .with_context(|| format!("Failed to write poster {}", output.display()))?;
+    Ok(())
+}
+
+/// Write a `size` x `size` poster cut from the most detailed of `tiles`.
+pub fn write_square(job: &Job, tiles: &[Tile], size: u32, crop: SquareCrop, output: &Path) -> Result<()> {
+    let analysis_height = analysis_height(job);
+    let (index, pixels) = best_tile(job, tiles, analysis_height)?;
+
+    let offset = match crop {
+        SquareCrop::Center => 0.5,
+        SquareCrop::Smart => busiest_window(&pixels, ANALYSIS_WIDTH as usize, analysis_height as usize),
+    };
+    // The crop runs on the full-size tile, so the offset is a fraction of the slack.
+    let filter = format!(
+        "crop='min(iw,ih)':'min(iw,ih)':'(iw-min(iw,ih))*{offset:.4}':'(ih-min(iw,ih))*{offset:.4}',scale={size}:{size}"
+    );
+    exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .arg("-i")
+        .arg(job.tile_path(index))
+        .args(["-vf", &filter, "-q:v", "2", "-update", "1", "-y"])
+        .arg(output))
+        .with_context(|| format!("Failed to write square poster {}", output.display()))?;
+    Ok(())
+}
+
+/// Tile `index` shrunk to `width` x `height` grey pixels.
+fn gray_pixels(job: &Job, index: usize, width: u32, height: u32) -> Result<Vec<u8>> {
+    let output = exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-v", "error", "-i"])
+        .arg(job.tile_path(index))
+        .args([
+            "-vf", &format!("scale={}:{}:flags=area", width, height),
+            "-pix_fmt", "gray",
+            "-f", "rawvideo",
+            "-",
+        ]))
+        .with_context(|| format!("Failed to analyze tile {}", index + 1))?;
+    if output.stdout.len() != (width * height) as usize {
+        anyhow::bail!("Unexpected pixel data for tile {}", index + 1);
+    }
+    Ok(output.stdout)
+}
+
+/// Gradient magnitude (|dx| + |dy|) at every pixel.
+fn detail(pixels: &[u8], width: usize) -> Vec<u64> {
+    let height = pixels.len() / width;
+    let at = |x: usize, y: usize| pixels[y * width + x] as i32;
+    let mut energy = vec![0; pixels.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let dx = if x + 1 < width { (at(x + 1, y) - at(x, y)).abs() } else { 0 };
+            let dy = if y + 1 < height { (at(x, y + 1) - at(x, y)).abs() } else { 0 };
+            energy[y * width + x] = (dx + dy) as u64;
+        }
+    }
+    energy
+}
+
+/// Where the square window with the most detail sits along the frame's long
+/// side, as a fraction of the room it has to move (0 = left/top).
+fn busiest_window(pixels: &[u8], width: usize, height: usize) -> f64 {
+    let energy = detail(pixels, width);
+    let landscape = width >= height;
+    let (long, side) = if landscape { (width, height) } else { (height, width) };
+    if long == side {
+        return 0.5;
+    }
+    // Detail per column (landscape) or per row (portrait).
+    let mut lines = vec![0u64; long];
+    for y in 0..height {
+        for x in 0..width {
+            lines[if landscape { x } else { y }] += energy[y * width + x];
+        }
+    }
+
+    let mut window: u64 = lines[..side].iter().sum();
+    let (mut best, mut best_start) = (window, 0);
+    for start in 1..=long - side {
+        window = window + lines[start + side - 1] - lines[start - 1];
+        if window > best {
+            (best, best_start) = (window, start);
+        }
+    }
+    if best == 0 {
+        return 0.5;
+    }
+    best_start as f64 / (long - side) as f64
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::mosaic::{Job, Tile};
+use crate::probe::{MediaInfo, Stream};
+use crate::sandbox;
+use crate::Options;
+
+/// Length of the clip taken at each tile.
+const CLIP_SECONDS: f64 = 2.0;
+
+/// Width of the preview video.
+const PREVIEW_WIDTH: u32 = 480;
+
+/// Loudness the preview's audio is normalized to (EBU R128, as for streaming).
+const TARGET_LUFS: f64 = -16.0;
+
+/// Which audio track goes into the preview video (`--preview-audio`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PreviewAudio {
+    /// The default track, passing over commentary and audio description.
+    Auto,
+    /// A silent preview.
+    None,
+    /// The nth audio track, counted from 1 as in the audio summary.
+    Track(usize),
+    /// The first track tagged with this language (`eng`, `jpn`, ...).
+    Language(String),
+}
+
+impl PreviewAudio {
+    /// Parse an `auto`, `none`, track number or language code.
+    pub fn parse(value: &str) -> Result<PreviewAudio, String> {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "auto" => Ok(PreviewAudio::Auto),
+            "none" => Ok(PreviewAudio::None),
+            number if number.bytes().all(|b| b.is_ascii_digit()) => match number.parse() {
+                Ok(n) if n > 0 => Ok(PreviewAudio::Track(n)),
+                _ => Err("audio tracks are counted from 1".to_string()),
+            },
+            language if language.len() >= 2 && language.bytes().all(|b| b.is_ascii_alphabetic()) => {
+                Ok(PreviewAudio::Language(language.to_string()))
+            }
+            _ => Err(format!("expected auto, none, a track number or a language code, not {:?}", value)),
+        }
+    }
+
+    /// The track of `info` this picks, if any.
+    fn pick<'a>(&self, info: &'a MediaInfo) -> Option<&'a Stream> {
+        let mut tracks = info.streams.iter().filter(|s| s.codec_type == "audio");
+        match self {
+            PreviewAudio::None => None,
+            PreviewAudio::Track(n) => tracks.nth(n - 1),
+            PreviewAudio::Language(language) => {
+                tracks.find(|s| s.tags.get("language").is_some_and(|l| l.eq_ignore_ascii_case(language)))
+            }
+            PreviewAudio::Auto => {
+                let tracks: Vec<&Stream> = tracks.collect();
+                let main: Vec<&Stream> = tracks.iter().copied().filter(|s| !is_secondary(s)).collect();
+                let flagged = |s: &&Stream| s.disposition.get("default") == Some(&1);
+                main.iter().copied().find(flagged)
+                    .or(main.first().copied())
+                    .or(tracks.first().copied())
+            }
+        }
+    }
+}
+
+/// Whether an audio track is commentary or audio description rather than
+/// the programme's own sound, by its disposition or title.
+fn is_secondary(track: &Stream) -> bool {
+    let flagged = ["comment", "visual_impaired", "descriptions"]
+        .iter()
+        .any(|d| track.disposition.get(*d) == Some(&1));
+    let title = track.tags.get("title").map(|t| t.to_lowercase()).unwrap_or_default();
+    flagged || ["commentary", "description", "descriptive"].iter().any(|w| title.contains(w))
+}
+
+/// `<sheet stem>_preview.mp4` next to the sheet.
+pub fn path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_preview.mp4", stem))
+}
+
+/// Write a short preview video of the job's video: a clip of
+/// `CLIP_SECONDS` from each of `tiles`' frames, in time order, with the
+/// audio track `opts.preview_audio` picks (loudness-normalized with
+/// `opts.preview_loudnorm`).
+pub fn write(job: &mut Job, tiles: &[Tile], sheet: &Path, opts: &Options) -> Result<()> {
+    let audio = opts.preview_audio.pick(&job.info).map(|s| s.index);
+    if audio.is_none() && matches!(opts.preview_audio, PreviewAudio::Track(_) | PreviewAudio::Language(_)) {
+        job.warnings.push("preview has no sound: no audio track matches --preview-audio".to_string());
+    }
+    let mut times: Vec<f64> = tiles.iter().filter(|t| !t.blank).map(|t| t.shown).collect();
+    times.sort_by(f64::total_cmp);
+    if times.is_empty() {
+        anyhow::bail!("No frames to make a preview from");
+    }
+
+    let src = job.source();
+    let mut cmd = sandbox::command("ffmpeg", job.sandbox());
+    let mut graph = String::new();
+    let mut inputs = String::new();
+    for (i, &time) in times.iter().enumerate() {
+        let start = (time - CLIP_SECONDS / 2.0).max(0.0);
+        cmd.args(["-ss", &format!("{:.3}", start), "-t", &format!("{:.3}", CLIP_SECONDS), "-i", src.path]);
+        graph += &format!(
+            "[{}:{}]scale={}:-2,setsar=1,fps=25,format=yuv420p[v{}];",
+            i, src.stream, PREVIEW_WIDTH, i
+        );
+        inputs += &format!("[v{}]", i);
+        if let Some(index) = audio {
+            graph += &format!("[{}:{}]aformat=sample_rates=48000:channel_layouts=stereo[a{}];", i, index, i);
+            inputs += &format!("[a{}]", i);
+        }
+    }
+    let with_audio = audio.is_some() as u8;
+    graph += &format!("{}concat=n={}:v=1:a={}[v]", inputs, times.len(), with_audio);
+    if audio.is_some() {
+        graph += "[a]";
+        if opts.preview_loudnorm {
+            graph += &format!(";[a]loudnorm=I={}:TP=-1.5:LRA=11[an]", TARGET_LUFS);
+        }
+    }
+    cmd.args(["-filter_complex", &graph, "-map", "[v]"]);
+    if audio.is_some() {
+        cmd.args(["-map", if opts.preview_loudnorm { "[an]" } else { "[a]" }, "-c:a", "aac", "-b:a", "128k", "-ar", "48000"]);
+    }
+    let output = path(sheet);
+    cmd.args(["-c:v", opts.preview_codec]);
+    match opts.preview_codec {
+        "libx264" => cmd.args(["-preset", "veryfast", "-crf", "28"]),
+        _ => cmd.args(["-q:v", "5"]),
+    };
+    exec::run(cmd
+        .args(["-movflags", "+faststart", "-y"])
+        .arg(&output))
+        .with_context(|| format!("Failed to write preview {}", output.display()))?;
+    Ok(())
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io;
+use std::process::Command;
+use crate::exec;
+use crate::retry::{self, PersistentIoError, RetryPolicy};
+use crate::sandbox::{self, Sandbox};
+
+/// Container and stream metadata for a video, from a single ffprobe run.
+#[derive(Debug, Default, Deserialize)]
+pub struct MediaInfo {
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub streams: Vec<Stream>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+    /// Anything ffprobe complained about while probing.
+    #[serde(skip)]
+    pub diagnostics: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Format {
+    #[serde(default, deserialize_with = "de_opt_f64")]
+    pub duration: Option<f64>,
+    #[serde(default, deserialize_with = "de_opt_f64")]
+    pub start_time: Option<f64>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Stream {
+    pub index: usize,
+    #[serde(default)]
+    pub codec_type: String,
+    #[serde(default)]
+    pub codec_name: String,
+    #[serde(default)]
+    pub codec_tag_string: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Base frame rate as a fraction ("30000/1001").
+    #[serde(default)]
+    pub r_frame_rate: String,
+    /// Average frame rate; differs from `r_frame_rate` for variable frame rate video.
+    #[serde(default)]
+    pub avg_frame_rate: String,
+    pub channels: Option<u32>,
+    #[serde(default)]
+    pub channel_layout: String,
+    #[serde(default, deserialize_with = "de_opt_f64")]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub disposition: HashMap<String, i64>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub side_data_list: Vec<SideData>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SideData {
+    #[serde(default)]
+    pub side_data_type: String,
+    /// Set on "Display Matrix" side data for rotated (typically phone) video.
+    pub rotation: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Chapter {
+    #[serde(default, deserialize_with = "de_opt_f64")]
+    pub start_time: Option<f64>,
+    #[serde(default, deserialize_with = "de_opt_f64")]
+    pub end_time: Option<f64>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+/// Durations disagree when they differ by more than 5 seconds and 10%.
+fn durations_disagree(a: f64, b: f64) -> bool {
+    let diff = (a - b).abs();
+    diff > 5.0 && diff > 0.1 * a.max(b)
+}
+
+impl Stream {
+    pub fn is_attached_pic(&self) -> bool {
+        self.disposition.get("attached_pic").copied().unwrap_or(0) != 0
+    }
+
+    /// Frames per second, when ffprobe knows it ("0/0" when it doesn't).
+    pub fn frame_rate(&self) -> Option<f64> {
+        let (num, den) = self.r_frame_rate.split_once('/')?;
+        let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+        (num > 0.0 && den > 0.0).then(|| num / den)
+    }
+}
+
+/// ffprobe reports numbers as strings ("12.345000"); missing or "N/A" becomes `None`.
+fn de_opt_f64<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    let value: Option<String> = Option::deserialize(d)?;
+    Ok(value.and_then(|v| v.trim().parse().ok()))
+}
+
+/// The ffprobe command `probe` runs.
+pub fn command(video_path: &str, sandbox: Option<&Sandbox>) -> Command {
+    let mut cmd = sandbox::command("ffprobe", sandbox);
+    cmd.args([
+        "-v", "error",
+        "-show_format",
+        "-show_streams",
+        "-show_chapters",
+        "-of", "json",
+        video_path,
+    ]);
+    cmd
+}
+
+/// Probe a video's container and streams with ffprobe, retrying when it
+/// fails with a transient I/O error.
+pub fn probe(video_path: &str, sandbox: Option<&Sandbox>, retry: &RetryPolicy) -> Result<MediaInfo> {
+    let mut attempt = 1;
+    let output = loop {
+        let output = exec::output(&mut command(video_path, sandbox))
+            .with_context(|| "Failed to run ffprobe")?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() || !retry::is_transient_message(&stderr) {
+            break output;
+        }
+        if attempt > retry.retries {
+            return Err(PersistentIoError {
+                what: format!("probe {}", video_path),
+                attempts: attempt,
+                source: io::Error::other(stderr.trim().to_string()),
+            }.into());
+        }
+        retry.backoff(attempt);
+        attempt += 1;
+    };
+
+    // ffprobe prints nothing at all when it can't open the input; keep the
+    // diagnostics so the caller can still tell why.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = if stdout.trim().is_empty() { "{}" } else { &stdout };
+    let mut info: MediaInfo = serde_json::from_str(json)
+        .with_context(|| format!("Failed to parse ffprobe output for {}", video_path))?;
+    info.diagnostics = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    Ok(info)
+}
+
+impl MediaInfo {
+    /// Duration in seconds that is safe to sample within, plus a warning when
+    /// the container and video stream disagree about it.
+    ///
+    /// Bad remuxes often carry a container duration far longer than the video
+    /// actually runs (or a stream duration in the wrong time base). Sampling
+    /// past the real end yields no frames, so on disagreement the shorter of
+    /// the two is used.
+    pub fn duration(&self) -> Option<(f64, Option<String>)> {
+        let container = self.format.duration.filter(|d| *d > 0.0);
+        let stream = self.video_stream().and_then(|s| s.duration).filter(|d| *d > 0.0);
+
+        match (container, stream) {
+            (Some(c), Some(s)) if durations_disagree(c, s) => {
+                let used = c.min(s);
+                let warning = format!(
+                    "container duration {:.1}s disagrees with video stream duration {:.1}s; using {:.1}s",
+                    c, s, used
+                );
+                Some((used, Some(warning)))
+            }
+            (Some(c), _) => Some((c, None)),
+            (None, Some(s)) => Some((s, None)),
+            (None, None) => None,
+        }
+    }
+
+    /// Timestamp of the first packet; FFmpeg seeks are relative to this.
+    pub fn start_time(&self) -> f64 {
+        self.format.start_time.unwrap_or(0.0)
+    }
+
+    /// Title of the chapter playing `secs` after the start ("Chapter N" when
+    /// it has none), if the video has chapters.
+    pub fn chapter_at(&self, secs: f64) -> Option<String> {
+        let t = secs + self.start_time();
+        let (n, chapter) = self.chapters.iter().enumerate().find(|(_, c)| {
+            c.start_time.is_some_and(|start| start <= t) && c.end_time.is_none_or(|end| t < end)
+        })?;
+        Some(chapter.tags.get("title").cloned().unwrap_or_else(|| format!("Chapter {}", n + 1)))
+    }
+
+    /// The first real video stream, if any. Cover art embedded in mkv/mp4/mp3
+    /// files shows up as a video stream too, but is a single still image.
+    pub fn video_stream(&self) -> Option<&Stream> {
+        self.streams.iter().find(|s| s.codec_type == "video" && !s.is_attached_pic())
+    }
+
+    /// Explain why there is no usable video stream, for files where
+    /// `video_stream` returned `None`.
+    pub fn missing_video_reason(&self) -> String {
+        let has_audio = self.streams.iter().any(|s| s.codec_type == "audio");
+        let has_cover = self.streams.iter().any(|s| s.is_attached_pic());
+        match (has_audio, has_cover) {
+            (true, true) => "no video stream (audio-only file with cover art)".to_string(),
+            (true, false) => "no video stream (audio-only file)".to_string(),
+            (false, true) => "no video stream (only attached cover art)".to_string(),
+            (false, false) => "no video stream".to_string(),
+        }
+    }
+
+    /// Resolution of the first video stream as `WIDTHxHEIGHT`.
+    pub fn resolution(&self) -> String {
+        match self.video_stream() {
+            Some(Stream { width: Some(w), height: Some(h), .. }) => format!("{}x{}", w, h),
+            _ => String::new(),
+        }
+    }
+
+    /// Size of the first video stream as displayed, i.e. with rotation metadata
+    /// applied the same way FFmpeg auto-rotates extracted frames.
+    pub fn display_size(&self) -> Option<(u32, u32)> {
+        let stream = self.video_stream()?;
+        let (w, h) = (stream.width?, stream.height?);
+        let rotation = stream.side_data_list.iter()
+            .find_map(|sd| sd.rotation)
+            .or_else(|| stream.tags.get("rotate").and_then(|r| r.parse().ok()))
+            .unwrap_or(0.0);
+        if (rotation.abs().round() as i64) % 180 == 90 {
+            Some((h, w))
+        } else {
+            Some((w, h))
+        }
+    }
+
+    /// Why the content looks DRM-protected or otherwise encrypted, if it does.
+    ///
+    /// Catches CENC-encrypted MP4/MOV (Widevine, PlayReady, FairPlay sample
+    /// entries and protection side data) and encrypted HLS whose keys can't be
+    /// fetched, which would otherwise only show up as minutes of failed decoding.
+    pub fn encryption(&self) -> Option<String> {
+        for stream in &self.streams {
+            if matches!(stream.codec_tag_string.as_str(), "encv" | "enca" | "drmi" | "drms") {
+                return Some(format!(
+                    "stream #{} uses encrypted sample entry '{}'",
+                    stream.index, stream.codec_tag_string
+                ));
+            }
+            if stream.side_data_list.iter().any(|sd| sd.side_data_type.contains("Encryption")) {
+                return Some(format!("stream #{} carries encryption metadata", stream.index));
+            }
+        }
+
+        // FFmpeg's own messages, matched as whole words so file names and
+        // metadata ("licence", "Unencrypted") don't trip them.
+        let diagnostics = self.diagnostics.to_lowercase();
+        let words: Vec<&str> = diagnostics.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+        let markers: [&[&str]; 5] = [
+            &["unable", "to", "open", "key", "file"],
+            &["failed", "to", "open", "key"],
+            &["decryption", "key"],
+            &["encryption", "info"],
+            &["drm", "protected", "stream"],
+        ];
+        if markers.iter().any(|m| words.windows(m.len()).any(|w| w == *m)) {
+            return Some("ffprobe reports encrypted content without a usable key".to_string());
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_diagnostics(diagnostics: &str) -> MediaInfo {
+        MediaInfo { diagnostics: diagnostics.to_string(), ..MediaInfo::default() }
+    }
+
+    #[test]
+    fn ffmpeg_key_errors_mean_encryption() {
+        for diagnostics in [
+            "[hls @ 0x55d1] Unable to open key file https://example.com/key.bin",
+            "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x5581] Invalid decryption key len 8 provided",
+            "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x5581] Incorrect number of samples in encryption info",
+            "[asf @ 0x5581] DRM protected stream detected, decoding will likely fail!",
+        ] {
+            assert!(with_diagnostics(diagnostics).encryption().is_some(), "{}", diagnostics);
+        }
+    }
+
+    #[test]
+    fn ordinary_text_isnt_encryption() {
+        for diagnostics in [
+            "",
+            "[mov,mp4,m4a,3gp,3g2,mj2 @ 0x5581] stream 0, timescale not set",
+            "/media/Creative Commons licence/film.mp4: Invalid data found when processing input",
+            "/media/Unencrypted/keyframes.mkv: End of file",
+            "[matroska,webm @ 0x5581] Could not find codec parameters for stream 1 (Subtitle: hdmv_pgs_subtitle)",
+        ] {
+            assert_eq!(with_diagnostics(diagnostics).encryption(), None, "{}", diagnostics);
+        }
+    }
+
+    #[test]
+    fn encrypted_sample_entries_and_side_data_mean_encryption() {
+        let info = MediaInfo {
+            streams: vec![Stream { index: 0, codec_tag_string: "encv".to_string(), ..Stream::default() }],
+            ..MediaInfo::default()
+        };
+        assert!(info.encryption().is_some());
+
+        let side_data = SideData { side_data_type: "Encryption info".to_string(), rotation: None };
+        let info = MediaInfo {
+            streams: vec![Stream { index: 1, codec_tag_string: "avc1".to_string(), side_data_list: vec![side_data], ..Stream::default() }],
+            ..MediaInfo::default()
+        };
+        assert!(info.encryption().is_some());
+
+        let info = MediaInfo {
+            streams: vec![Stream { index: 0, codec_tag_string: "avc1".to_string(), ..Stream::default() }],
+            ..MediaInfo::default()
+        };
+        assert_eq!(info.encryption(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Whether the status line is drawn at all.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What the status line shows: the batch's part and the current stage.
+static LINE: Mutex<(String, String)> = Mutex::new((String::new(), String::new()));
+
+/// Width the line is cut to when the terminal's isn't known.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Draw a status line at the bottom of the terminal, if standard error is one.
+pub fn enable() {
+    ENABLED.store(io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+/// Set the stage of the current file ("extracting frame 3/9", "overlay").
+pub fn stage(stage: impl Into<String>) {
+    if ENABLED.load(Ordering::Relaxed) {
+        let mut line = LINE.lock().unwrap();
+        line.1 = stage.into();
+        draw(&line);
+    }
+}
+
+/// Remove the status line, say at the end of a batch.
+pub fn clear() {
+    if ENABLED.load(Ordering::Relaxed) {
+        let mut line = LINE.lock().unwrap();
+        *line = (String::new(), String::new());
+        erase();
+    }
+}
+
+/// Print something with the status line out of the way, then put it back.
+pub fn suspend(print: impl FnOnce()) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return print();
+    }
+    let line = LINE.lock().unwrap();
+    erase();
+    print();
+    draw(&line);
+}
+
+fn erase() {
+    eprint!("\r\x1b[K");
+}
+
+fn draw((batch, stage): &(String, String)) {
+    if batch.is_empty() && stage.is_empty() {
+        return;
+    }
+    let text = match (batch.is_empty(), stage.is_empty()) {
+        (false, false) => format!("{} · {}", batch, stage),
+        (true, _) => stage.clone(),
+        (_, true) => batch.clone(),
+    };
+    let width = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(DEFAULT_WIDTH);
+    let text: String = text.chars().take(width.saturating_sub(1)).collect();
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[K{}", text);
+    let _ = stderr.flush();
+}
+
+/// Files done in a batch, and how long they took, for the time remaining.
+pub struct Batch {
+    done: usize,
+    started: Instant,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch { done: 0, started: Instant::now() }
+    }
+
+    /// Show that `name` is next, with `left` more files after it.
+    pub fn next(&mut self, name: &str, left: usize) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+        let total = self.done + 1 + left;
+        let mut text = String::new();
+        if total > 1 {
+            text = format!("[{}/{}]", self.done + 1, total);
+            if self.done > 0 {
+                let eta = self.started.elapsed() / self.done as u32 * (left + 1) as u32;
+                text += &format!(" ETA {}", format_eta(eta));
+            }
+            text += " ";
+        }
+        text += name;
+        let mut line = LINE.lock().unwrap();
+        *line = (text, String::new());
+        draw(&line);
+        self.done += 1;
+    }
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        clear();
+    }
+}
+
+/// `1h02m`, `4m10s` or `12s`.
+fn format_eta(left: Duration) -> String {
+    let secs = left.as_secs();
+    match secs {
+        3600.. => format!("{}h{:02}m", secs / 3600, secs / 60 % 60),
+        60.. => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}s", secs),
+    }
+}
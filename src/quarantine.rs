@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::exec::{self, Category, CommandFailed};
+
+/// A file that failed, recorded so it can be retried later with `--retry-failed`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub reason: String,
+    /// What kind of failure FFmpeg's stderr points to, when recognized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    /// The end of FFmpeg's stderr, when a tool failure caused it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stderr_tail: String,
+}
+
+impl Entry {
+    pub fn new(path: &Path, err: &anyhow::Error) -> Self {
+        let mut stderr_tail = String::new();
+        let reason: Vec<String> = err.chain()
+            .map(|cause| match cause.downcast_ref::<CommandFailed>() {
+                Some(failed) => {
+                    stderr_tail = failed.stderr_tail.clone();
+                    match failed.category {
+                        Some(category) => format!("{} exited with {} ({})", failed.program, failed.status, category),
+                        None => format!("{} exited with {}", failed.program, failed.status),
+                    }
+                }
+                None => cause.to_string(),
+            })
+            .collect();
+
+        Entry {
+            path: path.to_path_buf(),
+            reason: reason.join(": "),
+            category: exec::category(err),
+            stderr_tail,
+        }
+    }
+}
+
+/// Read a quarantine list written by a previous run.
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read quarantine list {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse quarantine list {}", path.display()))
+}
+
+/// Write the quarantine list, replacing any previous one.
+pub fn write(path: &Path, entries: &[Entry]) -> Result<()> {
+    let json = serde_json::to_string_pretty(entries)?;
+    fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write quarantine list {}", path.display()))
+}
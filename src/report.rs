@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::html;
+use crate::naming;
+use crate::probe;
+use crate::sandbox::Sandbox;
+use crate::text;
+use crate::Options;
+
+/// What was learned about one video.
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    /// The probe results, or why probing failed.
+    info: Result<Summary, String>,
+    /// The video's sheet, if one has been generated.
+    sheet: Option<PathBuf>,
+}
+
+struct Summary {
+    duration: Option<f64>,
+    resolution: String,
+    video_codec: String,
+    audio_codecs: Vec<String>,
+}
+
+/// Probe every video in `dir` and write an HTML report of the collection:
+/// totals, breakdowns by codec and resolution, and a row per file with its
+/// sheet. The report goes to `output`, or `report.html` in `dir`.
+pub fn run(dir: &Path, output: Option<&Path>, opts: &Options) -> Result<()> {
+    let output = output.map(Path::to_path_buf).unwrap_or_else(|| dir.join("report.html"));
+    // ffprobe writes nothing, so the sandbox needs no writable directories.
+    let sandbox = opts.sandbox.then(|| Sandbox::new(Vec::new()));
+
+    let paths = crate::video_files(dir, opts)?;
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        exec::check_interrupted()?;
+        log::info!("Probing: {}", path.display());
+        let size = opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(&path))?.len();
+        let sandbox = sandbox.clone().map(|sandbox| sandbox.reading(&path));
+        let probed = match path.to_str() {
+            Some(video_path) => probe::probe(video_path, sandbox.as_ref(), &opts.retry),
+            None => Err(anyhow::anyhow!("the path isn't valid UTF-8")),
+        };
+        let info = match probed {
+            Ok(info) => Ok(Summary {
+                duration: info.duration().map(|(d, _)| d),
+                resolution: info.resolution(),
+                video_codec: info.video_stream().map(|s| s.codec_name.clone()).unwrap_or_default(),
+                audio_codecs: info.streams.iter()
+                    .filter(|s| s.codec_type == "audio")
+                    .map(|s| s.codec_name.clone())
+                    .collect(),
+            }),
+            Err(e) if exec::is_interrupted(&e) => return Err(e),
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        let sheet = Some(naming::output_path(&path, Some(dir), opts.output_dir.as_deref())).filter(|s| s.is_file());
+        entries.push(Entry { path, size, info, sheet });
+    }
+
+    let base = output.parent().unwrap_or(Path::new(""));
+    let page = render(dir, &entries, base);
+    fs::write(&output, page).with_context(|| format!("Failed to write report {}", output.display()))?;
+    log::info!("Wrote report on {} files to {}", entries.len(), output.display());
+    Ok(())
+}
+
+fn render(dir: &Path, entries: &[Entry], base: &Path) -> String {
+    let probed: Vec<&Summary> = entries.iter().filter_map(|e| e.info.as_ref().ok()).collect();
+    let durations: Vec<f64> = probed.iter().filter_map(|s| s.duration).collect();
+    let total_size: u64 = entries.iter().map(|e| e.size).sum();
+    let total_duration: f64 = durations.iter().sum();
+
+    let title = format!("Library report: {}", dir.display());
+    let mut page = String::new();
+    let _ = write!(
+        page,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        html::escape(&title),
+        html::STYLE,
+    );
+
+    page.push_str("<h2>Summary</h2>\n<table>\n");
+    let mut stat = |name: &str, value: String| {
+        let _ = writeln!(page, "<tr><th>{}</th><td>{}</td></tr>", name, html::escape(&value));
+    };
+    stat("Files", entries.len().to_string());
+    stat("Could not be probed", (entries.len() - probed.len()).to_string());
+    stat("With a sheet", entries.iter().filter(|e| e.sheet.is_some()).count().to_string());
+    stat("Total size", format_size(total_size));
+    stat("Total duration", text::format_time(total_duration));
+    if !durations.is_empty() {
+        let shortest = durations.iter().copied().fold(f64::INFINITY, f64::min);
+        let longest = durations.iter().copied().fold(0.0, f64::max);
+        stat("Average duration", text::format_time(total_duration / durations.len() as f64));
+        stat("Shortest / longest", format!("{} / {}", text::format_time(shortest), text::format_time(longest)));
+    }
+    page.push_str("</table>\n");
+
+    breakdown(&mut page, "Video codecs", probed.iter().map(|s| or_unknown(&s.video_codec)));
+    breakdown(&mut page, "Audio codecs", probed.iter().flat_map(|s| {
+        let codecs: Vec<String> = s.audio_codecs.iter().map(|c| or_unknown(c)).collect();
+        if codecs.is_empty() { vec!["(no audio)".to_string()] } else { codecs }
+    }));
+    breakdown(&mut page, "Resolutions", probed.iter().map(|s| or_unknown(&s.resolution)));
+
+    page.push_str("<h2>Files</h2>\n<table>\n<tr><th>Sheet</th><th>File</th><th>Duration</th><th>Resolution</th><th>Video</th><th>Audio</th><th>Size</th></tr>\n");
+    for entry in entries {
+        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        let sheet = match &entry.sheet {
+            Some(sheet) => {
+                let link = html::escape(&html::href(sheet, base));
+                format!("<a href=\"{0}\"><img class=\"sheet\" src=\"{0}\" alt=\"\" loading=\"lazy\"></a>", link)
+            }
+            None => "(none)".to_string(),
+        };
+        let _ = write!(
+            page,
+            "<tr><td>{}</td><td><a href=\"{}\">{}</a></td>",
+            sheet,
+            html::escape(&html::href(&entry.path, base)),
+            html::escape(&name),
+        );
+        match &entry.info {
+            Ok(s) => {
+                let _ = write!(
+                    page,
+                    "<td class=\"num\">{}</td><td>{}</td><td>{}</td><td>{}</td>",
+                    s.duration.map(text::format_time).unwrap_or_default(),
+                    html::escape(&s.resolution),
+                    html::escape(&s.video_codec),
+                    html::escape(&s.audio_codecs.join(", ")),
+                );
+            }
+            Err(reason) => {
+                let _ = write!(page, "<td colspan=\"4\">Could not be probed: {}</td>", html::escape(reason));
+            }
+        }
+        let _ = writeln!(page, "<td class=\"num\">{}</td></tr>", format_size(entry.size));
+    }
+    page.push_str("</table>\n</body>\n</html>\n");
+    page
+}
+
+/// A table counting how often each value occurs, most common first.
+fn breakdown(page: &mut String, heading: &str, values: impl Iterator<Item = String>) {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    if counts.is_empty() {
+        return;
+    }
+    let total: usize = counts.values().sum();
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    let _ = writeln!(page, "<h2>{}</h2>\n<table>", html::escape(heading));
+    for (value, count) in counts {
+        let _ = writeln!(
+            page,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}%</td></tr>",
+            html::escape(&value), count, count as f64 * 100.0 / total as f64
+        );
+    }
+    page.push_str("</table>\n");
+}
+
+fn or_unknown(value: &str) -> String {
+    if value.is_empty() { "(unknown)".to_string() } else { value.to_string() }
+}
+
+/// Decimal megabytes, or gigabytes from 1000 MB up.
+pub fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / 1_000_000.0;
+    if mb >= 1000.0 {
+        format!("{:.2} GB", mb / 1000.0)
+    } else {
+        format!("{:.2} MB", mb)
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use serde::Serialize;
+use crate::exec::Category;
+use crate::mosaic::Tile;
+use crate::palette::Swatch;
+use crate::quarantine;
+use crate::Created;
+
+/// Whether a record is written to standard output for each file (`--json`).
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Write a record per file from now on; standard output is left to them.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Created,
+    Skipped,
+    Failed,
+}
+
+/// What happened to one file, as a line of JSON.
+#[derive(Serialize)]
+struct Record<'a> {
+    source: &'a Path,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a Path>,
+    /// Duration sampled within, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolution: Option<String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tiles: &'a [Tile],
+    /// Time spent on the file, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seconds: Option<f64>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    colors: &'a [Swatch],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blurhash: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    warnings: &'a [String],
+    /// Why it was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<Category>,
+}
+
+impl<'a> Record<'a> {
+    fn new(source: &'a Path, status: Status) -> Self {
+        Record {
+            source,
+            status,
+            output: None,
+            duration: None,
+            resolution: None,
+            tiles: &[],
+            seconds: None,
+            colors: &[],
+            blurhash: None,
+            warnings: &[],
+            reason: None,
+            error: None,
+            category: None,
+        }
+    }
+}
+
+fn emit(record: &Record) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let line = serde_json::to_string(record).expect("records always serialize");
+    let mut stdout = io::stdout().lock();
+    if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+        log::error!("Failed to write the result of {} to standard output", record.source.display());
+    }
+}
+
+pub fn created(created: &Created, elapsed: Duration) {
+    emit(&Record {
+        output: Some(&created.output),
+        duration: created.info.duration().map(|(duration, _)| duration),
+        resolution: Some(created.info.resolution()).filter(|r| !r.is_empty()),
+        tiles: &created.tiles,
+        seconds: Some(elapsed.as_secs_f64()),
+        colors: &created.colors,
+        blurhash: created.blurhash.as_deref(),
+        warnings: &created.warnings,
+        ..Record::new(&created.video, Status::Created)
+    });
+}
+
+pub fn skipped(source: &Path, reason: &str) {
+    emit(&Record { reason: Some(reason), ..Record::new(source, Status::Skipped) });
+}
+
+pub fn failed(failure: &quarantine::Entry) {
+    emit(&Record {
+        error: Some(&failure.reason),
+        category: failure.category,
+        ..Record::new(&failure.path, Status::Failed)
+    });
+}
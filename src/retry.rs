@@ -0,0 +1,80 @@
+use std::fmt;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// How often to retry operations that can fail transiently on network
+/// filesystems (NFS/SMB returning EIO or ESTALE), with exponential backoff.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Error returned when a transient-looking failure persisted through every retry.
+#[derive(Debug)]
+pub struct PersistentIoError {
+    pub what: String,
+    pub attempts: u32,
+    pub source: io::Error,
+}
+
+impl fmt::Display for PersistentIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "persistent I/O error ({} attempts) while trying to {}: {}",
+            self.attempts, self.what, self.source
+        )
+    }
+}
+
+impl std::error::Error for PersistentIoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl RetryPolicy {
+    /// Run a filesystem operation, retrying transient errors. Other errors
+    /// are returned immediately.
+    pub fn io<T>(&self, what: impl fmt::Display, mut op: impl FnMut() -> io::Result<T>) -> anyhow::Result<T> {
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) if is_transient(&e) => {
+                    if attempt > self.retries {
+                        return Err(PersistentIoError { what: what.to_string(), attempts: attempt, source: e }.into());
+                    }
+                    self.backoff(attempt);
+                    attempt += 1;
+                }
+                Err(e) => return Err(anyhow::Error::new(e).context(format!("Failed to {}", what))),
+            }
+        }
+    }
+
+    /// Sleep before retry number `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) {
+        thread::sleep(self.delay.saturating_mul(2u32.saturating_pow(attempt - 1)));
+    }
+}
+
+/// Errors that network filesystems produce transiently.
+pub fn is_transient(err: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    const TRANSIENT: &[i32] = &[5 /* EIO */, 116 /* ESTALE */];
+    #[cfg(all(unix, not(target_os = "linux")))]
+    const TRANSIENT: &[i32] = &[5 /* EIO */, 70 /* ESTALE */];
+    #[cfg(not(unix))]
+    const TRANSIENT: &[i32] = &[];
+
+    err.raw_os_error().is_some_and(|code| TRANSIENT.contains(&code))
+        || matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::Interrupted)
+}
+
+/// Whether tool output (e.g. ffprobe's stderr) reports a transient I/O error.
+pub fn is_transient_message(message: &str) -> bool {
+    message.contains("Input/output error") || message.contains("Stale file handle")
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{bail, Result};
+
+/// Restricts spawned decoders to the system directories they run from, the
+/// files they're given to read and the directories they need to write into,
+/// with no network access.
+///
+/// Implemented by wrapping each command with bubblewrap (`bwrap`), so it is
+/// only available on Linux.
+#[derive(Clone)]
+pub struct Sandbox {
+    writable: Vec<PathBuf>,
+    readable: Vec<PathBuf>,
+}
+
+/// What the tools need of the system, bound read-only where they exist:
+/// their programs and libraries, fontconfig's setup and caches, and the
+/// dynamic linker's cache.
+const SYSTEM: &[&str] = &[
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/nix",
+    "/etc/fonts", "/etc/ld.so.cache", "/etc/ld.so.conf", "/etc/ld.so.conf.d",
+    "/etc/alternatives", "/etc/localtime", "/var/cache/fontconfig",
+];
+
+impl Sandbox {
+    /// Create a sandbox in which only `writable` directories may be modified.
+    pub fn new(writable: Vec<PathBuf>) -> Self {
+        Sandbox { writable, readable: Vec::new() }
+    }
+
+    /// This sandbox, with `path` readable (a video, a font, another job's
+    /// scratch directory) besides the system's files.
+    pub fn reading(mut self, path: &Path) -> Self {
+        if let Ok(path) = std::path::absolute(path) {
+            self.readable.push(path);
+        }
+        self
+    }
+
+    /// Build a command that runs `program` inside the sandbox.
+    pub fn command(&self, program: &str) -> Command {
+        let mut cmd = Command::new("bwrap");
+        for dir in SYSTEM {
+            cmd.args(["--ro-bind-try", dir, dir]);
+        }
+        cmd.args([
+            "--dev", "/dev",
+            "--proc", "/proc",
+            "--tmpfs", "/tmp",
+            "--unshare-all",
+            "--die-with-parent",
+            "--new-session",
+        ]);
+        // After the tmpfs, so what's in /tmp (uploads, scratch directories)
+        // shows through, with a tool installed somewhere of its own (~/bin,
+        // /opt/ffmpeg).
+        for path in program_dir(program).iter().chain(&self.readable) {
+            cmd.arg("--ro-bind-try").arg(path).arg(path);
+        }
+        for dir in &self.writable {
+            cmd.arg("--bind").arg(dir).arg(dir);
+        }
+        cmd.arg("--").arg(program);
+        cmd
+    }
+}
+
+/// The directory `program` is found in on the PATH, if it's not a system one.
+fn program_dir(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    let found = std::env::split_paths(&path).map(|dir| dir.join(program)).find(|p| p.is_file())?;
+    let dir = fs::canonicalize(found).ok()?.parent()?.to_path_buf();
+    (!SYSTEM.iter().any(|system| dir.starts_with(system))).then_some(dir)
+}
+
+/// Build a command for `program`, sandboxed if a sandbox is given.
+pub fn command(program: &str, sandbox: Option<&Sandbox>) -> Command {
+    match sandbox {
+        Some(sandbox) => sandbox.command(program),
+        None => Command::new(program),
+    }
+}
+
+/// Check up front that sandboxing can work on this system.
+pub fn check_available() -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        bail!("--sandbox is only supported on Linux (it requires bubblewrap)");
+    }
+    let works = Command::new("bwrap")
+        .args(["--ro-bind", "/", "/", "--unshare-all", "--", "true"])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+    if !works {
+        bail!("--sandbox requires a working bubblewrap (`bwrap`) installation with user namespaces enabled");
+    }
+    Ok(())
+}
+
+/// Absolute directory an output file will be written into, for use as a writable bind.
+pub fn output_dir(output_image: &Path) -> Result<PathBuf> {
+    let parent = match output_image.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(fs::canonicalize(parent)?)
+}
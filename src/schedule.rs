@@ -0,0 +1,245 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike};
+
+const MONTHS: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead to look for the next run before deciding a schedule (say,
+/// February 30th) never fires.
+const MAX_LOOKAHEAD_DAYS: i64 = 5 * 366;
+
+/// A cron schedule, in local time: minute, hour, day of month, month and day
+/// of week, each a bit set of the values that match.
+#[derive(Debug)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day of month or day of week given as `*`; as in cron, when both are
+    /// restricted a day matching either one counts.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// Parse a five-field cron expression ("0 3 * * *", "*/15 9-17 * * mon-fri")
+    /// or one of @hourly, @daily, @weekly, @monthly and @yearly.
+    pub fn parse(expr: &str) -> Result<Schedule> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail!("cron expression '{}' needs 5 fields: minute hour day-of-month month day-of-week", expr);
+        }
+        let parse = |i: usize, min, max, names| {
+            field(fields[i], min, max, names).with_context(|| format!("Invalid cron expression '{}'", expr))
+        };
+
+        let mut weekdays = parse(4, 0, 7, WEEKDAYS)?;
+        // 7 is Sunday too.
+        if weekdays & 1 << 7 != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Schedule {
+            minutes: parse(0, 0, 59, &[])?,
+            hours: parse(1, 0, 23, &[])?,
+            days: parse(2, 1, 31, &[])?,
+            months: parse(3, 1, 12, MONTHS)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day<Tz: TimeZone>(&self, t: &DateTime<Tz>) -> bool {
+        let day = self.days & 1 << t.day() != 0;
+        let weekday = self.weekdays & 1 << t.weekday().num_days_from_sunday() != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+        day && self.months & 1 << t.month() != 0
+    }
+
+    /// The first matching minute after `after`, if there is one within a few years.
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let end = after + Duration::days(MAX_LOOKAHEAD_DAYS);
+        while t < end {
+            // Skip non-matching days and hours whole.
+            if !self.matches_day(&t) {
+                t = next_midnight(&t)?;
+            } else if self.hours & 1 << t.hour() == 0 {
+                t += Duration::minutes(60 - t.minute() as i64);
+            } else if self.minutes & 1 << t.minute() == 0 {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+/// The start of the day after `t`'s. Days aren't all 24 hours long: not
+/// the ones the clocks change on.
+fn next_midnight<Tz: TimeZone>(t: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let day = t.date_naive().succ_opt()?;
+    // Where the clocks go forward at midnight, the day starts at the first hour it has.
+    (0..24).find_map(|hour| day.and_hms_opt(hour, 0, 0)?.and_local_timezone(t.timezone()).earliest())
+}
+
+/// Parse one cron field (`*`, `5`, `1-5`, `*/15`, `10-50/10`, `mon,wed`, ...)
+/// into a bit set of the values between `min` and `max` it selects.
+/// `names` are accepted in place of numbers, the first standing for `min`.
+fn field(spec: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(s)) {
+            return Ok(min + i as u32);
+        }
+        s.parse().with_context(|| format!("'{}' is not a number", s))
+    };
+
+    let mut mask = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().ok().filter(|&s| s > 0)
+                    .with_context(|| format!("bad step in '{}'", part))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (lo, hi) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((lo, hi)) => (value(lo)?, value(hi)?),
+            // "5/15" means from 5 onwards.
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if lo < min || hi > max || lo > hi {
+            bail!("'{}' is outside {}-{}", part, min, max);
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, MappedLocalTime, NaiveDate, NaiveDateTime, Utc};
+
+    /// Start of British Summer Time in 2024: 01:00 GMT on Sunday 31 March
+    /// became 02:00 BST.
+    const BST_STARTS: i64 = 1_711_846_800;
+
+    /// London around that change, as `Local` would be there.
+    #[derive(Clone, Copy, Debug)]
+    struct London;
+
+    impl London {
+        fn offset_at(utc: i64) -> FixedOffset {
+            FixedOffset::east_opt(if utc < BST_STARTS { 0 } else { 3600 }).unwrap()
+        }
+    }
+
+    impl TimeZone for London {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> London {
+            London
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<FixedOffset> {
+            let secs = local.and_utc().timestamp();
+            match secs {
+                _ if secs < BST_STARTS => MappedLocalTime::Single(London::offset_at(secs)),
+                _ if secs < BST_STARTS + 3600 => MappedLocalTime::None,
+                _ => MappedLocalTime::Single(London::offset_at(secs - 3600)),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            London::offset_at(utc.and_utc().timestamp())
+        }
+    }
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn bits(values: &[u32]) -> u64 {
+        values.iter().fold(0, |mask, v| mask | 1 << v)
+    }
+
+    #[test]
+    fn fields_select_values() {
+        assert_eq!(field("*", 1, 12, MONTHS).unwrap(), bits(&(1..=12).collect::<Vec<_>>()));
+        assert_eq!(field("*/15", 0, 59, &[]).unwrap(), bits(&[0, 15, 30, 45]));
+        assert_eq!(field("10-50/20", 0, 59, &[]).unwrap(), bits(&[10, 30, 50]));
+        assert_eq!(field("5/20", 0, 59, &[]).unwrap(), bits(&[5, 25, 45]));
+        assert_eq!(field("1,3,5", 0, 23, &[]).unwrap(), bits(&[1, 3, 5]));
+        assert_eq!(field("Mon-fri", 0, 7, WEEKDAYS).unwrap(), bits(&[1, 2, 3, 4, 5]));
+        assert_eq!(field("jan,dec", 1, 12, MONTHS).unwrap(), bits(&[1, 12]));
+        for bad in ["60", "5-1", "*/0", "x", "1-"] {
+            assert!(field(bad, 0, 59, &[]).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_expressions_and_shorthands() {
+        let daily = Schedule::parse("@daily").unwrap();
+        assert_eq!((daily.minutes, daily.hours), (1, 1));
+        assert!(daily.any_day && daily.any_weekday);
+        // 7 is Sunday, as 0 is.
+        assert_eq!(Schedule::parse("0 0 * * 7").unwrap().weekdays, 1);
+        let restricted = Schedule::parse("0 0 13 * fri").unwrap();
+        assert!(!restricted.any_day && !restricted.any_weekday);
+        assert!(Schedule::parse("0 0 * *").is_err());
+        assert!(Schedule::parse("0 24 * * *").is_err());
+    }
+
+    #[test]
+    fn next_after_finds_the_first_matching_minute() {
+        let workdays = Schedule::parse("*/15 9-17 * * mon-fri").unwrap();
+        // Friday evening to Monday morning.
+        assert_eq!(workdays.next_after(utc(2024, 3, 1, 17, 50)), Some(utc(2024, 3, 4, 9, 0)));
+        assert_eq!(workdays.next_after(utc(2024, 3, 4, 9, 0)), Some(utc(2024, 3, 4, 9, 15)));
+        // The 13th, or any Friday.
+        let either = Schedule::parse("0 0 13 * fri").unwrap();
+        assert_eq!(either.next_after(utc(2024, 3, 2, 0, 0)), Some(utc(2024, 3, 8, 0, 0)));
+        assert_eq!(either.next_after(utc(2024, 3, 8, 0, 0)), Some(utc(2024, 3, 13, 0, 0)));
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(utc(2024, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn next_after_skips_days_the_clocks_change_on() {
+        // That Sunday is 23 hours long: a day skipped as 24 hours would land
+        // at 01:00 on Monday and miss its midnight.
+        let mondays = Schedule::parse("0 0 * * mon").unwrap();
+        let sunday = London.with_ymd_and_hms(2024, 3, 31, 0, 30, 0).unwrap();
+        let monday = London.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+        assert_eq!(mondays.next_after(sunday), Some(monday));
+    }
+}
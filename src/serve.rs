@@ -0,0 +1,352 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::exec;
+use crate::html;
+use crate::mosaic::{SeekMode, Source};
+use crate::probe;
+use crate::sandbox::{self, Sandbox};
+use crate::Options;
+
+/// Widest frame `/frame` hands out, so a typo can't ask for a huge decode.
+const MAX_WIDTH: u32 = 3840;
+
+/// A cached frame: the file (and its modification time, so edits aren't
+/// served stale), the time in milliseconds and the requested width.
+type Key = (PathBuf, SystemTime, u64, Option<u32>);
+
+/// Encoded frames, dropping the least recently used once over budget.
+struct FrameCache {
+    budget: usize,
+    used: usize,
+    frames: HashMap<Key, Arc<Vec<u8>>>,
+    /// Least recently used first.
+    order: VecDeque<Key>,
+}
+
+impl FrameCache {
+    fn new(budget: usize) -> Self {
+        FrameCache { budget, used: 0, frames: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&mut self, key: &Key) -> Option<Arc<Vec<u8>>> {
+        let frame = self.frames.get(key)?.clone();
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.clone());
+        Some(frame)
+    }
+
+    fn insert(&mut self, key: Key, frame: Arc<Vec<u8>>) {
+        if frame.len() > self.budget || self.frames.contains_key(&key) {
+            return;
+        }
+        while self.used + frame.len() > self.budget {
+            let Some(oldest) = self.order.pop_front() else { break };
+            if let Some(evicted) = self.frames.remove(&oldest) {
+                self.used -= evicted.len();
+            }
+        }
+        self.used += frame.len();
+        self.order.push_back(key.clone());
+        self.frames.insert(key, frame);
+    }
+}
+
+/// What the workers share.
+struct State {
+    root: PathBuf,
+    sandbox: Option<Sandbox>,
+    cache: Mutex<FrameCache>,
+}
+
+/// Which pool of workers handles a request, picked by its `priority`
+/// parameter, so a flood of background requests (say, a script warming the
+/// cache for a whole library) never holds up a player's own requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lane {
+    Interactive,
+    Background,
+}
+
+impl Lane {
+    fn of(url: &str) -> Lane {
+        let query = url.split_once('?').map_or("", |(_, query)| query);
+        let background = query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .any(|(name, value)| html::url_decode(name) == "priority" && html::url_decode(value) == "background");
+        if background { Lane::Background } else { Lane::Interactive }
+    }
+}
+
+/// A failed request: the HTTP status and a message for the body.
+struct Failure(u16, String);
+
+impl Failure {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Failure(status, message.into())
+    }
+}
+
+/// Serve frames of the videos below `dir` over HTTP on `listen` until Ctrl-C.
+///
+/// `GET /frame?src=<path below dir>&t=<seconds>&w=<width>` returns the frame
+/// at that time as a JPEG, scaled to the width if one is given. Frames are
+/// kept in memory (up to `cache_mb`) so hover-seek previews, which ask for
+/// the same handful of positions over and over, are only decoded once.
+///
+/// Requests with `priority=background` are queued for their own
+/// `background_workers`; all others go to the `workers`.
+pub fn run(dir: &Path, listen: &str, cache_mb: usize, workers: usize, background_workers: usize, opts: &Options) -> Result<()> {
+    if workers == 0 || background_workers == 0 {
+        anyhow::bail!("--workers and --background-workers must be at least 1");
+    }
+    let root = dir.canonicalize().with_context(|| format!("Failed to open {}", dir.display()))?;
+    let server = Server::http(listen).map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+    // ffmpeg only writes to its stdout here, so the sandbox needs no writable directories.
+    let state = State {
+        root,
+        sandbox: opts.sandbox.then(|| Sandbox::new(Vec::new())),
+        cache: Mutex::new(FrameCache::new(cache_mb * 1_000_000)),
+    };
+    log::info!("Serving frames of {} on http://{}/frame", dir.display(), listen);
+
+    let (interactive_tx, interactive) = mpsc::channel();
+    let (background_tx, background) = mpsc::channel();
+    let (interactive, background) = (Mutex::new(interactive), Mutex::new(background));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| worker(&interactive, &state, opts));
+        }
+        for _ in 0..background_workers {
+            scope.spawn(|| worker(&background, &state, opts));
+        }
+        accept(&server, interactive_tx, background_tx);
+    });
+    exec::check_interrupted()
+}
+
+/// Queue requests for their lane's workers until Ctrl-C is pressed (or the
+/// server fails), then drop the queues so the workers stop.
+fn accept(server: &Server, interactive: Sender<Request>, background: Sender<Request>) {
+    while !exec::interrupted() {
+        match server.recv_timeout(Duration::from_secs(1)) {
+            Ok(Some(request)) => {
+                let lane = match Lane::of(request.url()) {
+                    Lane::Interactive => &interactive,
+                    Lane::Background => &background,
+                };
+                // Workers only go away once the queues are dropped.
+                let _ = lane.send(request);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::error!("Failed to accept a request: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Answer requests from one lane's queue until it's closed.
+fn worker(queue: &Mutex<Receiver<Request>>, state: &State, opts: &Options) {
+    loop {
+        // The lock is only held while waiting, not while answering.
+        let next = queue.lock().unwrap().recv_timeout(Duration::from_secs(1));
+        match next {
+            Ok(request) => handle(request, state, opts),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+fn handle(request: Request, state: &State, opts: &Options) {
+    // Players fetching previews usually live on another origin.
+    let cors = Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap();
+    let result = match request.method() {
+        Method::Get => respond(request.url(), state, opts),
+        _ => Err(Failure::new(405, "only GET is supported")),
+    };
+    let sent = match result {
+        Ok(frame) => request.respond(Response::from_data(frame.as_slice())
+            .with_header(Header::from_bytes("Content-Type", "image/jpeg").unwrap())
+            .with_header(Header::from_bytes("Cache-Control", "max-age=3600").unwrap())
+            .with_header(cors)),
+        Err(Failure(status, message)) => {
+            if status >= 500 {
+                log::error!("{} {}: {}", status, request.url(), message);
+            }
+            request.respond(Response::from_string(message + "\n").with_status_code(status).with_header(cors))
+        }
+    };
+    if let Err(e) = sent {
+        log::warn!("Failed to send a response: {}", e);
+    }
+}
+
+fn respond(url: &str, state: &State, opts: &Options) -> Result<Arc<Vec<u8>>, Failure> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    if path != "/frame" {
+        return Err(Failure::new(404, "not found"));
+    }
+    let params: HashMap<String, String> = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (html::url_decode(name), html::url_decode(value)))
+        .collect();
+
+    let src = params.get("src").ok_or_else(|| Failure::new(400, "missing src"))?;
+    let time: f64 = params.get("t")
+        .and_then(|t| t.parse().ok())
+        .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+        .ok_or_else(|| Failure::new(400, "t must be a time in seconds"))?;
+    let width = match params.get("w") {
+        Some(w) => Some(w.parse().ok()
+            .filter(|w| (1..=MAX_WIDTH).contains(w))
+            .ok_or_else(|| Failure::new(400, format!("w must be a width from 1 to {}", MAX_WIDTH)))?),
+        None => None,
+    };
+
+    let video = resolve(&state.root, src).ok_or_else(|| Failure::new(404, format!("no video {}", src)))?;
+    let modified = opts.retry.io(format_args!("stat {}", video.display()), || fs::metadata(&video)?.modified())
+        .map_err(|e| Failure::new(500, format!("{:#}", e)))?;
+    let key = (video, modified, (time * 1000.0).round() as u64, width);
+
+    if let Some(frame) = state.cache.lock().unwrap().get(&key) {
+        return Ok(frame);
+    }
+    let frame = match extract(&key.0, time, width, state.sandbox.as_ref(), opts) {
+        Ok(Some(frame)) => Arc::new(frame),
+        Ok(None) => return Err(Failure::new(404, format!("no frame at {:.3}s", time))),
+        Err(e) if exec::is_timed_out(&e) => return Err(Failure::new(504, format!("{:#}", e))),
+        Err(e) => return Err(Failure::new(500, format!("{:#}", e))),
+    };
+    state.cache.lock().unwrap().insert(key, frame.clone());
+    Ok(frame)
+}
+
+/// `src` as a file below `root`; nothing outside it (through `..`, absolute
+/// paths or symlinks) is served.
+fn resolve(root: &Path, src: &str) -> Option<PathBuf> {
+    let relative = Path::new(src);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let path = root.join(relative).canonicalize().ok()?;
+    (path.starts_with(root) && path.is_file()).then_some(path)
+}
+
+/// The frame at `time` as a JPEG, or `None` if there's none (past the end).
+fn extract(video: &Path, time: f64, width: Option<u32>, sandbox: Option<&Sandbox>, opts: &Options) -> Result<Option<Vec<u8>>> {
+    let video_path = video.to_str()
+        .with_context(|| format!("{} isn't a valid UTF-8 path", video.display()))?;
+    let sandbox = sandbox.cloned().map(|sandbox| sandbox.reading(video));
+    let sandbox = sandbox.as_ref();
+    let info = probe::probe(video_path, sandbox, &opts.retry)?;
+    let stream = info.video_stream().ok_or_else(|| anyhow::anyhow!(info.missing_video_reason()))?;
+    let src = Source {
+        path: video_path,
+        stream: stream.index,
+        start_time: info.start_time(),
+        sandbox,
+        frame_format: opts.frame_format,
+        // The command below scales and seeks itself.
+        width: None,
+        seek: SeekMode::Fast,
+        hwaccel: None,
+    };
+    let width = width.or(opts.tile_width);
+
+    let mut cmd = sandbox::command("ffmpeg", src.sandbox);
+    cmd.args([
+        "-v", "error",
+        "-ss", &format!("{:.3}", time),
+        "-i", src.path,
+        "-map", &src.map(),
+        "-frames:v", "1",
+    ]);
+    if let Some(width) = width {
+        cmd.args(["-vf", &format!("scale={}:-2", width)]);
+    }
+    cmd.args(["-q:v", "3", "-f", "image2pipe", "-c:v", "mjpeg", "-"]);
+    let output = exec::run_with_timeout(&mut cmd, opts.frame_timeout)
+        .with_context(|| format!("Failed to extract the frame at {:.3}s", time))?;
+
+    Ok((!output.stdout.is_empty()).then_some(output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_stays_inside_the_root() {
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.mp4"), b"").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("Shows/One")).unwrap();
+        fs::write(root.join("Shows/One/a.mp4"), b"").unwrap();
+
+        assert_eq!(resolve(&root, "Shows/One/a.mp4"), Some(root.join("Shows/One/a.mp4")));
+        assert_eq!(resolve(&root, "Shows/One"), None);
+        assert_eq!(resolve(&root, "Shows/missing.mp4"), None);
+        assert_eq!(resolve(&root, "Shows/One/../One/a.mp4"), None);
+        assert_eq!(resolve(&root, "../secret.mp4"), None);
+        assert_eq!(resolve(&root, "./Shows/One/a.mp4"), None);
+        let absolute = outside.path().join("secret.mp4");
+        assert_eq!(resolve(&root, absolute.to_str().unwrap()), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_rejects_symlinks_out_of_the_root() {
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.mp4"), b"").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("a.mp4"), b"").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret.mp4"), root.join("link.mp4")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.join("elsewhere")).unwrap();
+        std::os::unix::fs::symlink(root.join("a.mp4"), root.join("inside.mp4")).unwrap();
+
+        assert_eq!(resolve(&root, "link.mp4"), None);
+        assert_eq!(resolve(&root, "elsewhere/secret.mp4"), None);
+        assert_eq!(resolve(&root, "inside.mp4"), Some(root.join("a.mp4")));
+    }
+
+    fn key(time: u64) -> Key {
+        (PathBuf::from("a.mp4"), SystemTime::UNIX_EPOCH, time, None)
+    }
+
+    #[test]
+    fn cache_evicts_the_least_recently_used_over_budget() {
+        let mut cache = FrameCache::new(10);
+        cache.insert(key(1), Arc::new(vec![0; 4]));
+        cache.insert(key(2), Arc::new(vec![0; 4]));
+        // Reading 1 makes 2 the oldest.
+        assert!(cache.get(&key(1)).is_some());
+        cache.insert(key(3), Arc::new(vec![0; 4]));
+
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(3)).is_some());
+        assert_eq!(cache.used, 8);
+
+        // A frame bigger than the whole budget isn't kept, and evicts nothing.
+        cache.insert(key(4), Arc::new(vec![0; 11]));
+        assert!(cache.get(&key(4)).is_none());
+        assert_eq!(cache.frames.len(), 2);
+
+        // One filling the budget evicts everything else.
+        cache.insert(key(5), Arc::new(vec![0; 10]));
+        assert_eq!(cache.frames.keys().collect::<Vec<_>>(), [&key(5)]);
+        assert_eq!(cache.used, 10);
+        assert_eq!(cache.order.len(), 1);
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+/// Running totals of every batch run on this machine, kept in a local file
+/// and never sent anywhere.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    /// When counting started, in local time (RFC 3339).
+    pub since: String,
+    pub runs: u64,
+    pub created: u64,
+    /// Files passed over because their sheet was already there.
+    pub kept: u64,
+    /// Files skipped for any other reason (DRM, no video stream, ...).
+    pub skipped: u64,
+    pub failed: u64,
+    /// Failed files by kind of failure (`timeout`, `corrupt input`, ...).
+    pub failures: BTreeMap<String, u64>,
+    /// Time spent making the sheets created.
+    pub seconds_creating: f64,
+    /// Time the kept sheets would have taken to make again, at the average
+    /// time per sheet when they were kept.
+    pub seconds_saved: f64,
+}
+
+/// What one run adds to the totals.
+#[derive(Default)]
+pub struct Tally {
+    pub created: u64,
+    pub kept: u64,
+    pub skipped: u64,
+    /// The kind of each failure, `other` when there's none to tell.
+    pub failures: Vec<String>,
+    pub creating: Duration,
+}
+
+/// `stats.json` in `dir` (`--config-dir`) or the user's config directory.
+pub fn path(dir: Option<&Path>) -> Option<PathBuf> {
+    let dir = dir.map(Path::to_path_buf).or_else(crate::config::user_dir)?;
+    Some(dir.join("stats.json"))
+}
+
+/// Add `tally` to the totals in `path`, holding a lock on it so runs
+/// finishing together don't lose each other's counts.
+pub fn record(path: &Path, tally: &Tally) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.lock().with_context(|| format!("Failed to lock {}", path.display()))?;
+    let mut stats = parse(&mut file, path)?;
+
+    if stats.since.is_empty() {
+        stats.since = Local::now().to_rfc3339();
+    }
+    stats.runs += 1;
+    stats.created += tally.created;
+    stats.seconds_creating += tally.creating.as_secs_f64();
+    if stats.created > 0 {
+        stats.seconds_saved += tally.kept as f64 * stats.seconds_creating / stats.created as f64;
+    }
+    stats.kept += tally.kept;
+    stats.skipped += tally.skipped;
+    stats.failed += tally.failures.len() as u64;
+    for kind in &tally.failures {
+        *stats.failures.entry(kind.clone()).or_default() += 1;
+    }
+
+    let json = serde_json::to_string_pretty(&stats)? + "\n";
+    file.set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| file.write_all(json.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The totals in `path`; none yet if there's no file.
+pub fn read(path: &Path) -> Result<Stats> {
+    match File::open(path) {
+        Ok(mut file) => parse(&mut file, path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Stats::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn parse(file: &mut File, path: &Path) -> Result<Stats> {
+    let mut json = String::new();
+    file.read_to_string(&mut json).with_context(|| format!("Failed to read {}", path.display()))?;
+    if json.trim().is_empty() {
+        return Ok(Stats::default());
+    }
+    serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Start counting over (`thumbnailer stats --reset`).
+pub fn reset(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+/// Print the totals in `path` (`thumbnailer stats`).
+pub fn show(path: &Path) -> Result<()> {
+    let stats = read(path)?;
+    if stats.runs == 0 {
+        println!("No runs counted yet ({}).", path.display());
+        return Ok(());
+    }
+    let processed = stats.created + stats.kept + stats.skipped + stats.failed;
+    let since = stats.since.get(..10).unwrap_or(&stats.since);
+    println!("Since {}: {} runs, {} files processed", since, stats.runs, processed);
+    println!("  created  {}", stats.created);
+    println!("  kept     {} (sheet already there)", stats.kept);
+    println!("  skipped  {}", stats.skipped);
+    println!("  failed   {} ({:.1}%)", stats.failed, percent(stats.failed, processed));
+    let mut kinds: Vec<(&String, &u64)> = stats.failures.iter().collect();
+    kinds.sort_by_key(|&(_, count)| std::cmp::Reverse(*count));
+    for (kind, count) in kinds {
+        println!("    {:<16} {} ({:.1}%)", kind, count, percent(*count, processed));
+    }
+    if stats.created > 0 {
+        println!(
+            "Time creating sheets: {} ({:.1}s a sheet)",
+            duration(stats.seconds_creating),
+            stats.seconds_creating / stats.created as f64
+        );
+    }
+    println!("Time saved by kept sheets: {}", duration(stats.seconds_saved));
+    println!("({})", path.display());
+    Ok(())
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 { 0.0 } else { part as f64 * 100.0 / whole as f64 }
+}
+
+/// `seconds` as `3h 12m`, `4m 05s` or `12s`.
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::mosaic::{Job, Tile};
+use crate::sandbox;
+
+/// Width of each thumbnail in a storyboard sprite.
+const THUMB_WIDTH: u32 = 160;
+
+/// `<sheet stem>_storyboard.jpg` next to the sheet.
+pub fn image_path(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_storyboard.jpg", stem))
+}
+
+/// `<sheet stem>_storyboard.vtt`, the WebVTT track pointing into the sprite.
+pub fn track_path(sheet: &Path) -> PathBuf {
+    image_path(sheet).with_extension("vtt")
+}
+
+/// Write a storyboard for scrubbing previews in players: the job's tiles
+/// shrunk into a sprite `cols` wide, and a WebVTT track giving the part of
+/// the sprite each stretch of the video shows.
+pub fn write(job: &Job, tiles: &[Tile], cols: usize, sheet: &Path) -> Result<()> {
+    let (width, height) = job.info.display_size().unwrap_or((16, 9));
+    // Even, as 4:2:0 JPEG needs.
+    let thumb_height = ((THUMB_WIDTH as f64 * height as f64 / width as f64 / 2.0).round() as u32).max(1) * 2;
+    let cols = cols.clamp(1, tiles.len().max(1));
+    let rows = tiles.len().div_ceil(cols);
+
+    let image = image_path(sheet);
+    exec::run(sandbox::command("ffmpeg", job.sandbox())
+        .args(["-f", "image2", "-i"])
+        .arg(job.tile_pattern())
+        .args([
+            "-vf", &format!("scale={}:{},setsar=1,tile={}x{}", THUMB_WIDTH, thumb_height, cols, rows),
+            "-frames:v", "1",
+            "-q:v", "3",
+            "-update", "1",
+            "-y",
+        ])
+        .arg(&image))
+        .with_context(|| format!("Failed to write storyboard {}", image.display()))?;
+
+    let name = image.file_name().unwrap_or_default().to_string_lossy();
+    let track = track(tiles, job.duration, cols, (THUMB_WIDTH, thumb_height), &name);
+    let path = track_path(sheet);
+    fs::write(&path, track).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// WebVTT cues covering the video from each tile's frame to the next's, in
+/// time order, each pointing at its thumbnail in sprite `image`.
+fn track(tiles: &[Tile], duration: f64, cols: usize, (width, height): (u32, u32), image: &str) -> String {
+    let mut order: Vec<usize> = (0..tiles.len()).collect();
+    order.sort_by(|&a, &b| tiles[a].shown.total_cmp(&tiles[b].shown));
+    let mut vtt = String::from("WEBVTT\n");
+    for (n, &i) in order.iter().enumerate() {
+        // The first cue starts at 0, so scrubbing anywhere finds a thumbnail.
+        let start = if n == 0 { 0.0 } else { tiles[i].shown };
+        let end = order.get(n + 1).map_or(duration, |&next| tiles[next].shown).max(start);
+        let (x, y) = ((i % cols) as u32 * width, (i / cols) as u32 * height);
+        let _ = write!(
+            vtt, "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            cue_time(start), cue_time(end), image, x, y, width, height
+        );
+    }
+    vtt
+}
+
+/// `hh:mm:ss.ttt`, as WebVTT cue timings are written.
+fn cue_time(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
@@ -0,0 +1,227 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Prepare a filename for rendering in the overlay.
+///
+/// Normalizes to NFC (macOS stores names decomposed, which drawtext renders as
+/// base letters followed by stray combining marks), replaces control
+/// characters such as embedded newlines, and shortens names longer than
+/// `max_graphemes` with an ellipsis, never cutting inside a character, emoji
+/// sequence or combining cluster.
+pub fn display_name(name: &str, max_graphemes: usize) -> String {
+    let name: String = name.nfc()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect();
+    truncate_graphemes(&name, max_graphemes)
+}
+
+/// Truncate `text` to at most `max` grapheme clusters, ending in `…` if shortened.
+pub fn truncate_graphemes(text: &str, max: usize) -> String {
+    if text.graphemes(true).count() <= max {
+        return text.to_string();
+    }
+    let mut out: String = text.graphemes(true).take(max.saturating_sub(1)).collect();
+    out.push('…');
+    out
+}
+
+/// Escape a value (such as a file path) for use as a filter option inside an
+/// FFmpeg filtergraph passed with `-vf`/`-filter_complex`.
+///
+/// FFmpeg unescapes filter arguments twice: once when splitting the graph into
+/// filters (where `\ ' [ ] , ;` are special) and once when splitting a
+/// filter's arguments into options (where `\ ' :` are special). Leading and
+/// trailing whitespace is trimmed at both levels, so all whitespace is escaped too.
+pub fn escape_filter_value(value: &str) -> String {
+    let option_level = escape_chars(value, &['\\', '\'', ':']);
+    escape_chars(&option_level, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+fn escape_chars(value: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) || c.is_whitespace() {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Longest slug (in bytes) before it's cut, leaving room for suffixes
+/// within the usual 255-byte limit on file names.
+const MAX_SLUG_LENGTH: usize = 120;
+
+/// Names Windows refuses as files, whatever the extension.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// `name` made safe as a file name on any filesystem: accents dropped, only
+/// ASCII letters, digits, `.`, `_` and `-` kept, runs of anything else
+/// turned into one `-`, and no leading dots or reserved device names.
+pub fn slug(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.nfkd().filter(|&c| !unicode_normalization::char::is_combining_mark(c)) {
+        if c.is_ascii_alphanumeric() || "._-".contains(c) {
+            if c == '.' && out.ends_with('-') {
+                out.pop();
+            }
+            out.push(c);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    let mut end = out.len().min(MAX_SLUG_LENGTH);
+    while !out.is_char_boundary(end) {
+        end -= 1;
+    }
+    let out = out[..end].trim_matches(['-', '.']);
+    match out {
+        "" => "video".to_string(),
+        out if RESERVED_NAMES.contains(&out.split('.').next().unwrap_or_default().to_lowercase().as_str()) => {
+            format!("_{}", out)
+        }
+        out => out.to_string(),
+    }
+}
+
+/// `HH:MM:SS` for a time in seconds.
+pub fn format_clock(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// `h:mm:ss` (or `m:ss`) for a time in seconds.
+pub fn format_time(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    match secs / 3600 {
+        0 => format!("{}:{:02}", secs / 60, secs % 60),
+        h => format!("{}:{:02}:{:02}", h, secs / 60 % 60, secs % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Model of FFmpeg's `av_get_token`: read up to an unquoted, unescaped
+    /// terminator, honoring `\x` escapes and `'...'` quoting, and trimming
+    /// surrounding whitespace. Returns the token and the unparsed rest.
+    fn get_token<'a>(input: &'a str, term: &str) -> (String, &'a str) {
+        let input = input.trim_start_matches([' ', '\n', '\t', '\r']);
+        let mut out = String::new();
+        let mut keep = 0; // length of out that must survive trailing-whitespace trimming
+        let mut chars = input.char_indices().peekable();
+        while let Some(&(i, c)) = chars.peek() {
+            if term.contains(c) {
+                out.truncate(out.trim_end_matches([' ', '\n', '\t', '\r']).len().max(keep));
+                return (out, &input[i..]);
+            }
+            chars.next();
+            match c {
+                '\\' => {
+                    if let Some((_, next)) = chars.next() {
+                        out.push(next);
+                        keep = out.len();
+                    }
+                }
+                '\'' => {
+                    for (_, q) in chars.by_ref() {
+                        if q == '\'' {
+                            break;
+                        }
+                        out.push(q);
+                    }
+                    keep = out.len();
+                }
+                _ => out.push(c),
+            }
+        }
+        out.truncate(out.trim_end_matches([' ', '\n', '\t', '\r']).len().max(keep));
+        (out, "")
+    }
+
+    /// Parse `name=opts` the way the graph parser and then the option parser
+    /// would, returning the option values in order.
+    fn parse_filter(filter: &str) -> Vec<(String, String)> {
+        let (graph_token, rest) = get_token(filter, "[],;");
+        assert!(rest.is_empty(), "filtergraph split early at {:?}", rest);
+        let (_, mut args) = graph_token.split_once('=').expect("filter has arguments");
+
+        let mut options = Vec::new();
+        loop {
+            let (key, rest) = args.split_once('=').expect("option has a key");
+            let (value, rest) = get_token(rest, ":");
+            options.push((key.to_string(), value));
+            match rest.strip_prefix(':') {
+                Some(rest) => args = rest,
+                None => break,
+            }
+        }
+        options
+    }
+
+    fn round_trip(value: &str) -> String {
+        let filter = format!("drawtext=fontfile={}:x=10", escape_filter_value(value));
+        let options = parse_filter(&filter);
+        assert_eq!(options.len(), 2, "{:?} split into {:?}", value, options);
+        assert_eq!(options[1], ("x".to_string(), "10".to_string()));
+        options[0].1.clone()
+    }
+
+    #[test]
+    fn nasty_filenames_round_trip() {
+        let nasty = [
+            "plain.mkv",
+            "it's a trap.mkv",
+            "a:b:c.mp4",
+            "one, two; three.ts",
+            "[group] episode [1080p].mkv",
+            "100% done.mov",
+            "%{pts} %{localtime}.mp4",
+            r"C:\Videos\clip.mp4",
+            r"trailing backslash\",
+            "'quoted'",
+            "  leading and trailing spaces  ",
+            "tab\tand\nnewline",
+            "emoji 🎬 ünïcödé.mkv",
+            "=equals=.mp4",
+            "\\'\\'::;;,,[[]]",
+        ];
+        for name in nasty {
+            assert_eq!(round_trip(name), name);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn any_string_round_trips(value in "[ -~\t\n'\\\\:,;\\[\\]%=é🎬]{0,40}") {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn truncation_stays_on_grapheme_boundaries(name in "\\PC{0,30}", max in 1usize..20) {
+            let out = truncate_graphemes(&name, max);
+            prop_assert!(out.graphemes(true).count() <= max);
+            let kept = out.strip_suffix('…').unwrap_or(&out);
+            prop_assert!(name.starts_with(kept));
+        }
+    }
+
+    #[test]
+    fn slugs_are_plain_ascii() {
+        assert_eq!(slug("Café: «Le Film» (2019).mkv"), "Cafe-Le-Film-2019.mkv");
+        assert_eq!(slug("../..\\secret"), "secret");
+        assert_eq!(slug("CON.mkv"), "_CON.mkv");
+        assert_eq!(slug("日本語"), "video");
+    }
+
+    #[test]
+    fn display_name_normalizes_to_nfc() {
+        let decomposed = "Cafe\u{301}.mov";
+        assert_eq!(display_name(decomposed, 60), "Caf\u{e9}.mov");
+    }
+}
@@ -0,0 +1,151 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+/// Built-in sheet styles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeName {
+    /// Tiles edge to edge, details in a translucent box over the first tile.
+    Classic,
+    /// Spaced tiles on near-black, details in a strip above the grid.
+    Dark,
+    /// Spaced tiles on white, details in a strip above the grid.
+    Light,
+    /// Thin gaps and plain text with no box.
+    Minimal,
+}
+
+/// Where and how the details line sits on the sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Banner {
+    /// In a box of `banner_color` over the top-left tiles.
+    Overlay,
+    /// In a band of `banner_color` added above the grid.
+    Strip,
+    /// Straight onto the tiles, without a box.
+    Plain,
+    /// In a panel of `banner_color` above the grid, with the duration and
+    /// the video and audio codecs under the file's details.
+    Header,
+}
+
+/// Colors and decoration of a sheet. Colors are anything FFmpeg accepts
+/// (`white`, `#1e1e1e`, `black@0.5`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Fills the gaps between tiles and around the edge.
+    pub background: String,
+    /// Color of the details text.
+    pub text: String,
+    /// Outline drawn around every tile.
+    pub border: String,
+    /// Outline width in pixels; 0 draws none.
+    pub border_width: u32,
+    /// Whether every tile casts a shadow down and to the right.
+    pub shadow: bool,
+    /// Gap between tiles, in pixels.
+    pub spacing: u32,
+    /// Gap between the tiles and the edge of the sheet, in pixels.
+    pub margin: u32,
+    pub banner: Banner,
+    pub banner_color: String,
+    /// Height of a strip or header banner in pixels, the text shrunk to
+    /// fit; as tall as its text when `None`.
+    pub banner_height: Option<u32>,
+}
+
+impl ThemeName {
+    pub fn theme(self) -> Theme {
+        let (background, text, border, border_width, spacing, banner, banner_color) = match self {
+            ThemeName::Classic => ("black", "white", "black", 0, 0, Banner::Overlay, "black@0.5"),
+            ThemeName::Dark => ("#1e1e1e", "#e8e8e8", "#3c3c3c", 2, 12, Banner::Strip, "#111111"),
+            ThemeName::Light => ("#ffffff", "#202020", "#c8c8c8", 2, 12, Banner::Strip, "#f0f0f0"),
+            ThemeName::Minimal => ("black", "white", "black", 0, 4, Banner::Plain, "black@0"),
+        };
+        Theme {
+            background: background.to_string(),
+            text: text.to_string(),
+            border: border.to_string(),
+            border_width,
+            shadow: false,
+            spacing,
+            margin: spacing,
+            banner,
+            banner_color: banner_color.to_string(),
+            banner_height: None,
+        }
+    }
+}
+
+/// The `[theme]` table of the config file: a base theme and any fields to
+/// change on top of it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeOverrides {
+    /// Used when `--theme` isn't given. In a directory's config, the theme
+    /// for that directory starts over from it.
+    pub base: Option<ThemeName>,
+    pub background: Option<String>,
+    pub text: Option<String>,
+    pub border: Option<String>,
+    pub border_width: Option<u32>,
+    pub shadow: Option<bool>,
+    /// Sets the margin too, unless that's given.
+    pub spacing: Option<u32>,
+    pub margin: Option<u32>,
+    pub banner: Option<Banner>,
+    pub banner_color: Option<String>,
+    pub banner_height: Option<u32>,
+}
+
+impl ThemeOverrides {
+    /// Apply `over`'s fields on top of these. A base theme in `over` starts over.
+    pub fn merge(&mut self, over: &ThemeOverrides) {
+        if over.base.is_some() {
+            self.clone_from(over);
+            return;
+        }
+        let replace = |field: &mut Option<String>, value: &Option<String>| {
+            if value.is_some() {
+                field.clone_from(value);
+            }
+        };
+        replace(&mut self.background, &over.background);
+        replace(&mut self.text, &over.text);
+        replace(&mut self.border, &over.border);
+        replace(&mut self.banner_color, &over.banner_color);
+        self.border_width = over.border_width.or(self.border_width);
+        self.shadow = over.shadow.or(self.shadow);
+        self.spacing = over.spacing.or(self.spacing);
+        // A new spacing takes the margin with it, as it does when applied.
+        self.margin = if over.spacing.is_some() { over.margin } else { over.margin.or(self.margin) };
+        self.banner = over.banner.or(self.banner);
+        self.banner_height = over.banner_height.or(self.banner_height);
+    }
+
+    /// `name` (or the configured base, or classic) with the overrides applied.
+    pub fn resolve(&self, name: Option<ThemeName>) -> Theme {
+        self.apply(name.or(self.base).unwrap_or(ThemeName::Classic).theme())
+    }
+
+    /// `theme` with the overrides applied, ignoring the base.
+    pub fn apply(&self, mut theme: Theme) -> Theme {
+        let replace = |field: &mut String, value: &Option<String>| {
+            if let Some(value) = value {
+                field.clone_from(value);
+            }
+        };
+        replace(&mut theme.background, &self.background);
+        replace(&mut theme.text, &self.text);
+        replace(&mut theme.border, &self.border);
+        replace(&mut theme.banner_color, &self.banner_color);
+        theme.border_width = self.border_width.unwrap_or(theme.border_width);
+        theme.shadow = self.shadow.unwrap_or(theme.shadow);
+        theme.spacing = self.spacing.unwrap_or(theme.spacing);
+        theme.margin = self.margin.or(self.spacing).unwrap_or(theme.margin);
+        theme.banner = self.banner.unwrap_or(theme.banner);
+        theme.banner_height = self.banner_height.or(theme.banner_height);
+        theme
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Result};
+use crate::exec;
+
+/// Caps on how FFmpeg and ffprobe read, across every command this process
+/// runs, so sheets made from a network share leave room for its other users.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Bytes per second all the tools together may read.
+    pub bandwidth: Option<f64>,
+    /// Tools that may run at once.
+    pub connections: Option<usize>,
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+/// Tools running, for `Limits::connections`.
+static RUNNING: Mutex<usize> = Mutex::new(0);
+static FINISHED: Condvar = Condvar::new();
+
+/// Bytes that may be read before tools are held back (negative once they
+/// read ahead of the limit), and when that was last worked out.
+static ALLOWANCE: Mutex<Option<(f64, Instant)>> = Mutex::new(None);
+
+/// How often waiting commands check whether they may go on.
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Apply `limits` to every command run from now on.
+pub fn set(limits: Limits) -> Result<()> {
+    if limits.bandwidth.is_some() && !cfg!(target_os = "linux") {
+        bail!("--bandwidth-limit is only supported on Linux");
+    }
+    let _ = LIMITS.set(limits);
+    Ok(())
+}
+
+/// The allowance after topping it up for the time since it was last worked
+/// out; at most a second's worth builds up.
+fn allowance(rate: f64) -> std::sync::MutexGuard<'static, Option<(f64, Instant)>> {
+    let mut allowance = ALLOWANCE.lock().unwrap();
+    let now = Instant::now();
+    let (bytes, since) = allowance.unwrap_or((rate, now));
+    *allowance = Some(((bytes + rate * now.duration_since(since).as_secs_f64()).min(rate), now));
+    allowance
+}
+
+/// A command's share of the limits, held while it runs.
+pub struct Permit {
+    counted: bool,
+    /// The child being watched, how much it had read when last looked at
+    /// and whether it's stopped for reading too much.
+    child: Option<(u32, u64, bool)>,
+}
+
+/// Wait until another command may start: a connection is free and reading
+/// hasn't got ahead of the bandwidth limit.
+pub fn start() -> Result<Permit> {
+    let limits = LIMITS.get().copied().unwrap_or_default();
+    if let Some(rate) = limits.bandwidth {
+        while allowance(rate).is_some_and(|(bytes, _)| bytes < 0.0) {
+            exec::check_interrupted()?;
+            thread::sleep(WAIT_INTERVAL);
+        }
+    }
+    let Some(max) = limits.connections else {
+        return Ok(Permit { counted: false, child: None });
+    };
+    let mut running = RUNNING.lock().unwrap();
+    while *running >= max {
+        exec::check_interrupted()?;
+        running = FINISHED.wait_timeout(running, WAIT_INTERVAL).unwrap().0;
+    }
+    *running += 1;
+    Ok(Permit { counted: true, child: None })
+}
+
+impl Permit {
+    /// Charge what child `pid` and its descendants (FFmpeg under `bwrap`,
+    /// with `--sandbox`) read since the last call to the shared allowance,
+    /// stopping them while it's overdrawn and letting them go on once it
+    /// isn't. Called as the child is polled.
+    pub fn poll(&mut self, pid: u32) {
+        let Some(rate) = LIMITS.get().and_then(|l| l.bandwidth) else {
+            return;
+        };
+        let tree = process_tree(pid);
+        let reads: Vec<u64> = tree.iter().filter_map(|&p| bytes_read(p)).collect();
+        if reads.is_empty() {
+            return;
+        }
+        let read = reads.iter().sum::<u64>();
+        let (last, paused) = match self.child {
+            Some((watched, last, paused)) if watched == pid => (last, paused),
+            _ => (0, false),
+        };
+        let mut allowance = allowance(rate);
+        let bytes = &mut allowance.as_mut().unwrap().0;
+        *bytes -= read.saturating_sub(last) as f64;
+        let pause = *bytes < 0.0;
+        drop(allowance);
+        if pause != paused {
+            for &p in &tree {
+                signal(p, pause);
+            }
+        }
+        self.child = Some((pid, read, pause));
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        // A stopped child can only have been killed, and then reaped, so
+        // there's nothing left to let go on.
+        if self.counted {
+            *RUNNING.lock().unwrap() -= 1;
+            FINISHED.notify_one();
+        }
+    }
+}
+
+/// Process `pid` and its descendants, by the parents in `/proc/<pid>/stat`.
+/// (`bwrap --new-session` leaves FFmpeg outside the child's process group.)
+#[cfg(target_os = "linux")]
+fn process_tree(pid: u32) -> Vec<u32> {
+    let parents: Vec<(u32, u32)> = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let child: u32 = entry.ok()?.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", child)).ok()?;
+            // The command name, in parentheses, may hold spaces; the parent
+            // is the second field after it.
+            let parent = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()?;
+            Some((child, parent))
+        })
+        .collect();
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(parents.iter().filter(|&&(_, p)| p == parent).map(|&(child, _)| child));
+        i += 1;
+    }
+    tree
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_tree(pid: u32) -> Vec<u32> {
+    vec![pid]
+}
+
+/// Bytes process `pid` has read so far, from `/proc/<pid>/io`.
+#[cfg(target_os = "linux")]
+fn bytes_read(pid: u32) -> Option<u64> {
+    let io = std::fs::read_to_string(format!("/proc/{}/io", pid)).ok()?;
+    io.lines().find_map(|line| line.strip_prefix("rchar:")?.trim().parse().ok())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bytes_read(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Stop process `pid`, or let it go on.
+#[cfg(target_os = "linux")]
+fn signal(pid: u32, stop: bool) {
+    let signal = if stop { libc::SIGSTOP } else { libc::SIGCONT };
+    // SAFETY: kill() only sends a signal; a pid that already exited just fails.
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn signal(_pid: u32, _stop: bool) {}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::exec;
+use crate::mosaic::Job;
+use crate::sandbox;
+
+/// Name of each sprite, numbered from 1 as a DASH `$Number$` template is.
+pub const TEMPLATE: &str = "tile-$Number$.jpg";
+
+/// Name of the description of the sprites.
+const METADATA: &str = "thumbnails.json";
+
+/// The DASH image AdaptationSet for the sprites, to paste into an MPD's Period.
+const ADAPTATION_SET: &str = "adaptation-set.xml";
+
+/// The HLS image media playlist of the sprites.
+const PLAYLIST: &str = "images.m3u8";
+
+/// The `EXT-X-IMAGE-STREAM-INF` line pointing a multivariant playlist at it.
+const STREAM_INF: &str = "image-stream-inf.txt";
+
+/// How the thumbnails of a packager's thumbnail track are taken and tiled
+/// (`--thumbnail-interval`, `--thumbnail-width`, `--thumbnail-grid`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrackLayout {
+    /// Seconds between thumbnails.
+    pub interval: f64,
+    /// Width of every thumbnail; the height follows the video's shape.
+    pub width: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+/// The sprites written for a video, as packagers need to reference them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Track {
+    /// File name of each sprite, `$Number$` standing for its number.
+    pub template: String,
+    /// Number of the first sprite.
+    pub start_number: u32,
+    pub images: u32,
+    pub thumbnails: u32,
+    /// Seconds between thumbnails, and covered by each sprite.
+    pub interval: f64,
+    pub image_duration: f64,
+    pub duration: f64,
+    /// Size of each thumbnail, and how many across and down a sprite holds.
+    pub width: u32,
+    pub height: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+/// `<sheet stem>_thumbnails/`, next to the sheet.
+pub fn dir(sheet: &Path) -> PathBuf {
+    let stem = sheet.file_stem().unwrap_or_default().to_string_lossy();
+    sheet.with_file_name(format!("{}_thumbnails", stem))
+}
+
+/// `thumbnails.json` in the sheet's thumbnail directory, written last.
+pub fn metadata_path(sheet: &Path) -> PathBuf {
+    dir(sheet).join(METADATA)
+}
+
+/// The sprites, description and manifests in the sheet's thumbnail directory.
+pub fn files(sheet: &Path) -> Vec<PathBuf> {
+    let dir = dir(sheet);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| is_sprite(p) || p.file_name().is_some_and(|n| [METADATA, ADAPTATION_SET, PLAYLIST, STREAM_INF].iter().any(|m| n == *m)))
+        .collect();
+    files.sort();
+    files
+}
+
+fn is_sprite(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (prefix, suffix) = TEMPLATE.split_once("$Number$").unwrap_or_default();
+    name.strip_prefix(prefix)
+        .and_then(|n| n.strip_suffix(suffix))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Write the job's video as a thumbnail track a packager can serve: a
+/// thumbnail every `layout.interval` seconds from the start, all one size,
+/// tiled into numbered sprites of `layout.cols x layout.rows`, with a
+/// `thumbnails.json` describing them and the manifest entries streams
+/// reference them by. It's a pass of its own over the whole video, not made
+/// from the sheet's frames.
+pub fn write(job: &Job, layout: &TrackLayout, sheet: &Path) -> Result<Track> {
+    let (width, height) = job.info.display_size().unwrap_or((16, 9));
+    // Even, as 4:2:0 JPEG needs.
+    let thumb_height = ((layout.width as f64 * height as f64 / width as f64 / 2.0).round() as u32).max(1) * 2;
+    let thumbnails = ((job.duration / layout.interval).ceil() as u32).max(1);
+    let per_image = layout.cols * layout.rows;
+
+    let dir = dir(sheet);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // Sprites numbered past this run's last would be taken for its own.
+    for stale in files(sheet) {
+        fs::remove_file(&stale).with_context(|| format!("Failed to remove {}", stale.display()))?;
+    }
+    let src = job.source();
+    let pattern = dir.join(TEMPLATE.replace("$Number$", "%d"));
+    // Every thumbnail the same size, letterboxed if the frame changes shape.
+    let filter = format!(
+        "fps=1/{interval},scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,tile={c}x{r}",
+        interval = layout.interval, w = layout.width, h = thumb_height, c = layout.cols, r = layout.rows
+    );
+    exec::run(sandbox::command("ffmpeg", src.sandbox)
+        .args(["-i", src.path, "-map", &src.map()])
+        .args(["-t", &format!("{:.3}", job.duration)])
+        .args(["-vf", &filter, "-q:v", "3", "-start_number", "1", "-y"])
+        .arg(&pattern))
+        .with_context(|| format!("Failed to write the thumbnail track in {}", dir.display()))?;
+
+    let track = Track {
+        template: TEMPLATE.to_string(),
+        start_number: 1,
+        images: thumbnails.div_ceil(per_image),
+        thumbnails,
+        interval: layout.interval,
+        image_duration: layout.interval * per_image as f64,
+        duration: job.duration,
+        width: layout.width,
+        height: thumb_height,
+        cols: layout.cols,
+        rows: layout.rows,
+    };
+    write_manifests(&dir, &track)?;
+    let path = metadata_path(sheet);
+    let json = serde_json::to_string_pretty(&track)? + "\n";
+    fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(track)
+}
+
+/// Write the DASH AdaptationSet (DASH-IF thumbnail tiles), the HLS image
+/// media playlist and its `EXT-X-IMAGE-STREAM-INF` line for `track`'s
+/// sprites in `dir`, with the sprites' peak bitrate as their bandwidth.
+fn write_manifests(dir: &Path, track: &Track) -> Result<()> {
+    let sprite = |n: u32| track.template.replace("$Number$", &n.to_string());
+    let numbers: Vec<u32> = (track.start_number..track.start_number + track.images).collect();
+    // The last sprite covers only what's left of the video.
+    let durations: Vec<f64> = (0..track.images)
+        .map(|i| (track.duration - i as f64 * track.image_duration).clamp(0.001, track.image_duration))
+        .collect();
+    let mut bandwidth = 0;
+    for (&n, &duration) in numbers.iter().zip(&durations) {
+        let path = dir.join(sprite(n));
+        let bytes = fs::metadata(&path).with_context(|| format!("Missing sprite {}", path.display()))?.len();
+        bandwidth = bandwidth.max((bytes as f64 * 8.0 / duration).ceil() as u64);
+    }
+    let (width, height) = (track.width * track.cols, track.height * track.rows);
+    let layout = format!("{}x{}", track.cols, track.rows);
+
+    let adaptation_set = format!(
+        concat!(
+            "<AdaptationSet id=\"thumbnails\" contentType=\"image\" mimeType=\"image/jpeg\">\n",
+            "  <SegmentTemplate media=\"{media}\" timescale=\"1000\" duration=\"{duration}\" startNumber=\"{start}\"/>\n",
+            "  <Representation id=\"thumbnails_{w}x{h}\" bandwidth=\"{bandwidth}\" width=\"{width}\" height=\"{height}\">\n",
+            "    <EssentialProperty schemeIdUri=\"http://dashif.org/thumbnail_tile\" value=\"{layout}\"/>\n",
+            "  </Representation>\n",
+            "</AdaptationSet>\n",
+        ),
+        media = track.template, duration = (track.image_duration * 1000.0).round() as u64, start = track.start_number,
+        w = track.width, h = track.height, bandwidth = bandwidth, width = width, height = height, layout = layout,
+    );
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-IMAGES-ONLY\n",
+        durations.iter().fold(0.0, |a: f64, &b| a.max(b)).ceil() as u64, track.start_number
+    );
+    for (&n, &duration) in numbers.iter().zip(&durations) {
+        playlist += &format!(
+            "#EXTINF:{:.3},\n#EXT-X-TILES:RESOLUTION={}x{},LAYOUT={},DURATION={:.3}\n{}\n",
+            duration, track.width, track.height, layout, track.interval, sprite(n)
+        );
+    }
+    playlist += "#EXT-X-ENDLIST\n";
+    let stream_inf = format!(
+        "#EXT-X-IMAGE-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{},CODECS=\"jpeg\",URI=\"{}\"\n",
+        bandwidth, width, height, PLAYLIST
+    );
+
+    for (name, contents) in [(ADAPTATION_SET, adaptation_set), (PLAYLIST, playlist), (STREAM_INF, stream_inf)] {
+        let path = dir.join(name);
+        fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use crate::probe::MediaInfo;
+
+/// SMPTE timecode of a video: its start timecode and frame rate.
+pub struct Timecode {
+    /// Frames per second counted by the timecode (30 for 29.97 material).
+    nominal: u64,
+    /// Actual frames per second.
+    rate: f64,
+    /// Whether frame numbers are dropped to keep NTSC timecode on the clock.
+    drop_frame: bool,
+    /// Frame count of the first frame's timecode.
+    start: u64,
+}
+
+impl Timecode {
+    /// The timecode of `info`'s video, starting at its `timecode` tag (on the
+    /// video stream, the container or a tmcd track) or at 00:00:00:00.
+    ///
+    /// `None` when the frame rate isn't known.
+    pub fn for_video(info: &MediaInfo) -> Option<Timecode> {
+        let rate = info.video_stream()?.frame_rate()?;
+        let nominal = (rate.round() as u64).max(1);
+        let ntsc = (rate - nominal as f64).abs() > 0.001 && nominal.is_multiple_of(30);
+
+        let tag = info.video_stream().and_then(|s| s.tags.get("timecode"))
+            .or_else(|| info.format.tags.get("timecode"))
+            .or_else(|| info.streams.iter().find_map(|s| s.tags.get("timecode")));
+        let mut timecode = Timecode { nominal, rate, drop_frame: ntsc, start: 0 };
+        if let Some(tag) = tag {
+            // The separator before the frames says which it is: ';' (or '.') for drop-frame.
+            timecode.drop_frame = ntsc && tag.contains([';', '.']);
+            timecode.start = timecode.parse(tag).unwrap_or(0);
+        }
+        Some(timecode)
+    }
+
+    /// Seconds after the video starts to seek to for the frame at timecode
+    /// `tag`: a quarter frame before it, so the seek lands on the frame
+    /// whichever way the time is rounded. Negative before the start timecode.
+    pub fn seek_time(&self, tag: &str) -> Option<f64> {
+        Some((self.parse(tag)? as f64 - self.start as f64 - 0.25) / self.rate)
+    }
+
+    /// Frame count of an `HH:MM:SS:FF` (or `HH:MM:SS;FF`) timecode.
+    fn parse(&self, tag: &str) -> Option<u64> {
+        let fields: Vec<u64> = tag.trim().split([':', ';', '.'])
+            .map(|f| f.parse().ok())
+            .collect::<Option<_>>()?;
+        let &[hours, minutes, seconds, frames] = fields.as_slice() else {
+            return None;
+        };
+        let total_minutes = hours * 60 + minutes;
+        let count = (total_minutes * 60 + seconds) * self.nominal + frames;
+        Some(count.saturating_sub(self.dropped_per_minute() * (total_minutes - total_minutes / 10)))
+    }
+
+    /// Frame numbers skipped at the start of each minute but every tenth.
+    fn dropped_per_minute(&self) -> u64 {
+        if self.drop_frame { self.nominal / 15 } else { 0 }
+    }
+
+    /// The timecode shown on the frame `secs` after the video starts.
+    pub fn at(&self, secs: f64) -> String {
+        let mut frame = self.start + (secs.max(0.0) * self.rate).round() as u64;
+        let drop = self.dropped_per_minute();
+        if drop > 0 {
+            let per_ten_minutes = self.nominal * 600 - drop * 9;
+            let per_minute = self.nominal * 60 - drop;
+            let (tens, rest) = (frame / per_ten_minutes, frame % per_ten_minutes);
+            frame += drop * 9 * tens;
+            if rest > drop {
+                frame += drop * ((rest - drop) / per_minute);
+            }
+        }
+
+        let fps = self.nominal;
+        let separator = if self.drop_frame { ';' } else { ':' };
+        format!(
+            "{:02}:{:02}:{:02}{}{:02}",
+            frame / (fps * 3600) % 24,
+            frame / (fps * 60) % 60,
+            frame / fps % 60,
+            separator,
+            frame % fps,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drop_frame(nominal: u64) -> Timecode {
+        Timecode { nominal, rate: nominal as f64 * 1000.0 / 1001.0, drop_frame: true, start: 0 }
+    }
+
+    /// Where frame `frame` is, in seconds after the start.
+    fn secs(timecode: &Timecode, frame: u64) -> f64 {
+        frame as f64 / timecode.rate
+    }
+
+    #[test]
+    fn drop_frame_known_answers() {
+        let tc = drop_frame(30);
+        for (tag, frame) in [
+            ("00:00:59;29", 1799),
+            // 00:01:00;00 and ;01 are dropped.
+            ("00:01:00;02", 1800),
+            ("00:09:59;29", 17981),
+            // Every tenth minute keeps its first two.
+            ("00:10:00;00", 17982),
+            ("00:10:00;01", 17983),
+            ("00:11:00;02", 19782),
+            ("01:00:00;00", 107892),
+        ] {
+            assert_eq!(tc.parse(tag), Some(frame), "{}", tag);
+            assert_eq!(tc.at(secs(&tc, frame)), tag, "{}", frame);
+        }
+
+        let tc = drop_frame(60);
+        assert_eq!(tc.parse("00:01:00;04"), Some(3600));
+        assert_eq!(tc.at(secs(&tc, 3600)), "00:01:00;04");
+        assert_eq!(tc.parse("00:10:00;00"), Some(35964));
+        assert_eq!(tc.at(secs(&tc, 35964)), "00:10:00;00");
+    }
+
+    #[test]
+    fn drop_frame_round_trips_over_a_day() {
+        for nominal in [30, 60] {
+            let tc = drop_frame(nominal);
+            let day = tc.parse("23:59:59;00").unwrap() + nominal;
+            // Every frame within a second of each minute's first, where
+            // frames are dropped or not, throughout the day.
+            for minute in 0..24 * 60 {
+                let first = format!("{:02}:{:02}:00;{:02}", minute / 60, minute % 60, tc.dropped_per_minute());
+                let around = tc.parse(&first).unwrap();
+                for frame in around.saturating_sub(nominal)..(around + nominal).min(day) {
+                    assert_eq!(tc.parse(&tc.at(secs(&tc, frame))), Some(frame), "{} fps, frame {}", nominal, frame);
+                }
+            }
+            // The day ends where the next one starts.
+            assert_eq!(tc.at(secs(&tc, day)), "00:00:00;00");
+        }
+    }
+}
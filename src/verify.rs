@@ -0,0 +1,96 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use crate::exec;
+use crate::index;
+use crate::mosaic;
+use crate::sandbox::Sandbox;
+use crate::Options;
+
+/// Why a video's sheet needs making again.
+enum Problem {
+    Missing,
+    /// It doesn't decode, or is too small to be a sheet.
+    Broken(String),
+    /// The video was modified after the sheet was.
+    SourceChanged,
+    /// The index says it was made with settings other than this run's.
+    OtherOptions,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Missing => write!(f, "missing"),
+            Problem::Broken(why) => write!(f, "broken: {}", why),
+            Problem::SourceChanged => write!(f, "stale: the video changed since"),
+            Problem::OtherOptions => write!(f, "stale: made with other settings"),
+        }
+    }
+}
+
+/// Check the sheet of every video in `dir`: that it's there and decodes,
+/// that the video hasn't changed since, and (with an `index_path`) that it
+/// was made with this run's settings. Prints the sheets that need making
+/// again, and writes their videos to `list` (one per line, for
+/// `--files-from`). Returns whether every sheet was fine.
+pub fn run(dir: &Path, index_path: Option<&Path>, list: Option<&Path>, opts: &Options) -> Result<bool> {
+    let index = match index_path {
+        Some(path) => index::read(path)?,
+        None => Vec::new(),
+    };
+    let fingerprint = opts.fingerprint();
+    // Only decoding here, so the sandbox needs no writable directories.
+    let sandbox = opts.sandbox.then(|| Sandbox::new(Vec::new()));
+
+    let videos = crate::library_videos(dir, opts)?;
+    let mut stale = Vec::new();
+    for video in &videos {
+        exec::check_interrupted()?;
+        let entry = index.iter().find(|e| e.path == *video);
+        let sheet = match entry.and_then(|e| e.sheet.clone()) {
+            Some(sheet) => sheet,
+            None => crate::templated_output_path(video, Some(dir), opts)?,
+        };
+        if let Some(problem) = check(video, &sheet, entry, &fingerprint, sandbox.as_ref(), opts)? {
+            println!("{} ({}): {}", video.display(), sheet.display(), problem);
+            stale.push(video.clone());
+        }
+    }
+
+    log::info!("Verified {} sheets: {} fine, {} to make again.", videos.len(), videos.len() - stale.len(), stale.len());
+    if let Some(list) = list {
+        let lines: String = stale.iter().map(|video| format!("{}\n", video.display())).collect();
+        fs::write(list, lines).with_context(|| format!("Failed to write {}", list.display()))?;
+    }
+    Ok(stale.is_empty())
+}
+
+/// What's wrong with `video`'s `sheet`, if anything.
+fn check(
+    video: &Path,
+    sheet: &Path,
+    entry: Option<&index::Entry>,
+    fingerprint: &str,
+    sandbox: Option<&Sandbox>,
+    opts: &Options,
+) -> Result<Option<Problem>> {
+    if !sheet.is_file() {
+        return Ok(Some(Problem::Missing));
+    }
+    let sandbox = sandbox.cloned().map(|sandbox| sandbox.reading(sheet));
+    match mosaic::validate_output(sheet, sandbox.as_ref()) {
+        Ok(()) => {}
+        Err(e) if exec::is_interrupted(&e) || exec::is_tool_missing(&e) => return Err(e),
+        Err(e) => return Ok(Some(Problem::Broken(format!("{:#}", e)))),
+    }
+    let modified = |path: &Path| opts.retry.io(format_args!("stat {}", path.display()), || fs::metadata(path)?.modified());
+    if modified(video)? > modified(sheet)? {
+        return Ok(Some(Problem::SourceChanged));
+    }
+    if entry.is_some_and(|e| e.options != fingerprint) {
+        return Ok(Some(Problem::OtherOptions));
+    }
+    Ok(None)
+}